@@ -0,0 +1,161 @@
+// Compares the counting sort cell lists of `LinkedListGrid` with the former
+// grid, which stored a hash map of particle indices per cell, for large
+// numbers of particles.
+//
+// cargo bench --bench grid
+
+#[macro_use]
+extern crate criterion;
+extern crate dem2d;
+
+use criterion::{BenchmarkId, Criterion};
+use dem2d::contact_search::{get_neighbours_ll, LinkedListGrid};
+use dem2d::physics::dem::DemDiscrete;
+
+/// Grid with a hash map from the entity id to the particle indices in
+/// every cell, as `LinkedListGrid` was implemented before.
+mod hashmap_grid {
+    use dem2d::physics::dem::DemDiscrete;
+    use std::collections::HashMap;
+
+    #[derive(Clone)]
+    pub struct CellGrid {
+        pub indices: HashMap<usize, Vec<usize>>,
+    }
+
+    pub struct Grid {
+        pub no_y_cells: usize,
+        pub x_min: f32,
+        pub y_min: f32,
+        pub size: f32,
+        pub cells: Vec<CellGrid>,
+    }
+
+    pub fn new(entity: &DemDiscrete, scale: f32) -> Grid {
+        let (mut x_min, mut x_max) = (entity.x[0], entity.x[0]);
+        let (mut y_min, mut y_max) = (entity.y[0], entity.y[0]);
+        let mut size: f32 = 0.;
+        for i in 0..entity.x.len() {
+            x_min = x_min.min(entity.x[i]);
+            x_max = x_max.max(entity.x[i]);
+            y_min = y_min.min(entity.y[i]);
+            y_max = y_max.max(entity.y[i]);
+            size = size.max(entity.h[i]);
+        }
+        size *= scale;
+        x_min -= size / 10.;
+        x_max += size / 10.;
+        y_min -= size / 10.;
+        y_max += size / 10.;
+        let no_x_cells = ((x_max - x_min) / size) as usize + 2;
+        let no_y_cells = ((y_max - y_min) / size) as usize + 2;
+
+        let mut cell = CellGrid {
+            indices: HashMap::new(),
+        };
+        cell.indices.insert(entity.id, vec![]);
+        let mut cells = vec![cell; no_x_cells * no_y_cells];
+        for i in 0..entity.x.len() {
+            let x_index = ((entity.x[i] - x_min) / size) as usize;
+            let y_index = ((entity.y[i] - y_min) / size) as usize;
+            let index = x_index * no_y_cells + y_index;
+            cells[index].indices.get_mut(&entity.id).unwrap().push(i);
+        }
+        Grid {
+            no_y_cells,
+            x_min,
+            y_min,
+            size,
+            cells,
+        }
+    }
+
+    pub fn neighbours<'a>(pos: [f32; 2], grid: &'a Grid, src_id: &usize) -> Vec<&'a Vec<usize>> {
+        let x_index = ((pos[0] - grid.x_min) / grid.size) as usize;
+        let y_index = ((pos[1] - grid.y_min) / grid.size) as usize;
+        let index = x_index * grid.no_y_cells + y_index;
+        let mut nbrs = vec![];
+        for neighbour in &[
+            Some(index),
+            index.checked_sub(1),
+            index.checked_add(1),
+            index.checked_sub(grid.no_y_cells),
+            index.checked_sub(grid.no_y_cells - 1),
+            index.checked_sub(grid.no_y_cells + 1),
+            index.checked_add(grid.no_y_cells),
+            index.checked_add(grid.no_y_cells - 1),
+            index.checked_add(grid.no_y_cells + 1),
+        ] {
+            if let Some(cell) = neighbour.and_then(|index| grid.cells.get(index)) {
+                nbrs.push(&cell.indices[src_id])
+            }
+        }
+        nbrs
+    }
+}
+
+/// Square lattice of `n` particles of radius 0.5 with a spacing of 1.5.
+fn block(n: usize) -> DemDiscrete {
+    let side = (n as f32).sqrt() as usize;
+    let mut grains = DemDiscrete::new(side * side, 0, "grains".to_string());
+    for i in 0..side * side {
+        grains.x[i] = 1.5 * (i % side) as f32;
+        grains.y[i] = 1.5 * (i / side) as f32;
+        grains.h[i] = 0.5;
+        grains.rad[i] = 0.5;
+    }
+    grains
+}
+
+fn grid_build(c: &mut Criterion) {
+    let mut group = c.benchmark_group("grid_build");
+    group.sample_size(10);
+    for &n in &[100_000, 250_000] {
+        let mut grains = block(n);
+        group.bench_with_input(BenchmarkId::new("hashmap_cells", n), &n, |b, _| {
+            b.iter(|| hashmap_grid::new(&grains, 2.))
+        });
+        group.bench_with_input(BenchmarkId::new("cell_list", n), &n, |b, _| {
+            b.iter(|| LinkedListGrid::new(&mut vec![&mut grains], 2.))
+        });
+    }
+    group.finish();
+}
+
+fn neighbour_search(c: &mut Criterion) {
+    let mut group = c.benchmark_group("neighbour_search");
+    group.sample_size(10);
+    for &n in &[100_000, 250_000] {
+        let mut grains = block(n);
+        let old = hashmap_grid::new(&grains, 2.);
+        let new = LinkedListGrid::new(&mut vec![&mut grains], 2.);
+
+        // count the candidate neighbours of all the particles
+        group.bench_with_input(BenchmarkId::new("hashmap_cells", n), &n, |b, _| {
+            b.iter(|| {
+                let mut count = 0;
+                for i in 0..grains.x.len() {
+                    for cell in hashmap_grid::neighbours([grains.x[i], grains.y[i]], &old, &0) {
+                        count += cell.len();
+                    }
+                }
+                count
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("cell_list", n), &n, |b, _| {
+            b.iter(|| {
+                let mut count = 0;
+                for i in 0..grains.x.len() {
+                    for cell in get_neighbours_ll([grains.x[i], grains.y[i], 0.], &new, &0) {
+                        count += cell.len();
+                    }
+                }
+                count
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, grid_build, neighbour_search);
+criterion_main!(benches);
@@ -1,19 +1,26 @@
+extern crate cgmath as cm;
 #[macro_use]
 extern crate dem2d;
 
-use dem2d::contact_search::LinkedListGrid;
-use dem2d::geometry::{grid_2d, hopper_2d};
-use dem2d::integrate::{integrate_initialize, integrate_stage1, integrate_stage2};
+use dem2d::geometry::{random_packing_2d, SizeDistribution};
+use dem2d::integrate::RK2;
+use dem2d::outlet::{Outlet, OutletRegion};
 use dem2d::physics::dem::DemDiscrete;
-use dem2d::physics::dem::equations::{body_force_dem, linear_viscoelastic_model_dem_other,
-                                     linear_viscoelastic_model_dem_self, make_forces_zero};
-use dem2d::save_data::{create_output_directory, dump_output};
+use dem2d::physics::dem::equations::{set_disk_inertia_dem, ContactLaw};
+use dem2d::physics::material::{Material, MaterialDatabase};
+use dem2d::physics::timestep::TimeStepCheck;
+use dem2d::physics::wall::{Wall, WallShape};
+use dem2d::save_data::create_output_directory;
+use dem2d::solver::{Equation, Solver};
+
+use cm::Vector3;
 
 pub struct SimulationData {
-    pub grains_spacing: f32,
+    pub grains_min_radius: f32,
+    pub grains_max_radius: f32,
+    pub grains_number: usize,
     pub grains_length: f32,
     pub grains_height: f32,
-    pub hopper_spacing: f32,
     pub hopper_br: f32,
     pub hopper_tr: f32,
     pub hopper_height: f32,
@@ -22,10 +29,11 @@ pub struct SimulationData {
 impl SimulationData {
     fn new() -> Self {
         SimulationData {
-            grains_spacing: 0.3,
+            grains_min_radius: 0.1,
+            grains_max_radius: 0.15,
+            grains_number: 150,
             grains_length: 4.,
             grains_height: 5.,
-            hopper_spacing: 0.3,
             hopper_tr: 5.,
             hopper_br: 1.,
             hopper_height: 7.,
@@ -33,50 +41,57 @@ impl SimulationData {
     }
 }
 
-fn setup_particle_properties(part1: &mut DemDiscrete, x: Vec<f32>, y: Vec<f32>, h: f32, mass: f32) {
-    let m_inv = 1. / mass;
+fn setup_particle_properties(
+    part1: &mut DemDiscrete,
+    x: Vec<f32>,
+    y: Vec<f32>,
+    rad: Vec<f32>,
+    material: &Material,
+) {
     for i in 0..part1.len {
         part1.x[i] = x[i];
         part1.y[i] = y[i];
-        part1.h[i] = h;
-        part1.rad[i] = h;
-        part1.m[i] = mass;
-        part1.m_inv[i] = m_inv;
+        part1.h[i] = rad[i];
+        part1.rad[i] = rad[i];
+        part1.m[i] = material.disk_mass(rad[i]);
+        part1.m_inv[i] = 1. / part1.m[i];
     }
 }
 
 fn main() {
     let sim_data = SimulationData::new();
 
-    let (xh, yh) = hopper_2d(
-        sim_data.hopper_br,
-        sim_data.hopper_tr,
-        sim_data.hopper_height,
-        sim_data.hopper_spacing,
-    );
-
-    let (xg, yg) = grid_2d(
+    // both the grains and the hopper are made of the same material
+    let mut materials = MaterialDatabase::new();
+    let mut glass = Material::new("glass".to_string(), 1000., 1e7, 0.3);
+    glass.en = 0.9;
+
+    // grains of radii uniformly distributed between the limits
+    let distribution = SizeDistribution::Uniform {
+        min: sim_data.grains_min_radius,
+        max: sim_data.grains_max_radius,
+    };
+    let (xg, yg, radg) = random_packing_2d(
         sim_data.grains_length,
         sim_data.grains_height,
-        sim_data.grains_spacing,
+        &distribution,
+        sim_data.grains_number,
+        0,
     );
     let mut grains = DemDiscrete::new(xg.len(), 0, "grains".to_string());
-    let mut hopper = DemDiscrete::new(xh.len(), 1, "hopper".to_string());
-
-    setup_particle_properties(
-        &mut grains,
-        xg,
-        yg,
-        sim_data.grains_spacing / 2.,
-        1000. * sim_data.grains_spacing.powf(2.),
-    );
-    setup_particle_properties(
-        &mut hopper,
-        xh,
-        yh,
-        sim_data.hopper_spacing / 2.,
-        1000. * sim_data.hopper_spacing.powf(2.),
-    );
+    setup_particle_properties(&mut grains, xg, yg, radg, &glass);
+    set_disk_inertia_dem(&mut grains);
+    grains.material_id = materials.add(glass);
+
+    // the sides of the hopper are analytic walls
+    let side = |x: f32| WallShape::Segment {
+        start: Vector3::new(x * sim_data.hopper_br, 0., 0.),
+        end: Vector3::new(x * sim_data.hopper_tr, sim_data.hopper_height, 0.),
+    };
+    let mut left = Wall::new(side(-1.), 1, "left_side".to_string());
+    let mut right = Wall::new(side(1.), 2, "right_side".to_string());
+    left.material_id = grains.material_id;
+    right.material_id = grains.material_id;
 
     // move the grains left
     for i in 0..grains.len{
@@ -84,69 +99,51 @@ fn main() {
         grains.y[i] += 3.;
     }
 
-    let dt = 1e-4;
-    let dim = 2;
-    let tf = 2.;
-    let mut time_step_number = 0;
-    let mut t = 0.;
-    let scale = 2.;
-    let stage1 = 1;
-    let stage2 = 2;
-
-    let dir_name = create_directory_return_name![];
-    let pfreq = 100;
-
-    while t < tf {
-        let grid = LinkedListGrid::new(&mut vec![&mut grains, &mut hopper], scale);
-        // initialize the components
-        integrate_initialize(&mut vec![&mut grains], dt);
-
-        // Compute the forces
-        make_forces_zero(&mut grains);
-        body_force_dem(&mut grains, 0., -9.81);
-        linear_viscoelastic_model_dem_other(
-            &mut grains,
-            &mut hopper,
-            1e7,
-            0.0,
-            dt,
-            stage1,
-            &grid,
-            dim,
-        );
-        linear_viscoelastic_model_dem_self(&mut grains, 1e7, 0.0, dt, stage1, &grid, dim);
-
-        // Execulte stage 1
-        integrate_stage1(&mut vec![&mut grains], dt);
-
-        // Compute the forces
-        make_forces_zero(&mut grains);
-        body_force_dem(&mut grains, 0., -9.81);
-        linear_viscoelastic_model_dem_other(
-            &mut grains,
-            &mut hopper,
-            1e7,
-            0.0,
-            dt,
-            stage2,
-            &grid,
-            dim,
-        );
-        linear_viscoelastic_model_dem_self(&mut grains, 1e7, 0.0, dt, stage2, &grid, dim);
-
-        // Execulte stage 2
-        integrate_stage2(&mut vec![&mut grains], dt);
-
-        // increase the time
-        t = t + dt;
-        if time_step_number % pfreq == 0 {
-            println!("{:?}", time_step_number);
-            dump_output(
-                &mut vec![&mut grains, &mut hopper],
-                time_step_number,
-                &dir_name,
-            );
-        }
-        time_step_number += 1;
+    let mut solver = Solver::new(RK2, materials, 1e-4, 2.);
+    solver.output_folder = Some(create_directory_return_name![]);
+    solver.set_output_frequency(100);
+    // warn if the time step exceeds the stable time step
+    solver.time_step_check = TimeStepCheck::Warn;
+    // rebuild the neighbour grid only after the grains move by 2 cm
+    solver.skin = 0.4 * sim_data.grains_min_radius;
+
+    let grains = solver.add_entity(grains);
+    let walls = [solver.add_wall(left), solver.add_wall(right)];
+    let law = ContactLaw::LinearViscoelastic;
+    solver.add_equation(Equation::BodyForce {
+        entity: grains,
+        gx: 0.,
+        gy: -9.81,
+    });
+    for &wall in &walls {
+        solver.add_equation(Equation::WallContact {
+            entity: grains,
+            wall,
+            law,
+        });
     }
+    solver.add_equation(Equation::SelfContact {
+        entity: grains,
+        law,
+    });
+    // the grains discharged through the orifice are removed once they fall
+    // below the hopper
+    let outlet = solver.add_outlet(Outlet::new(
+        grains,
+        OutletRegion::HalfPlane {
+            point: Vector3::new(0., -1., 0.),
+            normal: Vector3::new(0., 1., 0.),
+        },
+    ));
+    solver.add_on_output(|_, info| println!("{:?}", info.time_step_number));
+
+    solver.run().unwrap();
+
+    let outlet = &solver.outlets[outlet];
+    println!(
+        "{} grains discharged, {} kg at {} kg/s",
+        outlet.discharged,
+        outlet.discharged_mass,
+        outlet.mass_flow_rate(0., solver.t)
+    );
 }
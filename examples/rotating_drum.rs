@@ -0,0 +1,34 @@
+#[macro_use]
+extern crate dem2d;
+
+use dem2d::drum::RotatingDrum;
+use dem2d::integrate::RK2;
+use dem2d::physics::material::Material;
+use dem2d::save_data::create_output_directory;
+
+fn main() {
+    let mut glass = Material::new("glass".to_string(), 2500., 1e7, 0.3);
+    glass.en = 0.5;
+    glass.mu = 0.5;
+    glass.mu_r = 0.1;
+
+    // drum of radius 10 cm rotating counter-clockwise at 2 rad/s, 40 %
+    // filled with grains of radius 2 mm with rolling resistance
+    let drum = RotatingDrum::new(0.1, 2., 2e-3, glass);
+    let (mut solver, grains) = drum.solver(RK2, 5.);
+    solver.output_folder = Some(create_directory_return_name![]);
+    solver.set_output_frequency(1000);
+    solver.skin = 0.2 * drum.grains_radius;
+
+    // dynamic angle of repose from the free surface in the middle half of
+    // the drum
+    solver.add_on_output(move |entities, info| {
+        let grains = entities[grains].as_discrete().unwrap();
+        match drum.angle_of_repose(grains) {
+            Some(angle) => println!("t = {:.3}, angle of repose = {:.1}", info.t, angle.to_degrees()),
+            None => println!("t = {:.3}, no free surface", info.t),
+        }
+    });
+
+    solver.run().unwrap();
+}
@@ -39,23 +39,188 @@ macro_rules! impl_nnps{
     )*)
 }
 
+/// Particles of a single entity sorted by the cell they lie in. The indices
+/// of the particles in cell `c` are `indices[start[c]..start[c + 1]]`.
 #[derive(Debug, Clone)]
-pub struct CellGrid {
-    pub indices: HashMap<usize, Vec<usize>>,
+pub struct CellList {
+    pub start: Vec<usize>,
+    pub indices: Vec<usize>,
 }
 
-impl CellGrid {
-    pub fn new(keys: &Vec<usize>) -> Self {
-        let mut cell = CellGrid {
-            indices: HashMap::new(),
-        };
-        for key in keys {
-            cell.indices.insert(*key, Vec::new());
+impl CellList {
+    /// Counting sort of the particles by their cell index. Particles without
+    /// a cell are left out.
+    fn new(cell_of: &[Option<usize>], no_cells: usize) -> Self {
+        // number of particles in every cell, shifted by one
+        let mut start = vec![0; no_cells + 1];
+        for cell in cell_of.iter().filter_map(|&cell| cell) {
+            start[cell + 1] += 1;
+        }
+        // prefix sum gives the first position of every cell
+        for cell in 0..no_cells {
+            start[cell + 1] += start[cell];
+        }
+        let mut next = start.clone();
+        let mut indices = vec![0; start[no_cells]];
+        for (i, cell) in cell_of.iter().enumerate() {
+            if let Some(cell) = *cell {
+                indices[next[cell]] = i;
+                next[cell] += 1;
+            }
+        }
+        CellList { start, indices }
+    }
+
+    /// Indices of the particles in the given cell.
+    pub fn cell(&self, index: usize) -> &[usize] {
+        &self.indices[self.start[index]..self.start[index + 1]]
+    }
+}
+
+/// Fixed rectangular region of the simulation.
+///
+/// The domain can be periodic along either axis, in which case a particle
+/// leaving through one side reenters through the opposite side, and
+/// particles near opposite sides interact through their nearest images.
+///
+/// A sheared domain has Lees-Edwards boundaries for simple shear flow along
+/// x with the velocity gradient along y. It is periodic along both axes, and
+/// the images above the domain move along x with the shear velocity
+/// `shear_rate * (y_max - y_min)` relative to the domain, the images below
+/// it with the opposite velocity.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Domain {
+    pub x_min: f32,
+    pub x_max: f32,
+    pub y_min: f32,
+    pub y_max: f32,
+    pub periodic_x: bool,
+    pub periodic_y: bool,
+    /// imposed shear rate of Lees-Edwards boundaries, zero if not sheared
+    pub shear_rate: f32,
+}
+
+impl Domain {
+    pub fn new(x_min: f32, x_max: f32, y_min: f32, y_max: f32) -> Self {
+        assert!(x_min < x_max && y_min < y_max, "Domain limits are not ordered");
+        Domain {
+            x_min,
+            x_max,
+            y_min,
+            y_max,
+            periodic_x: false,
+            periodic_y: false,
+            shear_rate: 0.,
+        }
+    }
+
+    /// Domain which is periodic along the given axes.
+    pub fn periodic(mut self, periodic_x: bool, periodic_y: bool) -> Self {
+        self.periodic_x = periodic_x;
+        self.periodic_y = periodic_y;
+        self
+    }
+
+    /// Domain with Lees-Edwards boundaries at the given shear rate.
+    pub fn sheared(mut self, shear_rate: f32) -> Self {
+        self.periodic_x = true;
+        self.periodic_y = true;
+        self.shear_rate = shear_rate;
+        self
+    }
+
+    /// Velocity along x of the images above the domain relative to it.
+    pub fn shear_velocity(&self) -> f32 {
+        self.shear_rate * (self.y_max - self.y_min)
+    }
+
+    /// Displacement along x of the images above the domain at time `t`,
+    /// wrapped around the period.
+    pub fn shear_offset(&self, t: f32) -> f32 {
+        (self.shear_velocity() * t).rem_euclid(self.x_max - self.x_min)
+    }
+
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        x >= self.x_min && x <= self.x_max && y >= self.y_min && y <= self.y_max
+    }
+
+    /// Closest point of the domain.
+    pub fn clamp(&self, x: f32, y: f32) -> (f32, f32) {
+        (
+            x.max(self.x_min).min(self.x_max),
+            y.max(self.y_min).min(self.y_max),
+        )
+    }
+
+    /// Move a point leaving through a periodic side back into the domain.
+    pub fn wrap(&self, x: f32, y: f32) -> (f32, f32) {
+        (
+            wrap_coordinate(x, self.x_min, self.x_max, self.periodic_x),
+            wrap_coordinate(y, self.y_min, self.y_max, self.periodic_y),
+        )
+    }
+
+    /// Move a particle leaving through a side back into the domain at time
+    /// `t`, returning its new position and the change of its velocity along
+    /// x. A particle leaving through a sheared side reenters at the position
+    /// of its image, which is displaced and moves with the shear velocity.
+    pub fn wrap_sheared(&self, x: f32, y: f32, t: f32) -> (f32, f32, f32) {
+        if self.shear_rate == 0. {
+            let (x, y) = self.wrap(x, y);
+            return (x, y, 0.);
         }
-        cell
+        // number of periods crossed along y
+        let k = ((y - self.y_min) / (self.y_max - self.y_min)).floor();
+        let (x, y) = self.wrap(x - k * self.shear_offset(t), y);
+        (x, y, -k * self.shear_velocity())
+    }
+
+    /// Separation `(dx, dy)` of two points, replaced along the periodic
+    /// axes by the separation from the nearest image. The displacement of
+    /// the images of a sheared domain is not included, see
+    /// `LinkedListGrid::min_image`.
+    pub fn min_image(&self, dx: f32, dy: f32) -> (f32, f32) {
+        (
+            min_image_coordinate(dx, self.x_max - self.x_min, self.periodic_x),
+            min_image_coordinate(dy, self.y_max - self.y_min, self.periodic_y),
+        )
+    }
+}
+
+fn wrap_coordinate(x: f32, min: f32, max: f32, periodic: bool) -> f32 {
+    if !periodic || (x >= min && x < max) {
+        return x;
+    }
+    let x = min + (x - min).rem_euclid(max - min);
+    // rounding can put points just below the minimum onto the maximum
+    if x >= max {
+        min
+    } else {
+        x
+    }
+}
+
+fn min_image_coordinate(dx: f32, length: f32, periodic: bool) -> f32 {
+    if periodic {
+        dx - length * (dx / length).round()
+    } else {
+        dx
     }
 }
 
+/// Uniform grid of square cells over all the entities of a simulation. The
+/// particles of every entity are binned separately into a `CellList`, so
+/// that the neighbours of a particle can be searched in any single entity.
+///
+/// The grid covers either the bounding box of the particles at the time it
+/// is built, or a fixed `Domain`. Particles outside a fixed domain are not
+/// binned, and are listed in `outside`.
+///
+/// Along the periodic axes of the domain the cells wrap around, so that the
+/// neighbours of a particle near one side include the particles near the
+/// opposite side. Across the sides of a sheared domain the cells are
+/// displaced by `shear_offset`, which has to be kept up to date with the
+/// time of the particle positions.
 #[derive(Debug)]
 pub struct LinkedListGrid {
     pub no_x_cells: usize,
@@ -64,121 +229,590 @@ pub struct LinkedListGrid {
     pub x_max: f32,
     pub y_min: f32,
     pub y_max: f32,
+    /// smallest size of the cells
     pub size: f32,
-    pub cells: Vec<CellGrid>,
+    /// size of the cells in x and y, larger than `size` along the periodic
+    /// axes so that a whole number of cells spans the period
+    pub size_x: f32,
+    pub size_y: f32,
+    pub periodic_x: bool,
+    pub periodic_y: bool,
+    /// velocity and displacement along x of the images above a sheared
+    /// domain, both zero if the domain is not sheared
+    pub shear_velocity: f32,
+    pub shear_offset: f32,
+    /// cell lists of the entities, in the order of the world
+    pub cells: Vec<CellList>,
+    /// entity id and index of the particles which are outside the grid
+    pub outside: Vec<(usize, usize)>,
+    // position of every entity id in `cells`
+    entity_index: HashMap<usize, usize>,
 }
 
 impl LinkedListGrid {
-    pub fn new<T: NNPS>(world: &mut Vec<&mut T>, scale: f32) -> LinkedListGrid {
+    /// Bin the particles of all the entities in `world`. Entities of
+    /// different types can share a grid through `Vec<&mut dyn NNPS>`.
+    pub fn new<T: NNPS + ?Sized>(world: &mut Vec<&mut T>, scale: f32) -> LinkedListGrid {
+        LinkedListGrid::new_with_skin(world, scale, 0.)
+    }
+
+    /// Bin the particles with cells enlarged by a skin distance. The grid
+    /// finds all the neighbours as long as no particle moves by more than
+    /// half the skin, see `VerletGrid`.
+    pub fn new_with_skin<T: NNPS + ?Sized>(
+        world: &mut Vec<&mut T>,
+        scale: f32,
+        skin: f32,
+    ) -> LinkedListGrid {
         // compute the limits of the grid
-        let mut x_min = world[0].get_x()[0];
-        let mut x_max = world[0].get_x()[0];
-        let mut y_min = world[0].get_y()[0];
-        let mut y_max = world[0].get_y()[0];
-        // find particle with maximum size to set
-        // the size of the grid cell
-        let mut size = 0.;
-
-        for i in 0..world.len() {
-            let ent_i = &world[i].get_parts_mut_nnps();
-            for i in 0..ent_i.x.len() {
-                if x_min > ent_i.x[i] {
-                    x_min = ent_i.x[i];
-                }
-                if x_max < ent_i.x[i] {
-                    x_max = ent_i.x[i];
-                }
-                if y_min > ent_i.y[i] {
-                    y_min = ent_i.y[i];
-                }
-                if y_max < ent_i.y[i] {
-                    y_max = ent_i.y[i];
-                }
-                if size < ent_i.h[i] {
-                    size = ent_i.h[i];
-                }
+        let mut x_min = f32::MAX;
+        let mut x_max = f32::MIN;
+        let mut y_min = f32::MAX;
+        let mut y_max = f32::MIN;
+        for entity in world.iter() {
+            let (x, y) = (entity.get_x(), entity.get_y());
+            for i in 0..x.len() {
+                x_min = x_min.min(x[i]);
+                x_max = x_max.max(x[i]);
+                y_min = y_min.min(y[i]);
+                y_max = y_max.max(y[i]);
             }
         }
-        // scale the size
-        size = size * scale;
+        if x_min > x_max {
+            // without particles, such as entities filled by an inlet, the
+            // grid has a single cell and is rebuilt once particles are added
+            return LinkedListGrid::bin(world, 2., Domain::new(-1., 1., -1., 1.));
+        }
+        let size = cell_size(world, scale, skin);
+
         // increase the size of the grid by changing
-        // the limits
-        x_min = x_min - size / 10.;
-        x_max = x_max + size / 10.;
-        y_min = y_min - size / 10.;
-        y_max = y_max + size / 10.;
+        // the limits, such that the particles stay inside it while they
+        // move within the skin
+        let pad = size / 10. + skin / 2.;
+        let domain = Domain::new(x_min - pad, x_max + pad, y_min - pad, y_max + pad);
+        LinkedListGrid::bin(world, size, domain)
+    }
 
-        // number of cells in x direction and y direction
-        let no_x_cells = ((x_max - x_min) / size) as usize + 2;
-        let no_y_cells = ((y_max - y_min) / size) as usize + 2;
+    /// Bin the particles of `world` in a grid over a fixed domain.
+    pub fn new_in_domain<T: NNPS + ?Sized>(
+        world: &mut Vec<&mut T>,
+        scale: f32,
+        skin: f32,
+        domain: Domain,
+    ) -> LinkedListGrid {
+        let size = cell_size(world, scale, skin);
+        LinkedListGrid::bin(world, size, domain)
+    }
 
-        // get all keys of the entities
-        let mut keys: Vec<usize> = vec![];
-        for i in 0..world.len() {
-            let ent_i = world[i].get_parts_mut_nnps();
-            keys.push(*ent_i.id);
-        }
+    fn bin<T: NNPS + ?Sized>(world: &mut Vec<&mut T>, size: f32, domain: Domain) -> LinkedListGrid {
+        // without particles there is nothing to search, and a single cell
+        let size = if size > 0. {
+            size
+        } else {
+            (domain.x_max - domain.x_min).max(domain.y_max - domain.y_min)
+        };
+        // number of cells in x direction and y direction
+        let (no_x_cells, size_x) = cells_along(domain.x_max - domain.x_min, size, domain.periodic_x);
+        let (no_y_cells, size_y) = cells_along(domain.y_max - domain.y_min, size, domain.periodic_y);
+        // the images across the sheared sides are searched in the cells
+        // beyond them, which must differ from the cells below them
+        assert!(
+            domain.shear_rate == 0. || no_y_cells >= 3,
+            "A sheared domain needs at least three cells along y"
+        );
 
-        // create cells of required size
-        let mut cells: Vec<CellGrid> = vec![CellGrid::new(&keys); no_x_cells * no_y_cells];
+        let mut grid = LinkedListGrid {
+            no_x_cells,
+            no_y_cells,
+            x_min: domain.x_min,
+            x_max: domain.x_max,
+            y_min: domain.y_min,
+            y_max: domain.y_max,
+            size,
+            size_x,
+            size_y,
+            periodic_x: domain.periodic_x,
+            periodic_y: domain.periodic_y,
+            shear_velocity: domain.shear_velocity(),
+            shear_offset: 0.,
+            cells: Vec::with_capacity(world.len()),
+            outside: vec![],
+            entity_index: HashMap::new(),
+        };
 
-        for j in 0..world.len() {
-            let entity = world[j].get_parts_mut_nnps();
-            let id = entity.id;
+        let mut cell_of = vec![];
+        for entity in world.iter_mut() {
+            let entity = entity.get_parts_mut_nnps();
+            cell_of.clear();
             for i in 0..entity.x.len() {
-                // find the index
-                let x_index = ((entity.x[i] - x_min) / size) as usize;
-                let y_index = ((entity.y[i] - y_min) / size) as usize;
-                // one dimentional index is
-                let index = x_index * no_y_cells + y_index;
-                cells[index].indices.get_mut(&id).unwrap().push(i);
+                let (x, y) = domain.wrap(entity.x[i], entity.y[i]);
+                let cell = if domain.contains(x, y) {
+                    // particles on the upper limits belong to the last cell
+                    let (x_index, y_index) = grid.cell_index(x, y);
+                    let x_index = (x_index as usize).min(no_x_cells - 1);
+                    let y_index = (y_index as usize).min(no_y_cells - 1);
+                    Some(x_index * no_y_cells + y_index)
+                } else {
+                    grid.outside.push((*entity.id, i));
+                    None
+                };
+                cell_of.push(cell);
             }
+            grid.entity_index.insert(*entity.id, grid.cells.len());
+            grid.cells.push(CellList::new(&cell_of, no_x_cells * no_y_cells));
         }
-        let grid = LinkedListGrid {
-            no_x_cells,
-            no_y_cells,
-            x_min,
-            x_max,
-            y_min,
-            y_max,
-            size,
-            cells,
+        grid
+    }
+
+    /// Two dimensional index of the cell containing the point, which is
+    /// negative or beyond the number of cells for points outside the grid.
+    pub fn cell_index(&self, x: f32, y: f32) -> (isize, isize) {
+        (
+            ((x - self.x_min) / self.size_x).floor() as isize,
+            ((y - self.y_min) / self.size_y).floor() as isize,
+        )
+    }
+
+    /// Separation of two particles, from the nearest image along the
+    /// periodic axes, see `Domain::min_image`. The images across the sides
+    /// of a sheared domain are displaced by `shear_offset`.
+    pub fn min_image(&self, dx: f32, dy: f32) -> (f32, f32) {
+        let k = self.images_crossed(dy);
+        let dx = dx - k * self.shear_offset;
+        (
+            min_image_coordinate(dx, self.x_max - self.x_min, self.periodic_x),
+            dy - k * (self.y_max - self.y_min),
+        )
+    }
+
+    /// Velocity along x to be added to a particle at a separation `dy` along
+    /// y, to get the velocity of its nearest image in a sheared domain.
+    pub fn image_velocity(&self, dy: f32) -> f32 {
+        self.images_crossed(dy) * self.shear_velocity
+    }
+
+    // number of periods along y between a particle and the nearest image of
+    // another one at a separation `dy`
+    fn images_crossed(&self, dy: f32) -> f32 {
+        if self.periodic_y {
+            (dy / (self.y_max - self.y_min)).round()
+        } else {
+            0.
+        }
+    }
+
+    /// Cell list of the entity with the given id.
+    pub fn entity_cells(&self, id: usize) -> &CellList {
+        &self.cells[self.entity_index[&id]]
+    }
+}
+
+/// Number of cells along an axis of the given length and their size. Cells
+/// along a periodic axis are enlarged to span the length exactly, otherwise
+/// the last cell extends beyond it.
+fn cells_along(length: f32, size: f32, periodic: bool) -> (usize, f32) {
+    if periodic {
+        let no_cells = ((length / size).floor() as usize).max(1);
+        (no_cells, length / no_cells as f32)
+    } else {
+        (((length / size).ceil() as usize).max(1), size)
+    }
+}
+
+/// Cell size from the largest particle of `world`.
+fn cell_size<T: NNPS + ?Sized>(world: &mut Vec<&mut T>, scale: f32, skin: f32) -> f32 {
+    // find particle with maximum size to set
+    // the size of the grid cell
+    let mut size: f32 = 0.;
+    for entity in world.iter_mut() {
+        let ent_i = entity.get_parts_mut_nnps();
+        for i in 0..ent_i.h.len() {
+            size = size.max(ent_i.h[i]);
+        }
+    }
+    // scale the size
+    size * scale + skin
+}
+
+/// Linked list grid which is rebuilt only when the particles have moved far
+/// enough to miss a neighbour.
+///
+/// The cells are enlarged by a skin distance. The grid is reused until the
+/// largest displacement of any particle since the last build exceeds half
+/// the skin, as two particles approaching each other can then have closed
+/// the skin. A larger skin means fewer rebuilds, but more candidate
+/// neighbours to check at every force evaluation.
+pub struct VerletGrid {
+    pub grid: LinkedListGrid,
+    pub scale: f32,
+    pub skin: f32,
+    /// fixed domain of the grid, if not set the grid covers the particles
+    pub domain: Option<Domain>,
+    /// number of times the grid has been built
+    pub builds: usize,
+    // positions of the particles of every entity at the last build
+    x0: Vec<Vec<f32>>,
+    y0: Vec<Vec<f32>>,
+}
+
+impl VerletGrid {
+    pub fn new<T: NNPS + ?Sized>(world: &mut Vec<&mut T>, scale: f32, skin: f32) -> VerletGrid {
+        let mut grid = VerletGrid {
+            grid: LinkedListGrid::new_with_skin(world, scale, skin),
+            scale,
+            skin,
+            domain: None,
+            builds: 0,
+            x0: vec![],
+            y0: vec![],
         };
+        grid.save_positions(world);
+        grid
+    }
 
+    /// Grid over a fixed domain, see `LinkedListGrid::new_in_domain`.
+    pub fn new_in_domain<T: NNPS + ?Sized>(
+        world: &mut Vec<&mut T>,
+        scale: f32,
+        skin: f32,
+        domain: Domain,
+    ) -> VerletGrid {
+        let mut grid = VerletGrid {
+            grid: LinkedListGrid::new_in_domain(world, scale, skin, domain),
+            scale,
+            skin,
+            domain: Some(domain),
+            builds: 0,
+            x0: vec![],
+            y0: vec![],
+        };
+        grid.save_positions(world);
         grid
     }
+
+    fn save_positions<T: NNPS + ?Sized>(&mut self, world: &mut Vec<&mut T>) {
+        self.x0 = world.iter().map(|entity| entity.get_x().clone()).collect();
+        self.y0 = world.iter().map(|entity| entity.get_y().clone()).collect();
+        self.builds += 1;
+    }
+
+    /// Check if the grid has to be rebuilt, either because a particle moved
+    /// by more than half the skin, or the number of particles of `world` has
+    /// changed. Particles removed and added in equal numbers go unnoticed,
+    /// and need a `rebuild`.
+    pub fn needs_rebuild<T: NNPS + ?Sized>(&self, world: &mut Vec<&mut T>) -> bool {
+        if world.len() != self.x0.len() {
+            return true;
+        }
+        let max_disp_sq = (self.skin / 2.).powf(2.);
+        for (k, entity) in world.iter().enumerate() {
+            let (x, y) = (entity.get_x(), entity.get_y());
+            let (x0, y0) = (&self.x0[k], &self.y0[k]);
+            if x.len() != x0.len() {
+                return true;
+            }
+            for i in 0..x.len() {
+                let (dx, dy) = self.grid.min_image(x[i] - x0[i], y[i] - y0[i]);
+                if dx * dx + dy * dy > max_disp_sq {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// Rebuild the grid if needed, returns true if it was rebuilt.
+    pub fn update<T: NNPS + ?Sized>(&mut self, world: &mut Vec<&mut T>) -> bool {
+        if !self.needs_rebuild(world) {
+            return false;
+        }
+        self.rebuild(world);
+        true
+    }
+
+    /// Rebuild the grid from the current particles of `world`.
+    pub fn rebuild<T: NNPS + ?Sized>(&mut self, world: &mut Vec<&mut T>) {
+        self.grid = match self.domain {
+            Some(domain) => LinkedListGrid::new_in_domain(world, self.scale, self.skin, domain),
+            None => LinkedListGrid::new_with_skin(world, self.scale, self.skin),
+        };
+        self.save_positions(world);
+    }
 }
 
+/// Particles of the entity `src_id` in the cell of `pos` and the cells
+/// around it, as one slice of indices per cell. Cells beyond the limits of
+/// the grid are skipped, except along the periodic axes where the cells
+/// wrap around. Every cell is listed once, also when there are fewer than
+/// three cells along a periodic axis. Beyond the sides of a sheared domain
+/// the cells around the displaced position of the images are listed.
 pub fn get_neighbours_ll<'a>(
     pos: [f32; 3],
     grid: &'a LinkedListGrid,
     src_id: &usize,
-) -> Vec<&'a Vec<usize>> {
-    let cells = &grid.cells;
-
-    let x_index = ((pos[0] - grid.x_min) / grid.size) as usize;
-    let y_index = ((pos[1] - grid.y_min) / grid.size) as usize;
-
-    // index in grid
-    let index = x_index * grid.no_y_cells + y_index;
+) -> Vec<&'a [usize]> {
+    let cells = grid.entity_cells(*src_id);
+    let (x_index, y_index) = grid.cell_index(pos[0], pos[1]);
+    let (no_x_cells, no_y_cells) = (grid.no_x_cells as isize, grid.no_y_cells as isize);
+    let (y_lo, y_hi) = neighbour_span(y_index, no_y_cells, grid.periodic_y);
 
-    let mut neighbours_particle: Vec<&Vec<usize>> = Vec::new();
+    let mut neighbours_particle: Vec<&[usize]> = Vec::with_capacity(9);
 
     // for the stack of z = 0
-    for neighbour in &[
-        Some(index),
-        index.checked_sub(1),
-        index.checked_add(1),
-        index.checked_sub(grid.no_y_cells),
-        index.checked_sub(grid.no_y_cells - 1),
-        index.checked_sub(grid.no_y_cells + 1),
-        index.checked_add(grid.no_y_cells),
-        index.checked_add(grid.no_y_cells - 1),
-        index.checked_add(grid.no_y_cells + 1),
-    ] {
-        if let Some(cell) = neighbour.and_then(|index| cells.get(index)) {
-            neighbours_particle.push(&cell.indices[src_id])
+    for j in y_lo..y_hi {
+        let (j, x_index) = match neighbour_cell(j, no_y_cells, grid.periodic_y) {
+            Some((j, unwrapped)) if j != unwrapped && grid.shear_offset != 0. => {
+                // row beyond a sheared side, search around the position
+                // which is displaced onto the images
+                let k = ((unwrapped - j) / no_y_cells) as f32;
+                (j, grid.cell_index(pos[0] - k * grid.shear_offset, pos[1]).0)
+            }
+            Some((j, _)) => (j, x_index),
+            None => continue,
+        };
+        let (x_lo, x_hi) = neighbour_span(x_index, no_x_cells, grid.periodic_x);
+        for i in x_lo..x_hi {
+            let i = match neighbour_cell(i, no_x_cells, grid.periodic_x) {
+                Some((i, _)) => i,
+                None => continue,
+            };
+            neighbours_particle.push(cells.cell((i * no_y_cells + j) as usize));
         }
     }
     neighbours_particle
 }
+
+/// Range of cell indices around `index` along an axis with `no_cells` cells.
+fn neighbour_span(index: isize, no_cells: isize, periodic: bool) -> (isize, isize) {
+    if periodic && no_cells < 3 {
+        // all the cells are neighbours, and wrapping would repeat them
+        (0, no_cells)
+    } else {
+        (index - 1, index + 2)
+    }
+}
+
+/// Cell index along an axis, wrapped around along a periodic axis, and the
+/// index before wrapping, or none if it is beyond the grid.
+fn neighbour_cell(index: isize, no_cells: isize, periodic: bool) -> Option<(isize, isize)> {
+    if periodic {
+        Some((index.rem_euclid(no_cells), index))
+    } else if index >= 0 && index < no_cells {
+        Some((index, index))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{get_neighbours_ll, CellList, Domain, LinkedListGrid, VerletGrid};
+    use physics::dem::DemDiscrete;
+
+    fn particles(x: Vec<f32>, y: Vec<f32>) -> DemDiscrete {
+        let mut ent = DemDiscrete::new(x.len(), 0, "particles".to_string());
+        for i in 0..x.len() {
+            ent.x[i] = x[i];
+            ent.y[i] = y[i];
+            ent.h[i] = 0.5;
+        }
+        ent
+    }
+
+    #[test]
+    fn test_verlet_grid_is_rebuilt_after_moving_half_the_skin() {
+        let mut ent = particles(vec![0., 1., 2.], vec![0., 0., 0.]);
+        let skin = 0.4;
+        let mut grid = VerletGrid::new(&mut vec![&mut ent], 2., skin);
+        assert_eq!(1, grid.builds);
+        assert!((grid.grid.size - (2. * 0.5 + skin)).abs() < 1e-6);
+
+        // small displacements keep the grid
+        ent.x[0] += 0.15;
+        ent.y[2] -= 0.15;
+        assert!(!grid.update(&mut vec![&mut ent]));
+        assert_eq!(1, grid.builds);
+
+        // moving by more than half the skin triggers a rebuild
+        ent.x[1] += 0.25;
+        assert!(grid.update(&mut vec![&mut ent]));
+        assert_eq!(2, grid.builds);
+        assert!(!grid.update(&mut vec![&mut ent]));
+    }
+
+    #[test]
+    fn test_verlet_grid_finds_neighbours_within_the_skin() {
+        // two particles initially out of contact approach each other by
+        // less than half the skin each
+        let mut ent = particles(vec![0., 1.3], vec![0., 0.]);
+        let skin = 0.8;
+        let mut grid = VerletGrid::new(&mut vec![&mut ent], 2., skin);
+        ent.x[0] += 0.15;
+        ent.x[1] -= 0.15;
+        assert!(!grid.update(&mut vec![&mut ent]));
+
+        let nbrs = get_neighbours_ll([ent.x[0], ent.y[0], 0.], &grid.grid, &0);
+        assert!(nbrs.iter().any(|cell| cell.contains(&1)));
+    }
+
+    #[test]
+    fn test_verlet_grid_is_rebuilt_when_particles_change() {
+        let mut ent = particles(vec![0., 1.], vec![0., 0.]);
+        let mut grid = VerletGrid::new(&mut vec![&mut ent], 2., 0.4);
+        let mut more = particles(vec![0., 1., 2.], vec![0., 0., 0.]);
+        assert!(grid.update(&mut vec![&mut more]));
+    }
+
+    #[test]
+    fn test_counting_sort_of_cell_list() {
+        let cells = CellList::new(&[Some(2), Some(0), None, Some(2), Some(3), Some(0)], 5);
+        assert_eq!(vec![0, 2, 2, 4, 5, 5], cells.start);
+        assert_eq!(&[1, 5], cells.cell(0));
+        assert!(cells.cell(1).is_empty());
+        assert_eq!(&[0, 3], cells.cell(2));
+        assert_eq!(&[4], cells.cell(3));
+        assert!(cells.cell(4).is_empty());
+    }
+
+    #[test]
+    fn test_neighbours_include_all_particles_in_range() {
+        // particles on a regular lattice in two entities, the neighbours of
+        // every particle include all the particles within the cell size
+        let n = 20;
+        let (mut x, mut y) = (vec![], vec![]);
+        for i in 0..n * n {
+            x.push(0.37 * (i % n) as f32);
+            y.push(0.41 * (i / n) as f32);
+        }
+        let mut first = particles(x.clone(), y.clone());
+        let mut second = particles(y, x);
+        second.id = 1;
+        let grid = LinkedListGrid::new(&mut vec![&mut first, &mut second], 2.);
+
+        for i in 0..n * n {
+            let pos = [first.x[i], first.y[i], 0.];
+            let nbrs: Vec<usize> = get_neighbours_ll(pos, &grid, &1)
+                .iter()
+                .flat_map(|cell| cell.iter().cloned())
+                .collect();
+            for j in 0..n * n {
+                let (dx, dy) = (second.x[j] - pos[0], second.y[j] - pos[1]);
+                if (dx * dx + dy * dy).sqrt() < grid.size {
+                    assert!(nbrs.contains(&j));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_neighbours_do_not_wrap_around_columns() {
+        // a particle at the top of a column and another at the bottom of the
+        // next column are far apart, but adjacent in the flattened index
+        let mut ent = particles(vec![0., 0.6], vec![10., 0.]);
+        let grid = LinkedListGrid::new(&mut vec![&mut ent], 1.);
+        assert_eq!(2, grid.no_x_cells);
+        let nbrs = get_neighbours_ll([0., 10., 0.], &grid, &0);
+        assert!(nbrs.iter().all(|cell| !cell.contains(&1)));
+
+        // positions outside the grid only see the cells next to them
+        assert!(get_neighbours_ll([-10., -10., 0.], &grid, &0).is_empty());
+        let nbrs = get_neighbours_ll([0.6, -0.3, 0.], &grid, &0);
+        assert!(nbrs.iter().any(|cell| cell.contains(&1)));
+    }
+
+    #[test]
+    fn test_grid_over_fixed_domain() {
+        let mut ent = particles(vec![0., 1., 5., 12.], vec![0., 1., 10., 1.]);
+        let domain = Domain::new(0., 10., 0., 10.);
+        let grid = LinkedListGrid::new_in_domain(&mut vec![&mut ent], 2., 0., domain);
+        assert_eq!((10, 10), (grid.no_x_cells, grid.no_y_cells));
+        assert_eq!((0., 10.), (grid.x_min, grid.x_max));
+
+        // the particle on the upper limit is binned, the one beyond is not
+        assert_eq!(vec![(0, 3)], grid.outside);
+        let binned: usize = grid.entity_cells(0).indices.len();
+        assert_eq!(3, binned);
+        let nbrs = get_neighbours_ll([5., 9.5, 0.], &grid, &0);
+        assert!(nbrs.iter().any(|cell| cell.contains(&2)));
+
+        assert_eq!((10., 0.), domain.clamp(12., -1.));
+    }
+
+    #[test]
+    fn test_periodic_domain() {
+        let domain = Domain::new(0., 10., 0., 10.).periodic(true, false);
+        assert_eq!((0.5, 12.), domain.wrap(10.5, 12.));
+        assert_eq!((9.5, -1.), domain.wrap(-0.5, -1.));
+        assert_eq!((0., 5.), domain.wrap(10., 5.));
+        let (dx, dy) = domain.min_image(-9.6, -9.6);
+        assert!((dx - 0.4).abs() < 1e-5 && dy == -9.6);
+        let (dx, dy) = domain.min_image(9.6, 4.);
+        assert!((dx + 0.4).abs() < 1e-5 && dy == 4.);
+    }
+
+    #[test]
+    fn test_neighbours_wrap_around_periodic_axes() {
+        let mut ent = particles(vec![0.2, 9.8, 0.2], vec![5., 5., 9.8]);
+        let domain = Domain::new(0., 10., 0., 10.).periodic(true, false);
+        // cells are enlarged to span the period exactly
+        let grid = LinkedListGrid::new_in_domain(&mut vec![&mut ent], 3., 0., domain);
+        assert_eq!((6, 7), (grid.no_x_cells, grid.no_y_cells));
+        assert!((grid.size_x - 10. / 6.).abs() < 1e-6);
+        assert_eq!(1.5, grid.size_y);
+
+        // the particles near the left and right sides are neighbours, the
+        // ones near the bottom and top are not
+        let nbrs = get_neighbours_ll([0.2, 5., 0.], &grid, &0);
+        assert!(nbrs.iter().any(|cell| cell.contains(&1)));
+        let nbrs = get_neighbours_ll([0.2, 0.2, 0.], &grid, &0);
+        assert!(nbrs.iter().all(|cell| !cell.contains(&2)));
+        assert!((grid.min_image(0.2 - 9.8, 0.).0 - 0.4).abs() < 1e-5);
+
+        // with two cells along the periodic axis every cell is listed once
+        let domain = Domain::new(0., 2., 0., 10.).periodic(true, false);
+        let grid = LinkedListGrid::new_in_domain(&mut vec![&mut ent], 2., 0., domain);
+        assert_eq!(2, grid.no_x_cells);
+        assert_eq!(6, get_neighbours_ll([0.2, 5., 0.], &grid, &0).len());
+    }
+
+    #[test]
+    fn test_sheared_domain() {
+        let domain = Domain::new(0., 2., 0., 2.).sheared(0.5);
+        assert!(domain.periodic_x && domain.periodic_y);
+        assert_eq!(1., domain.shear_velocity());
+        assert_eq!(0.5, domain.shear_offset(2.5));
+
+        // leaving through the top side, the particle reenters at the bottom
+        // displaced by the offset and slowed down by the shear velocity
+        let (x, y, du) = domain.wrap_sheared(1., 2.1, 0.5);
+        assert!((x - 0.5).abs() < 1e-6 && (y - 0.1).abs() < 1e-6);
+        assert_eq!(-1., du);
+        let (x, y, du) = domain.wrap_sheared(1.9, -0.1, 0.5);
+        assert!((x - 0.4).abs() < 1e-6 && (y - 1.9).abs() < 1e-6);
+        assert_eq!(1., du);
+        assert_eq!((1., 1., 0.), domain.wrap_sheared(1., 1., 0.5));
+    }
+
+    #[test]
+    fn test_neighbours_across_sheared_sides() {
+        // the image of the particle at the bottom, displaced by 4, is right
+        // above the particle at the top
+        let mut ent = particles(vec![2., 8.], vec![9.8, 0.2]);
+        let domain = Domain::new(0., 10., 0., 10.).sheared(0.1);
+        let mut grid = LinkedListGrid::new_in_domain(&mut vec![&mut ent], 2., 0., domain);
+        let nbrs = get_neighbours_ll([2., 9.8, 0.], &grid, &0);
+        assert!(nbrs.iter().all(|cell| !cell.contains(&1)));
+
+        grid.shear_offset = 4.;
+        let nbrs = get_neighbours_ll([2., 9.8, 0.], &grid, &0);
+        assert!(nbrs.iter().any(|cell| cell.contains(&1)));
+        let nbrs = get_neighbours_ll([8., 0.2, 0.], &grid, &0);
+        assert!(nbrs.iter().any(|cell| cell.contains(&0)));
+
+        let (dx, dy) = grid.min_image(2. - 8., 9.8 - 0.2);
+        assert!(dx.abs() < 1e-5 && (dy + 0.4).abs() < 1e-5);
+        assert_eq!(1., grid.image_velocity(9.6));
+        assert_eq!(-1., grid.image_velocity(-9.6));
+        assert_eq!(0., grid.image_velocity(0.4));
+    }
+}
@@ -0,0 +1,162 @@
+// local imports
+use geometry::drum_fill_2d;
+use integrate::Integrator;
+use motion::Motion;
+use physics::dem::equations::{set_disk_inertia_dem, ContactLaw, RollingModel};
+use physics::dem::DemDiscrete;
+use physics::material::{Material, MaterialDatabase};
+use physics::timestep::estimate_time_step;
+use physics::wall::{Wall, WallShape};
+use postprocess::angle_of_repose;
+use solver::{Equation, Solver};
+
+// external crate imports
+use cm::Vector3;
+use std::f32::consts::PI;
+
+/// Drum of the given radius centred at the origin, partially filled with
+/// grains of equal size and rotating counter-clockwise about its centre.
+///
+/// The grains start on a square lattice at the bottom of the drum, see
+/// `drum_fill_2d`, and are set in motion by gravity and the friction of the
+/// drum, an analytic circular wall.
+pub struct RotatingDrum {
+    pub radius: f32,
+    /// angular velocity of the drum, counter-clockwise
+    pub omega: f32,
+    /// fraction of the area of the drum filled with grains
+    pub fill_fraction: f32,
+    pub grains_radius: f32,
+    /// material of the grains and the drum
+    pub material: Material,
+    pub rolling: RollingModel,
+    pub law: ContactLaw,
+    pub gravity: f32,
+    /// fraction of the critical time step used as the time step
+    pub safety_factor: f32,
+}
+
+impl RotatingDrum {
+    pub fn new(radius: f32, omega: f32, grains_radius: f32, material: Material) -> Self {
+        RotatingDrum {
+            radius,
+            omega,
+            fill_fraction: 0.4,
+            grains_radius,
+            material,
+            rolling: RollingModel::ConstantTorque,
+            law: ContactLaw::LinearViscoelastic,
+            gravity: 9.81,
+            safety_factor: 0.2,
+        }
+    }
+
+    /// Solver running the drum up to the final time `tf` with a stable time
+    /// step, and the index of the grains in it.
+    pub fn solver<I: Integrator>(&self, integrator: I, tf: f32) -> (Solver<I>, usize) {
+        let mut materials = MaterialDatabase::new();
+        let material_id = materials.add(self.material.clone());
+        materials.rolling = self.rolling;
+
+        let rad = self.grains_radius;
+        let (x, y) = drum_fill_2d(self.radius, self.fill_fraction, 2. * rad);
+        let mut grains = DemDiscrete::new(x.len(), 0, "grains".to_string());
+        let mass = self.material.disk_mass(rad);
+        for i in 0..grains.len {
+            grains.x[i] = x[i];
+            grains.y[i] = y[i];
+            grains.h[i] = rad;
+            grains.rad[i] = rad;
+            grains.m[i] = mass;
+            grains.m_inv[i] = 1. / mass;
+        }
+        set_disk_inertia_dem(&mut grains);
+        grains.material_id = material_id;
+
+        let circle = WallShape::Arc {
+            centre: Vector3::new(0., 0., 0.),
+            radius: self.radius,
+            start_angle: 0.,
+            end_angle: 2. * PI,
+        };
+        let rotation = Motion::Rotation {
+            pivot: Vector3::new(0., 0., 0.),
+            omega: self.omega,
+        };
+        let mut drum = Wall::new(circle, 1, "drum".to_string()).moving(rotation);
+        drum.material_id = material_id;
+
+        let dt = estimate_time_step(&mut vec![&mut grains], &materials)
+            .unwrap()
+            .stable(self.safety_factor);
+        let mut solver = Solver::new(integrator, materials, dt, tf);
+        solver.safety_factor = self.safety_factor;
+        let grains = solver.add_entity(grains);
+        let drum = solver.add_wall(drum);
+        solver.add_equation(Equation::BodyForce {
+            entity: grains,
+            gx: 0.,
+            gy: -self.gravity,
+        });
+        solver.add_equation(Equation::SelfContact {
+            entity: grains,
+            law: self.law,
+        });
+        solver.add_equation(Equation::WallContact {
+            entity: grains,
+            wall: drum,
+            law: self.law,
+        });
+        (solver, grains)
+    }
+
+    /// Dynamic angle of repose of the grains, from the free surface in the
+    /// middle half of the drum in bins two grains wide, see
+    /// `angle_of_repose`.
+    pub fn angle_of_repose(&self, grains: &DemDiscrete) -> Option<f32> {
+        let half_width = 0.5 * self.radius;
+        let no_bins = (half_width / (2. * self.grains_radius)).round().max(2.) as usize;
+        angle_of_repose(&grains.x, &grains.y, &grains.rad, 0., half_width, no_bins)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RotatingDrum;
+    use integrate::SymplecticEuler;
+    use physics::material::Material;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_angle_of_repose_in_rotating_drum() {
+        // the drum of the rotating drum example scaled up six times, with
+        // 125 grains, rotating at the same Froude number omega^2 R / g. The
+        // grains are softer, for a larger time step
+        let mut glass = Material::new("glass".to_string(), 2500., 1e5, 0.3);
+        glass.en = 0.5;
+        glass.mu = 0.5;
+        glass.mu_r = 0.1;
+        let omega = 2. / 6_f32.sqrt();
+        let drum = RotatingDrum::new(0.6, omega, 0.03, glass);
+        let (mut solver, grains) = drum.solver(SymplecticEuler, 2.5);
+        solver.set_output_frequency(10);
+
+        // the surface fluctuates with the avalanches, so the angle is averaged
+        // once the flow has developed
+        let angles = Rc::new(RefCell::new(vec![]));
+        let angles_c = angles.clone();
+        solver.add_on_output(move |entities, info| {
+            let grains = entities[grains].as_discrete().unwrap();
+            if info.t > 1. {
+                angles_c.borrow_mut().push(drum.angle_of_repose(grains).unwrap());
+            }
+        });
+        solver.run().unwrap();
+
+        let angles = angles.borrow();
+        let mean = angles.iter().sum::<f32>() / angles.len() as f32;
+        assert!(angles.len() >= 25);
+        assert!(mean.to_degrees() > 20. && mean.to_degrees() < 40.);
+    }
+}
@@ -1,4 +1,7 @@
 use ndarray::{Array};
+use rand::distributions::{IndependentSample, LogNormal, Normal};
+use rand::{Isaac64Rng, Rng, SeedableRng};
+use std::f32::consts::PI;
 
 pub fn linspace(start: f32, stop: f32, num: isize) -> Vec<f32> {
     Array::linspace(start, stop, num as usize).to_vec()
@@ -243,3 +246,365 @@ pub fn hopper_2d(
     hpl_y.append(&mut hpr_y);
     (hpl_x, hpl_y)
 }
+
+/// Returns particle coordinates on a circle of given radius centred at
+/// the origin, with the arc length between neighbours at most `spacing`.
+/// It can be used as a particle boundary of a drum, rotated with a
+/// prescribed motion.
+pub fn ring_2d(radius: f32, spacing: f32) -> (Vec<f32>, Vec<f32>) {
+    let no_particles = (2. * PI * radius / spacing).ceil().max(3.) as usize;
+    let dtheta = 2. * PI / no_particles as f32;
+    (0..no_particles)
+        .map(|i| {
+            let theta = i as f32 * dtheta;
+            (radius * theta.cos(), radius * theta.sin())
+        })
+        .unzip()
+}
+
+/// Returns particle coordinates on a square grid of given spacing, filling
+/// the bottom of a drum of given radius centred at the origin, up to the
+/// level where the circular segment below it is `fill_fraction` of the
+/// drum. The particles are at least half the spacing inside the drum.
+pub fn drum_fill_2d(radius: f32, fill_fraction: f32, spacing: f32) -> (Vec<f32>, Vec<f32>) {
+    let level = drum_fill_level(radius, fill_fraction);
+    let inner = radius - spacing / 2.;
+    let (xg, yg) = grid_2d(2. * inner, level + inner, spacing);
+
+    let mut x = vec![];
+    let mut y = vec![];
+    for (&xi, &yi) in xg.iter().zip(yg.iter()) {
+        // centre the grid on the drum
+        let (xi, yi) = (xi - inner + spacing / 2., yi - inner + spacing / 2.);
+        if xi * xi + yi * yi <= inner * inner && yi <= level {
+            x.push(xi);
+            y.push(yi);
+        }
+    }
+    (x, y)
+}
+
+/// Height, relative to the centre, of the free surface of a drum of given
+/// radius filled to `fill_fraction` of its area.
+pub fn drum_fill_level(radius: f32, fill_fraction: f32) -> f32 {
+    // area of the circular segment below the level, as a fraction of the
+    // circle, increases from 0 at the bottom to 1 at the top
+    let fraction = |level: f32| {
+        let h = (level / radius).clamp(-1., 1.);
+        (PI - h.acos() + h * (1. - h * h).sqrt()) / PI
+    };
+    let (mut low, mut high) = (-radius, radius);
+    for _ in 0..50 {
+        let mid = 0.5 * (low + high);
+        if fraction(mid) < fill_fraction {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    0.5 * (low + high)
+}
+
+/// Distribution of the radii of the particles of a polydisperse packing.
+/// The fractions are by number of particles.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SizeDistribution {
+    /// Radii uniformly distributed between `min` and `max`.
+    Uniform { min: f32, max: f32 },
+    /// Normal distribution of the radius, truncated to `min` and `max`.
+    Normal {
+        mean: f32,
+        std_dev: f32,
+        min: f32,
+        max: f32,
+    },
+    /// Log-normal distribution, where the logarithm of the radius has the
+    /// given mean and standard deviation, truncated to `min` and `max`.
+    LogNormal {
+        mean: f32,
+        std_dev: f32,
+        min: f32,
+        max: f32,
+    },
+    /// Discrete mixture of radii, given as pairs of radius and fraction.
+    /// The fractions need not add up to one. Use `mixture` to check that
+    /// there is at least one radius.
+    Mixture(Vec<(f32, f32)>),
+    /// Particle size distribution table, given as pairs of radius and
+    /// cumulative fraction of the particles finer than the radius, both
+    /// increasing. The radius is interpolated linearly between the rows.
+    /// Use `table` to check that there is at least one row.
+    Table(Vec<(f32, f32)>),
+}
+
+impl SizeDistribution {
+    /// Discrete mixture of the pairs of radius and fraction `radii`.
+    pub fn mixture(radii: Vec<(f32, f32)>) -> Self {
+        assert!(!radii.is_empty(), "a mixture needs at least one radius");
+        SizeDistribution::Mixture(radii)
+    }
+
+    /// Particle size distribution table of the pairs of radius and
+    /// cumulative fraction `table`.
+    pub fn table(table: Vec<(f32, f32)>) -> Self {
+        assert!(!table.is_empty(), "a size distribution table needs at least one row");
+        assert!(
+            table
+                .windows(2)
+                .all(|row| row[0].0 <= row[1].0 && row[0].1 <= row[1].1),
+            "the radii and fractions of a size distribution table have to increase"
+        );
+        SizeDistribution::Table(table)
+    }
+
+    /// Draw a radius from the distribution.
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> f32 {
+        match *self {
+            SizeDistribution::Uniform { min, max } => min + (max - min) * rng.gen::<f32>(),
+            SizeDistribution::Normal {
+                mean,
+                std_dev,
+                min,
+                max,
+            } => {
+                let normal = Normal::new(mean as f64, std_dev as f64);
+                truncated(|| normal.ind_sample(rng) as f32, min, max)
+            }
+            SizeDistribution::LogNormal {
+                mean,
+                std_dev,
+                min,
+                max,
+            } => {
+                let log_normal = LogNormal::new(mean as f64, std_dev as f64);
+                truncated(|| log_normal.ind_sample(rng) as f32, min, max)
+            }
+            SizeDistribution::Mixture(ref radii) => {
+                let total: f32 = radii.iter().map(|&(_, fraction)| fraction).sum();
+                let mut u = total * rng.gen::<f32>();
+                for &(rad, fraction) in radii {
+                    if u < fraction {
+                        return rad;
+                    }
+                    u -= fraction;
+                }
+                radii[radii.len() - 1].0
+            }
+            SizeDistribution::Table(ref table) => {
+                let (first, last) = (table[0].1, table[table.len() - 1].1);
+                let u = first + (last - first) * rng.gen::<f32>();
+                for row in table.windows(2) {
+                    let ((rad_0, f_0), (rad_1, f_1)) = (row[0], row[1]);
+                    if u < f_1 {
+                        return rad_0 + (rad_1 - rad_0) * (u - f_0) / (f_1 - f_0);
+                    }
+                }
+                table[table.len() - 1].0
+            }
+        }
+    }
+
+    /// Largest radius which can be drawn from the distribution.
+    pub fn max_radius(&self) -> f32 {
+        match *self {
+            SizeDistribution::Uniform { max, .. }
+            | SizeDistribution::Normal { max, .. }
+            | SizeDistribution::LogNormal { max, .. } => max,
+            SizeDistribution::Mixture(ref radii) => {
+                radii.iter().fold(0., |max, &(rad, _)| rad.max(max))
+            }
+            SizeDistribution::Table(ref table) => table[table.len() - 1].0,
+        }
+    }
+}
+
+/// Number of draws from a truncated distribution before the last one is
+/// clamped to the limits, which happens if they are far in its tails.
+const MAX_TRUNCATION_ATTEMPTS: usize = 1000;
+
+// draw from a distribution until the value is within the limits
+fn truncated<F: FnMut() -> f32>(mut sample: F, min: f32, max: f32) -> f32 {
+    assert!(min <= max, "truncation limits are not ordered");
+    let mut value = sample();
+    for _ in 1..MAX_TRUNCATION_ATTEMPTS {
+        if value >= min && value <= max {
+            return value;
+        }
+        value = sample();
+    }
+    value.max(min).min(max)
+}
+
+/// Number of random positions tried for a particle by `random_packing_2d`
+/// before the packing is considered full.
+const MAX_ATTEMPTS: usize = 10000;
+
+/**
+Returns particle coordinates and radii of a polydisperse packing filling
+the rectangle from the origin to (`length`, `height`), by random sequential
+addition: every particle is given a radius drawn from `distribution` and
+placed at random positions until it overlaps neither the other particles
+nor the sides of the rectangle.
+
+The generator is seeded with `seed`, so the same arguments give the same
+packing. Fewer than `no_particles` particles are returned if one can't be
+placed, which happens beyond a solid fraction of about one half. Denser
+packings can be obtained by letting the particles settle under gravity.
+ */
+pub fn random_packing_2d(
+    length: f32,
+    height: f32,
+    distribution: &SizeDistribution,
+    no_particles: usize,
+    seed: u64,
+) -> (Vec<f32>, Vec<f32>, Vec<f32>) {
+    let mut rng = Isaac64Rng::from_seed(&[seed][..]);
+
+    // cells of the size of the largest particle, so overlapping particles
+    // are in neighbouring cells
+    let cell_size = 2. * distribution.max_radius();
+    let no_x = ((length / cell_size).ceil() as usize).max(1);
+    let no_y = ((height / cell_size).ceil() as usize).max(1);
+    let cell_of = |x: f32, y: f32| {
+        (
+            ((x / cell_size) as usize).min(no_x - 1),
+            ((y / cell_size) as usize).min(no_y - 1),
+        )
+    };
+    let mut cells: Vec<Vec<usize>> = vec![vec![]; no_x * no_y];
+
+    let (mut x, mut y, mut rad) = (vec![], vec![], vec![]);
+    'particles: while x.len() < no_particles {
+        let r = distribution.sample(&mut rng);
+        if 2. * r > length || 2. * r > height {
+            break;
+        }
+        for _ in 0..MAX_ATTEMPTS {
+            let xi = r + (length - 2. * r) * rng.gen::<f32>();
+            let yi = r + (height - 2. * r) * rng.gen::<f32>();
+            let (cx, cy) = cell_of(xi, yi);
+            let overlaps = (cx.saturating_sub(1)..(cx + 2).min(no_x)).any(|i| {
+                (cy.saturating_sub(1)..(cy + 2).min(no_y)).any(|j| {
+                    cells[i + j * no_x].iter().any(|&k| {
+                        let (dx, dy) = (xi - x[k], yi - y[k]);
+                        dx * dx + dy * dy < (r + rad[k]) * (r + rad[k])
+                    })
+                })
+            });
+            if !overlaps {
+                cells[cx + cy * no_x].push(x.len());
+                x.push(xi);
+                y.push(yi);
+                rad.push(r);
+                continue 'particles;
+            }
+        }
+        break;
+    }
+    (x, y, rad)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{random_packing_2d, SizeDistribution};
+    use rand::{Isaac64Rng, SeedableRng};
+
+    #[test]
+    fn test_size_distributions_are_within_their_limits() {
+        let mut rng = Isaac64Rng::from_seed(&[1][..]);
+        let distributions = [
+            SizeDistribution::Uniform { min: 0.1, max: 0.2 },
+            SizeDistribution::Normal {
+                mean: 0.15,
+                std_dev: 0.05,
+                min: 0.1,
+                max: 0.2,
+            },
+            SizeDistribution::LogNormal {
+                mean: 0.15_f32.ln(),
+                std_dev: 0.5,
+                min: 0.1,
+                max: 0.2,
+            },
+        ];
+        for distribution in &distributions {
+            for _ in 0..1000 {
+                let rad = distribution.sample(&mut rng);
+                assert!((0.1..=0.2).contains(&rad));
+            }
+            assert_eq!(0.2, distribution.max_radius());
+        }
+    }
+
+    #[test]
+    fn test_mixture_and_table_fractions() {
+        let mut rng = Isaac64Rng::from_seed(&[2][..]);
+        let n = 10000;
+
+        // a quarter of small and three quarters of large particles
+        let mixture = SizeDistribution::mixture(vec![(0.1, 1.), (0.3, 3.)]);
+        let radii: Vec<f32> = (0..n).map(|_| mixture.sample(&mut rng)).collect();
+        let small = radii.iter().filter(|&&rad| rad == 0.1).count();
+        assert_eq!(n - small, radii.iter().filter(|&&rad| rad == 0.3).count());
+        assert!((small as f32 / n as f32 - 0.25).abs() < 0.02);
+        assert_eq!(0.3, mixture.max_radius());
+
+        // 80 % of the particles finer than 0.2, with a median of 0.15
+        let table = SizeDistribution::table(vec![(0.1, 0.), (0.2, 0.8), (0.4, 1.)]);
+        let radii: Vec<f32> = (0..n).map(|_| table.sample(&mut rng)).collect();
+        let fraction = |limit: f32| {
+            radii.iter().filter(|&&rad| rad < limit).count() as f32 / n as f32
+        };
+        assert!(radii.iter().all(|&rad| (0.1..=0.4).contains(&rad)));
+        assert!((fraction(0.2) - 0.8).abs() < 0.02);
+        assert!((fraction(0.15) - 0.4).abs() < 0.02);
+    }
+
+    #[test]
+    fn test_truncation_far_in_the_tail_is_clamped() {
+        let mut rng = Isaac64Rng::from_seed(&[3][..]);
+        let distribution = SizeDistribution::Normal {
+            mean: 0.,
+            std_dev: 1e-3,
+            min: 0.1,
+            max: 0.2,
+        };
+        assert_eq!(0.1, distribution.sample(&mut rng));
+    }
+
+    #[test]
+    #[should_panic(expected = "a mixture needs at least one radius")]
+    fn test_empty_mixture_is_rejected() {
+        SizeDistribution::mixture(vec![]);
+    }
+
+    #[test]
+    #[should_panic(expected = "a size distribution table needs at least one row")]
+    fn test_empty_table_is_rejected() {
+        SizeDistribution::table(vec![]);
+    }
+
+    #[test]
+    fn test_random_packing_is_reproducible_and_without_overlaps() {
+        let distribution = SizeDistribution::Uniform { min: 0.05, max: 0.1 };
+        let (x, y, rad) = random_packing_2d(4., 2., &distribution, 100, 42);
+        assert_eq!(100, x.len());
+        for i in 0..x.len() {
+            assert!(x[i] >= rad[i] && x[i] <= 4. - rad[i]);
+            assert!(y[i] >= rad[i] && y[i] <= 2. - rad[i]);
+            for j in 0..i {
+                let dist = ((x[i] - x[j]).powf(2.) + (y[i] - y[j]).powf(2.)).sqrt();
+                assert!(dist >= rad[i] + rad[j]);
+            }
+        }
+
+        // the same seed gives the same packing, another one a different one
+        let same = random_packing_2d(4., 2., &distribution, 100, 42);
+        assert_eq!((x.clone(), y, rad), same);
+        assert!(x != random_packing_2d(4., 2., &distribution, 100, 43).0);
+
+        // a full rectangle takes fewer particles than asked for
+        let (x, _, _) = random_packing_2d(1., 1., &distribution, 1000, 42);
+        assert!(x.len() > 20 && x.len() < 1000);
+    }
+}
@@ -0,0 +1,372 @@
+// local imports
+use contact_search::{get_neighbours_ll, Domain, LinkedListGrid, NNPS};
+use geometry::SizeDistribution;
+use physics::dem::DemDiscreteSrcTrait;
+use physics::material::MaterialDatabase;
+use physics::wall::Wall;
+use solver::Entity;
+
+// external crate imports
+use cm::{Vector3 as V3, Zero};
+use rand::{Isaac64Rng, Rng, SeedableRng};
+
+/// Rate at which an inlet inserts particles.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FlowRate {
+    /// number of particles per unit time
+    Particles(f32),
+    /// mass of particles per unit time
+    Mass(f32),
+}
+
+/// Number of random positions tried for a particle at every time step,
+/// before its insertion is postponed to the next one.
+const MAX_ATTEMPTS: usize = 100;
+
+/// Region through which particles are poured into a discrete entity while
+/// the simulation runs.
+///
+/// At every time step between `start` and `stop` the inlet owes the amount
+/// of particles given by the flow rate. They are placed at random positions
+/// in the region, where they overlap neither the particles of any entity
+/// nor the walls, with radii drawn from the size distribution. A particle
+/// which can't be placed is inserted at a later time step, so the flow is
+/// made up once the region clears. The amount owed is capped at what the
+/// region holds, a square lattice of the largest particles, so an inlet
+/// blocked for long doesn't release more than a region full of particles.
+pub struct Inlet {
+    /// index of the discrete entity in the solver
+    pub entity: usize,
+    pub region: Domain,
+    pub distribution: SizeDistribution,
+    pub rate: FlowRate,
+    /// velocity of the inserted particles
+    pub velocity: V3<f32>,
+    pub start: f32,
+    pub stop: f32,
+    /// number and total mass of the particles inserted so far
+    pub inserted: usize,
+    pub inserted_mass: f32,
+    rng: Isaac64Rng,
+    // amount owed by the inlet, in particles or mass
+    owed: f32,
+    // radius of the next particle to be inserted
+    next_rad: Option<f32>,
+}
+
+impl Inlet {
+    /// Inlet running from the start of the simulation, with the random
+    /// positions and radii seeded with `seed`.
+    pub fn new(
+        entity: usize,
+        region: Domain,
+        distribution: SizeDistribution,
+        rate: FlowRate,
+        seed: u64,
+    ) -> Self {
+        Inlet {
+            entity,
+            region,
+            distribution,
+            rate,
+            velocity: V3::zero(),
+            start: 0.,
+            stop: f32::MAX,
+            inserted: 0,
+            inserted_mass: 0.,
+            rng: Isaac64Rng::from_seed(&[seed][..]),
+            owed: 0.,
+            next_rad: None,
+        }
+    }
+
+    /// Insert the particles owed over the time step from `t` to `t + dt`
+    /// into the discrete entity, and return the number of particles
+    /// inserted.
+    pub fn insert(
+        &mut self,
+        entities: &mut [Entity],
+        walls: &[Wall],
+        materials: &MaterialDatabase,
+        t: f32,
+        dt: f32,
+    ) -> usize {
+        if t < self.start || t >= self.stop {
+            return 0;
+        }
+        let material_id = entities[self.entity]
+            .as_discrete()
+            .expect("an inlet inserts particles into a discrete entity")
+            .material_id;
+        let material = &materials.materials[material_id];
+
+        let capacity = self.capacity(material.disk_mass(self.distribution.max_radius()));
+        self.owed = capacity.min(
+            self.owed + match self.rate {
+                FlowRate::Particles(rate) | FlowRate::Mass(rate) => rate * dt,
+            },
+        );
+
+        // the grid over the region is built once something is owed, and the
+        // particles inserted since are checked separately
+        let mut grid = None;
+        let mut placed = vec![];
+        let mut inserted = 0;
+        loop {
+            let rad = match self.next_rad {
+                Some(rad) => rad,
+                None => self.distribution.sample(&mut self.rng),
+            };
+            self.next_rad = Some(rad);
+            let m = material.disk_mass(rad);
+            let amount = match self.rate {
+                FlowRate::Particles(_) => 1.,
+                FlowRate::Mass(_) => m,
+            };
+            if self.owed < amount {
+                break;
+            }
+            if grid.is_none() {
+                grid = Some(self.region_grid(entities));
+            }
+            let grid = grid.as_ref().unwrap();
+            let (x, y) = match self.free_position(rad, entities, walls, grid, &placed) {
+                Some(pos) => pos,
+                None => break,
+            };
+            placed.push((x, y, rad));
+
+            let ent = entities[self.entity].as_discrete_mut().unwrap();
+            let i = ent.add_particles(1);
+            ent.x[i] = x;
+            ent.y[i] = y;
+            ent.u[i] = self.velocity.x;
+            ent.v[i] = self.velocity.y;
+            ent.h[i] = rad;
+            ent.rad[i] = rad;
+            ent.m[i] = m;
+            ent.m_inv[i] = 1. / m;
+            ent.inertia[i] = 0.5 * m * rad.powf(2.);
+            ent.i_inv[i] = 1. / ent.inertia[i];
+
+            self.owed -= amount;
+            self.next_rad = None;
+            self.inserted += 1;
+            self.inserted_mass += m;
+            inserted += 1;
+        }
+        inserted
+    }
+
+    /// Amount of particles the region holds, in particles or mass, from a
+    /// square lattice of the largest particles of mass `m_max`.
+    fn capacity(&self, m_max: f32) -> f32 {
+        let (region, rad) = (self.region, self.distribution.max_radius());
+        let no_x = ((region.x_max - region.x_min) / (2. * rad)).floor();
+        let no_y = ((region.y_max - region.y_min) / (2. * rad)).floor();
+        let no_particles = (no_x * no_y).max(1.);
+        match self.rate {
+            FlowRate::Particles(_) => no_particles,
+            FlowRate::Mass(_) => no_particles * m_max,
+        }
+    }
+
+    /// Grid of the particles of all the entities around the region, with
+    /// cells large enough to find every particle overlapping a particle of
+    /// the distribution in the region.
+    fn region_grid(&self, entities: &mut [Entity]) -> LinkedListGrid {
+        let mut world: Vec<&mut Entity> = entities.iter_mut().collect();
+        let mut h_max: f32 = 0.;
+        for entity in world.iter_mut() {
+            h_max = entity.get_parts_mut_nnps().h.iter().fold(h_max, |max, &h| h.max(max));
+        }
+        let rad = self.distribution.max_radius();
+        let (region, pad) = (self.region, h_max + rad);
+        let domain = Domain::new(
+            region.x_min - pad,
+            region.x_max + pad,
+            region.y_min - pad,
+            region.y_max + pad,
+        );
+        LinkedListGrid::new_in_domain(&mut world, 1., rad, domain)
+    }
+
+    /// Random position in the region where a particle of radius `rad`
+    /// overlaps neither the particles of the entities, found from `grid`,
+    /// nor the particles `placed` since the grid was built, nor the walls.
+    fn free_position(
+        &mut self,
+        rad: f32,
+        entities: &mut [Entity],
+        walls: &[Wall],
+        grid: &LinkedListGrid,
+        placed: &[(f32, f32, f32)],
+    ) -> Option<(f32, f32)> {
+        let region = self.region;
+        let (length, height) = (region.x_max - region.x_min, region.y_max - region.y_min);
+        if 2. * rad > length || 2. * rad > height {
+            return None;
+        }
+        'attempts: for _ in 0..MAX_ATTEMPTS {
+            let x = region.x_min + rad + (length - 2. * rad) * self.rng.gen::<f32>();
+            let y = region.y_min + rad + (height - 2. * rad) * self.rng.gen::<f32>();
+            let overlaps = |x_j: f32, y_j: f32, rad_j: f32| {
+                let (dx, dy) = (x - x_j, y - y_j);
+                dx * dx + dy * dy < (rad + rad_j).powf(2.)
+            };
+            for entity in entities.iter_mut() {
+                let id = entity.id();
+                let ent = entity.get_parts_mut();
+                for cell in get_neighbours_ll([x, y, 0.], grid, &id) {
+                    for &j in cell {
+                        if overlaps(ent.x[j], ent.y[j], ent.rad[j]) {
+                            continue 'attempts;
+                        }
+                    }
+                }
+            }
+            for &(x_j, y_j, rad_j) in placed {
+                if overlaps(x_j, y_j, rad_j) {
+                    continue 'attempts;
+                }
+            }
+            let pos = V3::new(x, y, 0.);
+            for wall in walls {
+                for j in 0..wall.no_elements() {
+                    if let Some((_, distance)) = wall.normal_and_distance(j, pos) {
+                        if distance < rad {
+                            continue 'attempts;
+                        }
+                    }
+                }
+            }
+            return Some((x, y));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FlowRate, Inlet};
+    use cm::Vector3;
+    use contact_search::Domain;
+    use geometry::SizeDistribution;
+    use integrate::SymplecticEuler;
+    use physics::dem::equations::ContactLaw;
+    use physics::dem::DemDiscrete;
+    use physics::material::{Material, MaterialDatabase};
+    use physics::wall::{Wall, WallShape};
+    use solver::{Entity, Equation, Solver};
+
+    fn pouring_solver(rate: FlowRate, tf: f32) -> Solver<SymplecticEuler> {
+        let mut materials = MaterialDatabase::new();
+        let mut glass = Material::new("glass".to_string(), 1000., 1e5, 0.3);
+        glass.en = 0.5;
+        materials.add(glass);
+
+        // an empty entity filled by an inlet above a floor
+        let mut solver = Solver::new(SymplecticEuler, materials, 1e-3, tf);
+        let grains = solver.add_entity(DemDiscrete::new(0, 0, "grains".to_string()));
+        let floor = solver.add_wall(Wall::new(
+            WallShape::Line {
+                point: Vector3::new(0., 0., 0.),
+                normal: Vector3::new(0., 1., 0.),
+            },
+            1,
+            "floor".to_string(),
+        ));
+        let distribution = SizeDistribution::Uniform { min: 0.04, max: 0.06 };
+        let mut inlet = Inlet::new(grains, Domain::new(0., 1., 1., 1.5), distribution, rate, 7);
+        inlet.velocity = Vector3::new(0., -1., 0.);
+        solver.add_inlet(inlet);
+        solver.add_equation(Equation::BodyForce {
+            entity: grains,
+            gx: 0.,
+            gy: -10.,
+        });
+        solver.add_equation(Equation::SelfContact {
+            entity: grains,
+            law: ContactLaw::LinearViscoelastic,
+        });
+        solver.add_equation(Equation::WallContact {
+            entity: grains,
+            wall: floor,
+            law: ContactLaw::LinearViscoelastic,
+        });
+        solver
+    }
+
+    #[test]
+    fn test_inlet_inserts_particles_at_the_flow_rate() {
+        let mut solver = pouring_solver(FlowRate::Particles(100.), 0.5);
+        solver.inlets[0].stop = 0.4;
+        solver.run().unwrap();
+
+        let grains = solver.entities[0].as_discrete().unwrap();
+        assert_eq!(40, solver.inlets[0].inserted);
+        assert_eq!(40, grains.len);
+        assert_eq!(40, grains.tang_history.len());
+        assert!(grains.m.iter().all(|&m| m > 0.));
+        // the first particles have fallen out of the inlet
+        assert!(grains.y[0] < 1.);
+        let mass: f32 = grains.m.iter().sum();
+        assert!((solver.inlets[0].inserted_mass - mass).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_inlet_mass_flow_rate_without_overlaps() {
+        let mut solver = pouring_solver(FlowRate::Mass(200.), 0.);
+        let mut inserted_mass = 0.;
+        for _ in 0..200 {
+            solver.step();
+            // a particle is inserted only once its whole mass is owed
+            let inlet = &solver.inlets[0];
+            assert!(inlet.inserted_mass <= 200. * solver.t + 1e-3);
+            assert!(inlet.inserted_mass >= inserted_mass);
+            inserted_mass = inlet.inserted_mass;
+
+            // the inserted particles don't overlap
+            let grains = solver.entities[0].as_discrete().unwrap();
+            for i in 0..grains.len {
+                for j in 0..i {
+                    let dist = ((grains.x[i] - grains.x[j]).powf(2.)
+                        + (grains.y[i] - grains.y[j]).powf(2.))
+                        .sqrt();
+                    assert!(dist > 0.9 * (grains.rad[i] + grains.rad[j]));
+                }
+            }
+        }
+        // 40 kg owed, short of less than the mass of the next particle, which
+        // is at most 11.3 kg
+        assert!(inserted_mass > 40. - 11.4 && inserted_mass <= 40.);
+        assert_eq!(solver.inlets[0].inserted, solver.entities[0].as_discrete().unwrap().len);
+    }
+
+    #[test]
+    fn test_blocked_inlet_owes_at_most_the_region() {
+        let mut materials = MaterialDatabase::new();
+        materials.add(Material::new("glass".to_string(), 1000., 1e5, 0.3));
+        let distribution = SizeDistribution::Uniform { min: 0.1, max: 0.1 };
+        let region = Domain::new(0., 1., 0., 1.);
+        let mut inlet = Inlet::new(0, region, distribution, FlowRate::Particles(100.), 3);
+
+        // a large particle of another entity blocks the region for a second
+        let mut block = DemDiscrete::new(1, 1, "block".to_string());
+        block.x[0] = 0.5;
+        block.y[0] = 0.5;
+        block.h[0] = 2.;
+        block.rad[0] = 2.;
+        let grains = Entity::Discrete(DemDiscrete::new(0, 0, "grains".to_string()));
+        let mut entities = vec![grains, Entity::Discrete(block)];
+        assert_eq!(0, inlet.insert(&mut entities, &[], &materials, 0., 1.));
+        // a square lattice of 5 by 5 particles
+        assert_eq!(25., inlet.owed);
+
+        // once the region clears, no more than the region holds is inserted
+        entities.pop();
+        let inserted = inlet.insert(&mut entities, &[], &materials, 1., 0.);
+        assert!(inserted > 0 && inserted <= 25);
+        assert_eq!(inserted, entities[0].as_discrete().unwrap().len);
+    }
+}
@@ -1,23 +1,471 @@
-pub trait RK2 {
-    fn initialize(&mut self, dt: f32);
-    fn stage1(&mut self, dt: f32);
-    fn stage2(&mut self, dt: f32);
+// local imports
+use contact_search::Domain;
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+pub struct IntegrateMutParts<'a> {
+    pub x: &'a mut Vec<f32>,
+    pub y: &'a mut Vec<f32>,
+    pub u: &'a mut Vec<f32>,
+    pub v: &'a mut Vec<f32>,
+    pub omega_z: &'a mut Vec<f32>,
+    pub x0: &'a mut Vec<f32>,
+    pub y0: &'a mut Vec<f32>,
+    pub u0: &'a mut Vec<f32>,
+    pub v0: &'a mut Vec<f32>,
+    pub omega_z0: &'a mut Vec<f32>,
+    pub fx: &'a mut Vec<f32>,
+    pub fy: &'a mut Vec<f32>,
+    pub tauz: &'a mut Vec<f32>,
+    pub m_inv: &'a mut Vec<f32>,
+    pub i_inv: &'a mut Vec<f32>,
+}
+
+// trait which has to be implemented by every struct which is advanced in
+// time by an integrator
+pub trait Integrate {
+    fn get_parts_mut_integrate(&mut self) -> IntegrateMutParts;
+}
+
+#[macro_export]
+macro_rules! impl_integrate{
+    ($($t:ty)*) => ($(
+        impl Integrate for $t {
+            fn get_parts_mut_integrate(&mut self) -> IntegrateMutParts {
+                IntegrateMutParts{
+                    x: &mut self.x,
+                    y: &mut self.y,
+                    u: &mut self.u,
+                    v: &mut self.v,
+                    omega_z: &mut self.omega_z,
+                    x0: &mut self.x0,
+                    y0: &mut self.y0,
+                    u0: &mut self.u0,
+                    v0: &mut self.v0,
+                    omega_z0: &mut self.omega_z0,
+                    fx: &mut self.fx,
+                    fy: &mut self.fy,
+                    tauz: &mut self.tauz,
+                    m_inv: &mut self.m_inv,
+                    i_inv: &mut self.i_inv,
+                }
+            }
+        }
+    )*)
+}
+
+/// `out = base + rate * scale * dt` for every particle, where a missing
+/// `scale` is one.
+#[cfg(not(feature = "parallel"))]
+fn advance(out: &mut [f32], base: &[f32], rate: &[f32], scale: Option<&[f32]>, dt: f32) {
+    advance_serial(out, base, rate, scale, dt)
+}
+
+/// Serial loop over the particles, see `advance`.
+#[cfg(any(test, not(feature = "parallel")))]
+fn advance_serial(out: &mut [f32], base: &[f32], rate: &[f32], scale: Option<&[f32]>, dt: f32) {
+    match scale {
+        Some(scale) => for i in 0..out.len() {
+            out[i] = base[i] + rate[i] * scale[i] * dt;
+        },
+        None => for i in 0..out.len() {
+            out[i] = base[i] + rate[i] * dt;
+        },
+    }
+}
+
+#[cfg(feature = "parallel")]
+fn advance(out: &mut [f32], base: &[f32], rate: &[f32], scale: Option<&[f32]>, dt: f32) {
+    let out = out.par_iter_mut().zip(base.par_iter()).zip(rate.par_iter());
+    match scale {
+        Some(scale) => out.zip(scale.par_iter())
+            .for_each(|(((out, base), rate), scale)| *out = base + rate * scale * dt),
+        None => out.for_each(|((out, base), rate)| *out = base + rate * dt),
+    }
+}
+
+/// `out += rate * scale * dt` for every particle, where a missing `scale` is
+/// one.
+#[cfg(not(feature = "parallel"))]
+fn increment(out: &mut [f32], rate: &[f32], scale: Option<&[f32]>, dt: f32) {
+    increment_serial(out, rate, scale, dt)
+}
+
+/// Serial loop over the particles, see `increment`.
+#[cfg(any(test, not(feature = "parallel")))]
+fn increment_serial(out: &mut [f32], rate: &[f32], scale: Option<&[f32]>, dt: f32) {
+    match scale {
+        Some(scale) => for i in 0..out.len() {
+            out[i] += rate[i] * scale[i] * dt;
+        },
+        None => for i in 0..out.len() {
+            out[i] += rate[i] * dt;
+        },
+    }
+}
+
+#[cfg(feature = "parallel")]
+fn increment(out: &mut [f32], rate: &[f32], scale: Option<&[f32]>, dt: f32) {
+    let out = out.par_iter_mut().zip(rate.par_iter());
+    match scale {
+        Some(scale) => out.zip(scale.par_iter())
+            .for_each(|((out, rate), scale)| *out += rate * scale * dt),
+        None => out.for_each(|(out, rate)| *out += rate * dt),
+    }
+}
+
+/// Time integration scheme.
+///
+/// A time step is advanced by calling `initialize`, followed by a force
+/// evaluation and a call to `stage` for every evaluation from `1` to
+/// `force_evaluations()`:
+///
+/// ```text
+/// initialize(dt)
+/// for stage in 1..=force_evaluations() {
+///     compute forces with history stage history_stage(stage)
+///     stage(stage, dt)
+/// }
+/// ```
+pub trait Integrator {
+    /// Number of force evaluations needed to advance a single time step.
+    fn force_evaluations(&self) -> usize;
+
+    /// Stage to be passed to the force functions for the given evaluation.
+    /// The contact and bond histories are incremented at stage 1, and
+    /// committed to the end of the time step at stage 2, which has to be
+    /// the last evaluation of the step.
+    fn history_stage(&self, stage: usize) -> usize {
+        if stage == self.force_evaluations() {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// Time of the particle positions at the force evaluation `stage`, as a
+    /// fraction of the time step.
+    fn stage_time(&self, stage: usize) -> f32;
+
+    /// Prepare the entity for a new time step, before the first force
+    /// evaluation.
+    fn initialize(&self, ent: &mut IntegrateMutParts, dt: f32);
+
+    /// Advance the entity after the force evaluation `stage`.
+    fn stage(&self, stage: usize, ent: &mut IntegrateMutParts, dt: f32);
+
+    /// Whether `initialize` uses the forces of the previous time step, which
+    /// have to be evaluated once before the first time step.
+    fn needs_initial_forces(&self) -> bool {
+        false
+    }
+}
+
+/// Two stage Runge-Kutta (midpoint) scheme. Second order accurate, with two
+/// force evaluations per time step.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RK2;
+
+impl Integrator for RK2 {
+    fn force_evaluations(&self) -> usize {
+        2
+    }
+
+    fn stage_time(&self, stage: usize) -> f32 {
+        if stage == 1 {
+            0.
+        } else {
+            0.5
+        }
+    }
+
+    fn initialize(&self, ent: &mut IntegrateMutParts, _dt: f32) {
+        ent.x0.copy_from_slice(ent.x);
+        ent.y0.copy_from_slice(ent.y);
+        ent.u0.copy_from_slice(ent.u);
+        ent.v0.copy_from_slice(ent.v);
+        ent.omega_z0.copy_from_slice(ent.omega_z);
+    }
+
+    fn stage(&self, stage: usize, ent: &mut IntegrateMutParts, dt: f32) {
+        // the first stage propagates the particles to the half time step,
+        // the second from time t to the next time step
+        let dt = if stage == 1 { dt / 2. } else { dt };
+        // positions first, they are advanced with the current velocities
+        advance(ent.x, ent.x0, ent.u, None, dt);
+        advance(ent.y, ent.y0, ent.v, None, dt);
+        advance(ent.u, ent.u0, ent.fx, Some(ent.m_inv), dt);
+        advance(ent.v, ent.v0, ent.fy, Some(ent.m_inv), dt);
+        advance(ent.omega_z, ent.omega_z0, ent.tauz, Some(ent.i_inv), dt);
+    }
+}
+
+/// Velocity Verlet (kick-drift-kick leapfrog) scheme. Second order accurate
+/// and symplectic, with a single force evaluation per time step.
+///
+/// The forces of the previous time step are reused for the first half kick,
+/// so the forces have to be left untouched between two time steps. Evaluate
+/// the forces once before the first time step, otherwise the first half
+/// kick is skipped, see `needs_initial_forces`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct VelocityVerlet;
+
+impl Integrator for VelocityVerlet {
+    fn force_evaluations(&self) -> usize {
+        1
+    }
+
+    fn stage_time(&self, _stage: usize) -> f32 {
+        1.
+    }
+
+    fn initialize(&self, ent: &mut IntegrateMutParts, dt: f32) {
+        let dtb2 = dt / 2.;
+        // half kick with the forces at time t
+        increment(ent.u, ent.fx, Some(ent.m_inv), dtb2);
+        increment(ent.v, ent.fy, Some(ent.m_inv), dtb2);
+        increment(ent.omega_z, ent.tauz, Some(ent.i_inv), dtb2);
+
+        // drift to the next time step
+        increment(ent.x, ent.u, None, dt);
+        increment(ent.y, ent.v, None, dt);
+    }
+
+    fn stage(&self, _stage: usize, ent: &mut IntegrateMutParts, dt: f32) {
+        let dtb2 = dt / 2.;
+        // half kick with the forces at time t + dt
+        increment(ent.u, ent.fx, Some(ent.m_inv), dtb2);
+        increment(ent.v, ent.fy, Some(ent.m_inv), dtb2);
+        increment(ent.omega_z, ent.tauz, Some(ent.i_inv), dtb2);
+    }
+
+    fn needs_initial_forces(&self) -> bool {
+        true
+    }
 }
 
-pub fn integrate_initialize<T: RK2>(world: &mut Vec<&mut T>, dt: f32) {
+/// Symplectic (semi-implicit) Euler scheme. First order accurate and
+/// symplectic, with a single force evaluation per time step. The cheapest of
+/// the schemes.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SymplecticEuler;
+
+impl Integrator for SymplecticEuler {
+    fn force_evaluations(&self) -> usize {
+        1
+    }
+
+    fn stage_time(&self, _stage: usize) -> f32 {
+        0.
+    }
+
+    fn initialize(&self, _ent: &mut IntegrateMutParts, _dt: f32) {}
+
+    fn stage(&self, _stage: usize, ent: &mut IntegrateMutParts, dt: f32) {
+        // update the velocities first and move the particles with the new
+        // velocities
+        increment(ent.u, ent.fx, Some(ent.m_inv), dt);
+        increment(ent.v, ent.fy, Some(ent.m_inv), dt);
+        increment(ent.omega_z, ent.tauz, Some(ent.i_inv), dt);
+
+        increment(ent.x, ent.u, None, dt);
+        increment(ent.y, ent.v, None, dt);
+    }
+}
+
+pub fn initialize_step<I: Integrator, T: Integrate + ?Sized>(
+    integrator: &I,
+    world: &mut Vec<&mut T>,
+    dt: f32,
+) {
     for entity in world {
-        entity.initialize(dt)
+        integrator.initialize(&mut entity.get_parts_mut_integrate(), dt)
     }
 }
 
-pub fn integrate_stage1<T: RK2>(world: &mut Vec<&mut T>, dt: f32) {
+pub fn advance_stage<I: Integrator, T: Integrate + ?Sized>(
+    integrator: &I,
+    stage: usize,
+    world: &mut Vec<&mut T>,
+    dt: f32,
+) {
     for entity in world {
-        entity.stage1(dt)
+        integrator.stage(stage, &mut entity.get_parts_mut_integrate(), dt)
     }
 }
 
-pub fn integrate_stage2<T: RK2>(world: &mut Vec<&mut T>, dt: f32) {
+/// Move the particles which have left a periodic domain through one side
+/// back in through the opposite side, with the particle positions at time
+/// `t`. Particles crossing the sides of a sheared domain also take the
+/// velocity of their image, see `Domain::wrap_sheared`.
+///
+/// The positions and velocities at the beginning of the time step, a time
+/// `elapsed` before `t`, are moved onto the same image, so that the
+/// remaining stages of the time step are not affected.
+pub fn wrap_positions<T: Integrate + ?Sized>(
+    world: &mut Vec<&mut T>,
+    domain: &Domain,
+    t: f32,
+    elapsed: f32,
+) {
+    if !domain.periodic_x && !domain.periodic_y {
+        return;
+    }
     for entity in world {
-        entity.stage2(dt)
+        let ent = entity.get_parts_mut_integrate();
+        for i in 0..ent.x.len() {
+            let (x, y, du) = domain.wrap_sheared(ent.x[i], ent.y[i], t);
+            // the image was displaced less at the beginning of the step
+            ent.x0[i] += x - ent.x[i] - du * elapsed;
+            ent.y0[i] += y - ent.y[i];
+            ent.u[i] += du;
+            ent.u0[i] += du;
+            ent.x[i] = x;
+            ent.y[i] = y;
+        }
+    }
+}
+
+pub fn integrate_initialize<T: Integrate + ?Sized>(world: &mut Vec<&mut T>, dt: f32) {
+    initialize_step(&RK2, world, dt)
+}
+
+pub fn integrate_stage1<T: Integrate + ?Sized>(world: &mut Vec<&mut T>, dt: f32) {
+    advance_stage(&RK2, 1, world, dt)
+}
+
+pub fn integrate_stage2<T: Integrate + ?Sized>(world: &mut Vec<&mut T>, dt: f32) {
+    advance_stage(&RK2, 2, world, dt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{advance_stage, initialize_step, Integrate, IntegrateMutParts, Integrator,
+                SymplecticEuler, VelocityVerlet, RK2};
+
+    /// Single particle on a linear spring of unit stiffness and unit mass,
+    /// attached to the origin.
+    struct Oscillator {
+        x: Vec<f32>,
+        y: Vec<f32>,
+        u: Vec<f32>,
+        v: Vec<f32>,
+        omega_z: Vec<f32>,
+        x0: Vec<f32>,
+        y0: Vec<f32>,
+        u0: Vec<f32>,
+        v0: Vec<f32>,
+        omega_z0: Vec<f32>,
+        fx: Vec<f32>,
+        fy: Vec<f32>,
+        tauz: Vec<f32>,
+        m_inv: Vec<f32>,
+        i_inv: Vec<f32>,
+    }
+
+    impl_integrate![Oscillator];
+
+    impl Oscillator {
+        fn new() -> Self {
+            Oscillator {
+                x: vec![1.],
+                y: vec![0.],
+                u: vec![0.],
+                v: vec![0.],
+                omega_z: vec![0.],
+                x0: vec![0.],
+                y0: vec![0.],
+                u0: vec![0.],
+                v0: vec![0.],
+                omega_z0: vec![0.],
+                fx: vec![0.],
+                fy: vec![0.],
+                tauz: vec![0.],
+                m_inv: vec![1.],
+                i_inv: vec![1.],
+            }
+        }
+
+        fn compute_force(&mut self) {
+            self.fx[0] = -self.x[0];
+        }
+
+        fn energy(&self) -> f32 {
+            0.5 * (self.u[0] * self.u[0] + self.x[0] * self.x[0])
+        }
+    }
+
+    /// Integrate the oscillator over ten periods and return the error in
+    /// the position and the largest relative error in the energy.
+    fn run<I: Integrator>(integrator: &I, dt: f32) -> (f32, f32) {
+        let mut osc = Oscillator::new();
+        osc.compute_force();
+        let steps = (20. * ::std::f32::consts::PI / dt).round() as usize;
+        let mut energy_error: f32 = 0.;
+        for _ in 0..steps {
+            initialize_step(integrator, &mut vec![&mut osc], dt);
+            for stage in 1..integrator.force_evaluations() + 1 {
+                osc.compute_force();
+                advance_stage(integrator, stage, &mut vec![&mut osc], dt);
+            }
+            energy_error = energy_error.max((osc.energy() - 0.5).abs() / 0.5);
+        }
+        ((osc.x[0] - 1.).abs(), energy_error)
+    }
+
+    #[test]
+    fn test_force_evaluations_and_history_stages() {
+        assert_eq!(2, RK2.force_evaluations());
+        assert_eq!((1, 2), (RK2.history_stage(1), RK2.history_stage(2)));
+        // single evaluation schemes commit the history at every evaluation
+        assert_eq!(1, VelocityVerlet.force_evaluations());
+        assert_eq!(2, VelocityVerlet.history_stage(1));
+        assert_eq!(1, SymplecticEuler.force_evaluations());
+        assert_eq!(2, SymplecticEuler.history_stage(1));
+        // only velocity Verlet reuses the forces of the previous step
+        assert!(VelocityVerlet.needs_initial_forces());
+        assert!(!RK2.needs_initial_forces() && !SymplecticEuler.needs_initial_forces());
+    }
+
+    #[test]
+    fn test_integrators_on_harmonic_oscillator() {
+        let dt = 1e-2;
+        let (rk2_error, _) = run(&RK2, dt);
+        let (verlet_error, verlet_energy) = run(&VelocityVerlet, dt);
+        let (euler_error, euler_energy) = run(&SymplecticEuler, dt);
+
+        // second order schemes
+        assert!(rk2_error < 1e-2);
+        assert!(verlet_error < 1e-2);
+        // the symplectic schemes keep the energy bounded
+        assert!(verlet_energy < 1e-3);
+        assert!(euler_energy < 2e-2);
+        assert!(euler_error < 0.1);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_parallel_advance_matches_serial() {
+        use super::{advance, advance_serial, increment, increment_serial};
+
+        let n = 10000;
+        let base: Vec<f32> = (0..n).map(|i| 0.1 * (i % 17) as f32 - 0.5).collect();
+        let rate: Vec<f32> = (0..n).map(|i| 3. * ((i * 7) % 11) as f32 - 12.).collect();
+        let scale: Vec<f32> = (0..n).map(|i| 1. / (1 + i % 5) as f32).collect();
+        let dt = 1e-3;
+
+        for scale in [Some(&scale[..]), None] {
+            let mut serial = vec![0.; n];
+            let mut parallel = vec![0.; n];
+            advance_serial(&mut serial, &base, &rate, scale, dt);
+            advance(&mut parallel, &base, &rate, scale, dt);
+            assert_eq!(serial, parallel);
+
+            increment_serial(&mut serial, &rate, scale, 0.5 * dt);
+            increment(&mut parallel, &rate, scale, 0.5 * dt);
+            assert_eq!(serial, parallel);
+            assert!(serial.iter().zip(&base).any(|(out, base)| out != base));
+        }
     }
 }
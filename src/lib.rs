@@ -10,15 +10,28 @@ extern crate cgmath as cm;
 #[macro_use]
 extern crate ndarray;
 
+extern crate rand;
+
+#[cfg(feature = "parallel")]
+extern crate rayon;
+
 
 // local modules
 #[macro_use]
 pub mod contact_search;
+pub mod drum;
 pub mod geometry;
+pub mod inlet;
+#[macro_use]
 pub mod integrate;
 pub mod math;
+pub mod motion;
+pub mod outlet;
+pub mod packing;
 pub mod save_data;
+pub mod solver;
 pub mod physics;
+pub mod postprocess;
 
 use contact_search::{NNPS, NNPSMutParts};
 
@@ -0,0 +1,140 @@
+// external crate imports
+use cm::{Vector3 as V3, Zero};
+use std::f32::consts::PI;
+
+/// Rigid displacement of a boundary from its initial configuration at some
+/// time, with its velocity at that time.
+///
+/// The boundary is rotated by `angle` about `pivot` and then translated by
+/// `displacement`. It rotates with angular velocity `omega` about the
+/// displaced pivot, which moves with `velocity`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RigidMotion {
+    /// pivot of the rotation, in the initial configuration
+    pub pivot: V3<f32>,
+    pub displacement: V3<f32>,
+    pub angle: f32,
+    pub velocity: V3<f32>,
+    pub omega: f32,
+}
+
+impl RigidMotion {
+    /// The boundary at rest in its initial configuration.
+    pub fn rest() -> Self {
+        RigidMotion {
+            pivot: V3::zero(),
+            displacement: V3::zero(),
+            angle: 0.,
+            velocity: V3::zero(),
+            omega: 0.,
+        }
+    }
+
+    /// Rotate the direction `d` with the boundary.
+    pub fn rotate(&self, d: V3<f32>) -> V3<f32> {
+        let (sin, cos) = self.angle.sin_cos();
+        V3::new(cos * d.x - sin * d.y, sin * d.x + cos * d.y, 0.)
+    }
+
+    /// Current position of the point of the boundary at `p0` in the initial
+    /// configuration.
+    pub fn position(&self, p0: V3<f32>) -> V3<f32> {
+        self.pivot + self.displacement + self.rotate(p0 - self.pivot)
+    }
+
+    /// Velocity of the point of the boundary at `p` in the current
+    /// configuration.
+    pub fn velocity_at(&self, p: V3<f32>) -> V3<f32> {
+        let r = p - self.pivot - self.displacement;
+        self.velocity + self.omega * V3::new(-r.y, r.x, 0.)
+    }
+}
+
+/// Prescribed motion of a wall or a boundary entity, which is not advanced
+/// in time by the integrator.
+pub enum Motion {
+    /// Translation with a constant velocity.
+    Translation { velocity: V3<f32> },
+    /// Rotation with a constant angular velocity about a pivot, such as a
+    /// rotating drum.
+    Rotation { pivot: V3<f32>, omega: f32 },
+    /// Vibration with displacement `amplitude * sin(2 pi frequency t)`, such
+    /// as a vibrating bed.
+    Sinusoidal { amplitude: V3<f32>, frequency: f32 },
+    /// Any rigid motion given as a function of time.
+    Prescribed(Box<dyn Fn(f32) -> RigidMotion>),
+}
+
+impl Motion {
+    /// Rigid displacement and velocity at time `t`.
+    pub fn at(&self, t: f32) -> RigidMotion {
+        let rest = RigidMotion::rest();
+        match *self {
+            Motion::Translation { velocity } => RigidMotion {
+                displacement: velocity * t,
+                velocity,
+                ..rest
+            },
+            Motion::Rotation { pivot, omega } => RigidMotion {
+                pivot,
+                angle: omega * t,
+                omega,
+                ..rest
+            },
+            Motion::Sinusoidal {
+                amplitude,
+                frequency,
+            } => {
+                let w = 2. * PI * frequency;
+                RigidMotion {
+                    displacement: amplitude * (w * t).sin(),
+                    velocity: amplitude * w * (w * t).cos(),
+                    ..rest
+                }
+            }
+            Motion::Prescribed(ref motion) => motion(t),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Motion, RigidMotion};
+    use cm::{InnerSpace, Vector3};
+    use std::f32::consts::PI;
+
+    #[test]
+    fn test_rotation_about_pivot() {
+        let motion = Motion::Rotation {
+            pivot: Vector3::new(1., 0., 0.),
+            omega: 2.,
+        };
+        // a quarter turn
+        let kinematics = motion.at(PI / 4.);
+        let p = kinematics.position(Vector3::new(2., 0., 0.));
+        assert!((p - Vector3::new(1., 1., 0.)).magnitude() < 1e-6);
+        let v = kinematics.velocity_at(p);
+        assert!((v - Vector3::new(-2., 0., 0.)).magnitude() < 1e-6);
+    }
+
+    #[test]
+    fn test_sinusoidal_and_prescribed_motion() {
+        let motion = Motion::Sinusoidal {
+            amplitude: Vector3::new(0., 0.1, 0.),
+            frequency: 2.,
+        };
+        let kinematics = motion.at(0.125);
+        assert!((kinematics.displacement.y - 0.1).abs() < 1e-6);
+        assert!(kinematics.velocity.y.abs() < 1e-5);
+        assert_eq!(motion.at(0.).position(Vector3::new(1., 1., 0.)), Vector3::new(1., 1., 0.));
+
+        let motion = Motion::Prescribed(Box::new(|t| RigidMotion {
+            displacement: Vector3::new(t * t, 0., 0.),
+            velocity: Vector3::new(2. * t, 0., 0.),
+            ..RigidMotion::rest()
+        }));
+        let kinematics = motion.at(2.);
+        assert_eq!(Vector3::new(5., 1., 0.), kinematics.position(Vector3::new(1., 1., 0.)));
+        assert_eq!(Vector3::new(4., 0., 0.), kinematics.velocity_at(Vector3::new(3., 3., 0.)));
+    }
+}
@@ -0,0 +1,232 @@
+// local imports
+use contact_search::Domain;
+use physics::dem::DemDiscreteSrcTrait;
+use solver::{remove_particles, Entity};
+
+// external crate imports
+use cm::{InnerSpace, Vector3 as V3};
+
+/// Region through which particles leave the simulation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutletRegion {
+    /// particles whose centre is in the rectangle
+    Rectangle(Domain),
+    /// particles whose centre is behind the line through `point`, on the side
+    /// opposite to `normal`, such as below the plane under a hopper
+    HalfPlane { point: V3<f32>, normal: V3<f32> },
+}
+
+impl OutletRegion {
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        match *self {
+            OutletRegion::Rectangle(ref domain) => domain.contains(x, y),
+            OutletRegion::HalfPlane { point, normal } => {
+                (V3::new(x, y, 0.) - point).dot(normal) < 0.
+            }
+        }
+    }
+}
+
+/// Region removing the particles of an entity which reach it, such as the
+/// orifice of a hopper.
+///
+/// The particles are removed at the beginning of every time step, and the
+/// contact histories of all the entities are renumbered. The discharged mass
+/// is recorded at every time step in which particles leave.
+pub struct Outlet {
+    /// index of the entity in the solver
+    pub entity: usize,
+    pub region: OutletRegion,
+    /// number and total mass of the particles discharged so far
+    pub discharged: usize,
+    pub discharged_mass: f32,
+    /// time and total discharged mass, after every removal
+    pub history: Vec<(f32, f32)>,
+}
+
+impl Outlet {
+    pub fn new(entity: usize, region: OutletRegion) -> Self {
+        Outlet {
+            entity,
+            region,
+            discharged: 0,
+            discharged_mass: 0.,
+            history: vec![],
+        }
+    }
+
+    /// Remove the particles of the entity in the region at time `t`, and
+    /// return the number of particles removed.
+    pub fn remove(&mut self, entities: &mut [Entity], t: f32) -> usize {
+        let (remove, mass) = {
+            let ent = entities[self.entity].get_parts_mut();
+            let mut remove = vec![];
+            let mut mass = 0.;
+            for i in 0..ent.x.len() {
+                if self.region.contains(ent.x[i], ent.y[i]) {
+                    remove.push(i);
+                    mass += ent.m[i];
+                }
+            }
+            (remove, mass)
+        };
+        if remove.is_empty() {
+            return 0;
+        }
+
+        remove_particles(entities, self.entity, &remove);
+        self.discharged += remove.len();
+        self.discharged_mass += mass;
+        self.history.push((t, self.discharged_mass));
+        remove.len()
+    }
+
+    /// Mean mass flow rate between the times `t0` and `t1`, from the
+    /// recorded history.
+    pub fn mass_flow_rate(&self, t0: f32, t1: f32) -> f32 {
+        let mass_at = |t: f32| {
+            self.history
+                .iter()
+                .take_while(|&&(ti, _)| ti <= t)
+                .last()
+                .map_or(0., |&(_, mass)| mass)
+        };
+        (mass_at(t1) - mass_at(t0)) / (t1 - t0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Outlet, OutletRegion};
+    use cm::Vector3;
+    use contact_search::Domain;
+    use integrate::SymplecticEuler;
+    use physics::dem::equations::ContactLaw;
+    use physics::dem::DemDiscrete;
+    use physics::material::{Material, MaterialDatabase};
+    use solver::{Equation, Solver};
+
+    fn column(n: usize) -> DemDiscrete {
+        // a column of touching particles
+        let mut grains = DemDiscrete::new(n, 0, "grains".to_string());
+        for i in 0..n {
+            grains.y[i] = 0.095 * i as f32;
+            grains.h[i] = 0.05;
+            grains.rad[i] = 0.05;
+            grains.m[i] = 1. + i as f32;
+            grains.m_inv[i] = 1. / grains.m[i];
+            grains.inertia[i] = 0.5 * grains.m[i] * 0.05_f32.powf(2.);
+            grains.i_inv[i] = 1. / grains.inertia[i];
+        }
+        grains
+    }
+
+    #[test]
+    fn test_outlet_regions() {
+        let plane = OutletRegion::HalfPlane {
+            point: Vector3::new(0., -1., 0.),
+            normal: Vector3::new(0., 1., 0.),
+        };
+        assert!(plane.contains(5., -1.5));
+        assert!(!plane.contains(5., -0.5));
+
+        let orifice = OutletRegion::Rectangle(Domain::new(-0.1, 0.1, -1., -0.8));
+        assert!(orifice.contains(0., -0.9));
+        assert!(!orifice.contains(0.2, -0.9));
+    }
+
+    #[test]
+    fn test_outlet_discharges_falling_particles() {
+        // frictional contacts, so the particles have tangential histories
+        let mut materials = MaterialDatabase::new();
+        let mut glass = Material::new("glass".to_string(), 1000., 1e5, 0.3);
+        glass.en = 0.5;
+        glass.mu = 0.5;
+        materials.add(glass);
+
+        let mut solver = Solver::new(SymplecticEuler, materials, 1e-3, 0.);
+        let grains = solver.add_entity(column(10));
+        solver.add_equation(Equation::BodyForce {
+            entity: grains,
+            gx: 0.,
+            gy: -10.,
+        });
+        solver.add_equation(Equation::SelfContact {
+            entity: grains,
+            law: ContactLaw::LinearViscoelastic,
+        });
+
+        // a heavy particle pushed down harder than the column keeps it
+        // compressed, so the contacts last while it is discharged
+        let mut piston = column(1);
+        piston.id = 1;
+        piston.y[0] = 0.95;
+        piston.m[0] = 20.;
+        piston.m_inv[0] = 1. / 20.;
+        let piston = solver.add_entity(piston);
+        solver.add_equation(Equation::BodyForce {
+            entity: piston,
+            gx: 0.,
+            gy: -30.,
+        });
+        for &(dst, src) in &[(grains, piston), (piston, grains)] {
+            solver.add_equation(Equation::Contact {
+                dst,
+                src,
+                law: ContactLaw::LinearViscoelastic,
+            });
+        }
+        let outlet = solver.add_outlet(Outlet::new(
+            grains,
+            OutletRegion::HalfPlane {
+                point: Vector3::new(0., -0.3, 0.),
+                normal: Vector3::new(0., 1., 0.),
+            },
+        ));
+
+        // the column falls through the plane, the lowest particles first
+        let (mut last, mut remapped) = (10, 0);
+        while solver.t < 0.8 {
+            solver.step();
+            let grains = solver.entities[grains].as_discrete().unwrap();
+            assert_eq!(10, grains.len + solver.outlets[outlet].discharged);
+            assert!(grains.len <= last);
+            last = grains.len;
+
+            // the histories refer only to the remaining particles, which
+            // touch their neighbours in the column, one unit of mass apart
+            assert_eq!(grains.len, grains.tang_history.len());
+            for (i, history) in grains.tang_history.iter().enumerate() {
+                if let Some(partners) = history.get(&grains.id) {
+                    for &j in partners.keys() {
+                        assert!(j < grains.len);
+                        assert_eq!(1., (grains.m[i] - grains.m[j]).abs());
+                        if solver.outlets[outlet].discharged > 0 {
+                            remapped += 1;
+                        }
+                    }
+                }
+            }
+            // and so do those of the piston, which touches the heaviest one
+            let piston = solver.entities[piston].as_discrete().unwrap();
+            if let Some(partners) = piston.tang_history[0].get(&grains.id) {
+                assert!(partners.keys().all(|&j| grains.m[j] == 10.));
+            }
+            // the lightest particles are discharged first
+            if grains.len > 0 {
+                assert_eq!(11 - grains.len, grains.m[0] as usize);
+            }
+        }
+
+        assert!(remapped > 0);
+
+        let outlet = &solver.outlets[outlet];
+        assert!(outlet.discharged > 0);
+        let n = outlet.discharged as f32;
+        assert!((outlet.discharged_mass - n * (n + 1.) / 2.).abs() < 1e-3);
+        assert_eq!(outlet.discharged_mass, outlet.history.last().unwrap().1);
+        assert!(outlet.history.windows(2).all(|w| w[0].0 < w[1].0 && w[0].1 < w[1].1));
+        let rate = outlet.mass_flow_rate(0., solver.t);
+        assert!((rate - outlet.discharged_mass / solver.t).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,274 @@
+// local imports
+use geometry::{random_packing_2d, SizeDistribution};
+use integrate::SymplecticEuler;
+use physics::dem::equations::{set_disk_inertia_dem, ContactLaw};
+use physics::dem::DemDiscrete;
+use physics::material::{Material, MaterialDatabase};
+use physics::timestep::estimate_time_step;
+use physics::wall::{Wall, WallShape};
+use solver::{Equation, Solver};
+
+// external crate imports
+use cm::Vector3;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::{self, BufReader};
+
+/// Positions and radii of the particles of a packing, which can be saved,
+/// loaded and used to initialise an entity.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Packing {
+    pub x: Vec<f32>,
+    pub y: Vec<f32>,
+    pub rad: Vec<f32>,
+}
+
+impl Packing {
+    /// Write the packing as lines of x, y and radius.
+    pub fn save(&self, file_name: &str) -> io::Result<()> {
+        let mut file = File::create(file_name)?;
+        for i in 0..self.x.len() {
+            writeln!(&mut file, "{} {} {}", self.x[i], self.y[i], self.rad[i])?;
+        }
+        Ok(())
+    }
+
+    /// Read a packing written by `save`.
+    pub fn load(file_name: &str) -> io::Result<Self> {
+        let file = BufReader::new(File::open(file_name)?);
+        let mut packing = Packing {
+            x: vec![],
+            y: vec![],
+            rad: vec![],
+        };
+        for line in file.lines() {
+            let line = line?;
+            let values = line
+                .split_whitespace()
+                .map(|value| value.parse::<f32>())
+                .collect::<Result<Vec<f32>, _>>()
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            match values.len() {
+                0 => continue,
+                3 => {
+                    packing.x.push(values[0]);
+                    packing.y.push(values[1]);
+                    packing.rad.push(values[2]);
+                }
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("expected x, y and radius, found {:?}", line),
+                    ))
+                }
+            }
+        }
+        Ok(packing)
+    }
+
+    /// Entity made of disks at the positions of the packing, with their mass
+    /// and moment of inertia computed from the density of the material
+    /// `material_id` of `materials`.
+    pub fn to_dem_discrete(
+        &self,
+        id: usize,
+        name: String,
+        materials: &MaterialDatabase,
+        material_id: usize,
+    ) -> DemDiscrete {
+        let material = &materials.materials[material_id];
+        let mut entity = DemDiscrete::new(self.x.len(), id, name);
+        for i in 0..self.x.len() {
+            entity.x[i] = self.x[i];
+            entity.y[i] = self.y[i];
+            entity.h[i] = self.rad[i];
+            entity.rad[i] = self.rad[i];
+            entity.m[i] = material.disk_mass(self.rad[i]);
+            entity.m_inv[i] = 1. / entity.m[i];
+        }
+        set_disk_inertia_dem(&mut entity);
+        entity.material_id = material_id;
+        entity
+    }
+}
+
+/// Gravity deposition of a random cloud of particles in a container with a
+/// floor at y = 0 and sides at x = 0 and x = `length`.
+///
+/// The cloud is generated by `random_packing_2d` in the region of the given
+/// `length` and `height` above the floor, and settles under gravity with the
+/// contact law of the simulations until its kinetic energy drops below
+/// `tolerance` times the potential energy of the particles lifted by their
+/// radius, $\sum m g R$.
+pub struct Deposition {
+    pub length: f32,
+    pub height: f32,
+    pub distribution: SizeDistribution,
+    pub no_particles: usize,
+    /// seed of the random cloud
+    pub seed: u64,
+    /// material of the particles and the container
+    pub material: Material,
+    pub law: ContactLaw,
+    pub gravity: f32,
+    /// fraction of the critical time step used as the time step
+    pub safety_factor: f32,
+    pub tolerance: f32,
+    /// the deposition stops at this time even if the particles still move
+    pub max_time: f32,
+}
+
+impl Deposition {
+    pub fn new(
+        length: f32,
+        height: f32,
+        distribution: SizeDistribution,
+        no_particles: usize,
+        material: Material,
+    ) -> Self {
+        Deposition {
+            length,
+            height,
+            distribution,
+            no_particles,
+            seed: 0,
+            material,
+            law: ContactLaw::LinearViscoelastic,
+            gravity: 9.81,
+            safety_factor: 0.2,
+            tolerance: 1e-4,
+            max_time: 10.,
+        }
+    }
+
+    /// Deposit the particles and return the relaxed packing.
+    pub fn run(&self) -> Packing {
+        let (x, y, rad) = random_packing_2d(
+            self.length,
+            self.height,
+            &self.distribution,
+            self.no_particles,
+            self.seed,
+        );
+        let mut materials = MaterialDatabase::new();
+        let material_id = materials.add(self.material.clone());
+        let cloud = Packing { x, y, rad };
+        let mut grains = cloud.to_dem_discrete(0, "grains".to_string(), &materials, material_id);
+
+        let dt = estimate_time_step(&mut vec![&mut grains], &materials)
+            .unwrap()
+            .stable(self.safety_factor);
+        let mut solver = Solver::new(SymplecticEuler, materials, dt, self.max_time);
+        let grains = solver.add_entity(grains);
+        solver.add_equation(Equation::BodyForce {
+            entity: grains,
+            gx: 0.,
+            gy: -self.gravity,
+        });
+        solver.add_equation(Equation::SelfContact {
+            entity: grains,
+            law: self.law,
+        });
+        let container = [
+            (Vector3::new(0., 0., 0.), Vector3::new(0., 1., 0.)),
+            (Vector3::new(0., 0., 0.), Vector3::new(1., 0., 0.)),
+            (Vector3::new(self.length, 0., 0.), Vector3::new(-1., 0., 0.)),
+        ];
+        for (k, &(point, normal)) in container.iter().enumerate() {
+            let mut wall = Wall::new(
+                WallShape::Line { point, normal },
+                k + 1,
+                format!("container_{}", k),
+            );
+            wall.material_id = material_id;
+            let wall = solver.add_wall(wall);
+            solver.add_equation(Equation::WallContact {
+                entity: grains,
+                wall,
+                law: self.law,
+            });
+        }
+
+        // the particles are at rest until they fall, so the kinetic energy
+        // is only checked once the top of the cloud can reach the floor
+        let fall_time = (2. * self.height / self.gravity).sqrt();
+        while solver.t < self.max_time {
+            solver.step();
+            if solver.t < fall_time || solver.time_step_number % 100 != 0 {
+                continue;
+            }
+            let grains = solver.entities[grains].as_discrete().unwrap();
+            let (mut kinetic, mut potential) = (0., 0.);
+            for i in 0..grains.len {
+                kinetic += 0.5 * grains.m[i] * (grains.u[i].powf(2.) + grains.v[i].powf(2.))
+                    + 0.5 * grains.inertia[i] * grains.omega_z[i].powf(2.);
+                potential += grains.m[i] * self.gravity * grains.rad[i];
+            }
+            if kinetic < self.tolerance * potential {
+                break;
+            }
+        }
+        if solver.t >= self.max_time {
+            eprintln!(
+                "warning: the particles have not settled at the end of the deposition, t = {}",
+                solver.t
+            );
+        }
+
+        let grains = solver.entities[grains].as_discrete().unwrap();
+        Packing {
+            x: grains.x.clone(),
+            y: grains.y.clone(),
+            rad: grains.rad.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Deposition, Packing};
+    use geometry::SizeDistribution;
+    use physics::material::{Material, MaterialDatabase};
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn test_deposited_packing_settles_on_the_floor() {
+        let mut glass = Material::new("glass".to_string(), 1000., 1e6, 0.3);
+        glass.en = 0.3;
+        glass.mu = 0.5;
+        let distribution = SizeDistribution::Uniform { min: 0.04, max: 0.06 };
+        let deposition = Deposition::new(1., 1.5, distribution, 60, glass.clone());
+        let packing = deposition.run();
+        assert_eq!(60, packing.x.len());
+
+        // the particles are in the container, in a bed lower than the cloud
+        let mut top = 0_f32;
+        for i in 0..packing.x.len() {
+            assert!(packing.x[i] > 0.9 * packing.rad[i]);
+            assert!(packing.x[i] < 1. - 0.9 * packing.rad[i]);
+            assert!(packing.y[i] > 0.9 * packing.rad[i]);
+            top = top.max(packing.y[i] + packing.rad[i]);
+        }
+        assert!(top < 0.8);
+
+        // the same deposition gives the same packing
+        assert_eq!(packing, deposition.run());
+
+        // saved and loaded to initialise an entity
+        let file_name = env::temp_dir().join("dem2d_test_packing.txt");
+        let file_name = file_name.to_str().unwrap();
+        packing.save(file_name).unwrap();
+        let loaded = Packing::load(file_name).unwrap();
+        fs::remove_file(file_name).unwrap();
+        assert_eq!(packing, loaded);
+
+        let mut materials = MaterialDatabase::new();
+        let glass = materials.add(glass);
+        let grains = loaded.to_dem_discrete(3, "grains".to_string(), &materials, glass);
+        assert_eq!((3, glass), (grains.id, grains.material_id));
+        assert_eq!(packing.x, grains.x);
+        assert!((grains.m[0] - materials.materials[glass].disk_mass(packing.rad[0])).abs() < 1e-6);
+        assert!(grains.i_inv[0] > 0.);
+    }
+}
@@ -1,10 +1,28 @@
 // local imports
-use super::{Bond, DemBondedDstTrait};
+use super::{Bond, BondProperties, BreakageEvent, DemBonded, DemBondedDstTrait};
+use physics::dem::equations::{contact_force_dem, ContactAccumulator, ContactContext, ContactLaw,
+                              ContactParticles};
+use physics::material::MaterialDatabase;
 use contact_search::{get_neighbours_ll, LinkedListGrid};
-use math::distance;
+use math::{distance, unit_vector_from_point};
 
 // external crate imports
-use cm::{Vector3 as V3, Zero};
+use cm::{InnerSpace, Vector3 as V3};
+
+pub fn make_forces_zero_bonded_dem(entity: &mut DemBonded) {
+    for i in 0..entity.len {
+        entity.fx[i] = 0.;
+        entity.fy[i] = 0.;
+        entity.tauz[i] = 0.;
+    }
+}
+
+pub fn body_force_bonded_dem(entity: &mut DemBonded, gx: f32, gy: f32) {
+    for i in 0..entity.len {
+        entity.fx[i] += entity.m[i] * gx;
+        entity.fy[i] += entity.m[i] * gy;
+    }
+}
 
 /// Setup the DemBonded structure. Given the particle array, create the bonds of
 /// each particle with it neighbours.  Here scale is multiplied by each
@@ -48,14 +66,32 @@ pub fn setup_bonded_structure<T: DemBondedDstTrait>(dest: &mut T, scale: f32) {
     }
 }
 
-fn internal_force_bonded_dem<T: DemBondedDstTrait>(
+/// Forces and moments due to the parallel bonds of the bonded particle model
+/// of Potyondy and Cundall (2004).
+///
+/// The forces and the moment of each bond are incremented from the relative
+/// motion of the bonded particles over the time step,
+///
+/// $\Delta F_n = k_n A \Delta u_n$, $\Delta F_s = k_s A \Delta u_s$,
+/// $\Delta M = k_b I \Delta \theta$,
+///
+/// and are added to the force and torque of the particle.
+///
+/// Same as the tangential history of the contacts, `bonds` hold the bonds at
+/// the current stage and `bonds0` at time t. The force is computed from the
+/// bond at the current stage, and the bond is incremented for the next stage.
+///
+/// In a periodic domain particle i is bonded to the nearest image of j given
+/// by `grid`, so that a bond stays intact when it crosses a side.
+pub fn internal_force_bonded_dem<T: DemBondedDstTrait>(
     dest: &mut T,
-    kn: f32,
-    mu: f32,
+    props: &BondProperties,
+    dt: f32,
+    stage: usize,
+    grid: &LinkedListGrid,
 ) {
     let dst = dest.get_parts_mut();
 
-    // Select the neighbours function according to the dimention
     for i in 0..*dst.len {
         // position of particle i
         let pos_i = V3::new(dst.x[i], dst.y[i], 0.);
@@ -65,8 +101,189 @@ fn internal_force_bonded_dem<T: DemBondedDstTrait>(
         let ang_vel_i = V3::new(0., 0., dst.omega_z[i]);
 
         // iterate over the bonds of particle i
-        for (j, bond) in &mut dst.bonds[i] {
-            println!("{}, {:?}", j, bond);
+        for (&j, bond) in dst.bonds[i].iter_mut() {
+            // position and velocity of the nearest image of particle j
+            let pos_j = image_position(grid, pos_i, dst.x[j], dst.y[j]);
+            let du_j = grid.image_velocity(dst.y[i] - dst.y[j]);
+            let vel_j = V3::new(dst.u[j] + du_j, dst.v[j], 0.);
+            let ang_vel_j = V3::new(0., 0., dst.omega_z[j]);
+
+            // normal passing from i to j and the tangent of the bond
+            let nij = unit_vector_from_point(pos_i, pos_j);
+            let tij = V3::unit_z().cross(nij);
+
+            // force due to the bond at the current stage
+            let f = bond.normal_force * nij + bond.shear_force * tij;
+            dst.fx[i] += f.x;
+            dst.fy[i] += f.y;
+            // torque due to the shear force acting at the surface of i and the
+            // bending moment
+            dst.tauz[i] += dst.rad[i] * bond.shear_force + bond.moment;
+
+            // velocity of j relative to i at the contact point
+            let v_rel = vel_j + ang_vel_j.cross(-dst.rad[j] * nij)
+                - vel_i - ang_vel_i.cross(dst.rad[i] * nij);
+
+            // increments of the bond forces and the moment
+            let (_, area, moi) = props.section(dst.rad[i], dst.rad[j]);
+            let d_fn = props.kn * area * v_rel.dot(nij) * dt;
+            let d_fs = props.ks * area * v_rel.dot(tij) * dt;
+            let d_m = props.kb * moi * (ang_vel_j.z - ang_vel_i.z) * dt;
+
+            // Increment the bond for next stage
+            if stage == 1 {
+                bond.normal_force += d_fn;
+                bond.shear_force += d_fs;
+                bond.moment += d_m;
+            } else if stage == 2 {
+                // use the bond at time t i.e., bonds0
+                let bond0 = dst.bonds0[i].entry(j).or_default();
+                bond.normal_force = bond0.normal_force + d_fn;
+                bond.shear_force = bond0.shear_force + d_fs;
+                bond.moment = bond0.moment + d_m;
+                *bond0 = bond.clone();
+            }
+        }
+    }
+}
+
+/// Position of the image of the particle at `(x_j, y_j)` nearest to `pos_i`.
+fn image_position(grid: &LinkedListGrid, pos_i: V3<f32>, x_j: f32, y_j: f32) -> V3<f32> {
+    let (dx, dy) = grid.min_image(pos_i.x - x_j, pos_i.y - y_j);
+    V3::new(pos_i.x - dx, pos_i.y - dy, 0.)
+}
+
+/// Break the bonds whose stresses exceed the strength given by the breakage
+/// criterion of `props`.
+///
+/// A broken bond is removed from `bonds` and `bonds0` of both the particles
+/// and recorded, along with the time `t`, in the breakage events of the
+/// entity. The breakage point lies on the surface of particle i, towards the
+/// nearest image of j given by `grid`.
+pub fn break_bonds_bonded_dem<T: DemBondedDstTrait>(
+    dest: &mut T,
+    props: &BondProperties,
+    t: f32,
+    grid: &LinkedListGrid,
+) {
+    let dst = dest.get_parts_mut();
+
+    // find the broken bonds, each pair is checked once
+    let mut broken = vec![];
+    for i in 0..*dst.len {
+        for (&j, bond) in &dst.bonds[i] {
+            if j < i {
+                continue;
+            }
+            let (radius, area, moi) = props.section(dst.rad[i], dst.rad[j]);
+            let sigma = bond.normal_force / area + bond.moment.abs() * radius / moi;
+            let tau = bond.shear_force.abs() / area;
+
+            if let Some(mode) = props.criterion.failure(sigma, tau) {
+                let pos_i = V3::new(dst.x[i], dst.y[i], 0.);
+                let pos_j = image_position(grid, pos_i, dst.x[j], dst.y[j]);
+                let pos_c = pos_i + dst.rad[i] * unit_vector_from_point(pos_i, pos_j);
+                broken.push(BreakageEvent {
+                    time: t,
+                    i,
+                    j,
+                    mode,
+                    x: pos_c.x,
+                    y: pos_c.y,
+                });
+            }
         }
     }
+
+    for event in broken {
+        dst.bonds[event.i].remove(&event.j);
+        dst.bonds[event.j].remove(&event.i);
+        dst.bonds0[event.i].remove(&event.j);
+        dst.bonds0[event.j].remove(&event.i);
+        dst.breakage_events.push(event);
+    }
+}
+
+/// Contact force between the particles of a bonded entity which are not bonded
+/// to each other.
+fn contact_force_bonded_dem_self(
+    dest: &mut DemBonded,
+    law: ContactLaw,
+    materials: &MaterialDatabase,
+    dt: f32,
+    stage: usize,
+    grid: &LinkedListGrid,
+) {
+    let particles = ContactParticles {
+        x: &dest.x,
+        y: &dest.y,
+        u: &dest.u,
+        v: &dest.v,
+        omega_z: &dest.omega_z,
+        rad: &dest.rad,
+        m: &dest.m,
+        inertia: &dest.inertia,
+        id: dest.id,
+        material_id: dest.material_id,
+    };
+    let mut acc = ContactAccumulator {
+        fx: &mut dest.fx,
+        fy: &mut dest.fy,
+        tauz: &mut dest.tauz,
+        tang_history: &mut dest.tang_history,
+        tang_history0: &mut dest.tang_history0,
+        roll_history: &mut dest.roll_history,
+        roll_history0: &mut dest.roll_history0,
+    };
+    let bonds = &dest.bonds;
+    let pair = materials
+        .pair(dest.material_id, dest.material_id)
+        .expect("missing material of a contact");
+    let ctx = ContactContext {
+        law,
+        pair,
+        dt,
+        stage,
+    };
+
+    contact_force_dem(
+        &particles,
+        &mut acc,
+        &particles,
+        &ctx,
+        grid,
+        |i, j| i == j || bonds[i].contains_key(&j),
+    );
+}
+
+/// Linear dashpot model between the particles of a bonded entity which are
+/// not bonded to each other, such as the two sides of a crack.
+///
+/// Contacts between bonded and non bonded entities use
+/// `linear_viscoelastic_model_dem_other` in either direction.
+pub fn linear_viscoelastic_model_bonded_dem_self(
+    dest: &mut DemBonded,
+    materials: &MaterialDatabase,
+    dt: f32,
+    stage: usize,
+    grid: &LinkedListGrid,
+) {
+    let law = ContactLaw::LinearViscoelastic;
+    contact_force_bonded_dem_self(dest, law, materials, dt, stage, grid);
+}
+
+/// Hertz-Mindlin model between the particles of a bonded entity which are
+/// not bonded to each other.
+///
+/// Contacts between bonded and non bonded entities use
+/// `hertz_mindlin_model_dem_other` in either direction.
+pub fn hertz_mindlin_model_bonded_dem_self(
+    dest: &mut DemBonded,
+    materials: &MaterialDatabase,
+    dt: f32,
+    stage: usize,
+    grid: &LinkedListGrid,
+) {
+    let law = ContactLaw::HertzMindlin;
+    contact_force_bonded_dem_self(dest, law, materials, dt, stage, grid);
 }
@@ -1,26 +1,158 @@
 #[macro_use]
 pub mod equations;
+#[cfg(test)]
 mod tests;
 
 // local imports
 use contact_search::{NNPSMutParts, NNPS};
+use integrate::{Integrate, IntegrateMutParts};
+use physics::dem::{indices_after_removal, remap_history, remap_partners, retain_particles,
+                   DemDiscreteDstStrkt, DemDiscreteDstTrait, DemDiscreteSrcStrkt,
+                   DemDiscreteSrcTrait};
 use std::collections::HashMap;
 
 // external crate imports
-use cm::{Vector3};
+use cm::Vector3;
 
-#[derive(Clone, Debug)]
+/// Parallel bond between two particles.
+///
+/// The bond of particle i with particle j is described in the local frame of
+/// the bond, with the normal n passing from i to j and the tangent
+/// t = z x n. The forces and the moment are accumulated incrementally.
+#[derive(Clone, Debug, Default)]
 pub struct Bond {
-    tang_overlap: Vector3<f32>,
+    /// normal force carried by the bond, positive in tension
+    pub normal_force: f32,
+    /// shear force along the tangent t
+    pub shear_force: f32,
+    /// bending moment
+    pub moment: f32,
 }
 
 impl Bond {
     pub fn new() -> Self {
         Bond {
-            tang_overlap: Vector3::new(0., 0., 0.),
+            normal_force: 0.,
+            shear_force: 0.,
+            moment: 0.,
+        }
+    }
+}
+
+/// Properties of the parallel bonds of a bonded entity, following the bonded
+/// particle model of Potyondy and Cundall (2004).
+///
+/// The bond between particles i and j is a beam of unit thickness with a
+/// radius of `radius_multiplier * min(R_i, R_j)`.
+#[derive(Clone, Copy, Debug)]
+pub struct BondProperties {
+    /// normal stiffness per unit area
+    pub kn: f32,
+    /// shear stiffness per unit area
+    pub ks: f32,
+    /// bending stiffness per unit area
+    pub kb: f32,
+    pub radius_multiplier: f32,
+    /// criterion deciding when a bond breaks
+    pub criterion: BreakageCriterion,
+}
+
+impl BondProperties {
+    /// Bond with the given normal and shear stiffness, whose bending stiffness
+    /// equals the normal stiffness and whose radius is the radius of the
+    /// smaller particle.
+    pub fn new(kn: f32, ks: f32) -> Self {
+        BondProperties {
+            kn,
+            ks,
+            kb: kn,
+            radius_multiplier: 1.,
+            criterion: BreakageCriterion::None,
+        }
+    }
+
+    /// Radius, area and moment of inertia of the cross section of the bond
+    /// between particles of radius `rad_i` and `rad_j`.
+    pub fn section(&self, rad_i: f32, rad_j: f32) -> (f32, f32, f32) {
+        let radius = self.radius_multiplier * rad_i.min(rad_j);
+        let area = 2. * radius;
+        let moi = 2. / 3. * radius.powf(3.);
+        (radius, area, moi)
+    }
+}
+/// Strength criterion of the parallel bonds.
+///
+/// The stresses acting on a bond with normal force $F_n$, shear force $F_s$ and
+/// moment $M$ are
+///
+/// $\sigma = \frac{F_n}{A} + \frac{|M| \bar{R}}{I}$, $\tau = \frac{|F_s|}{A}$
+///
+/// where the normal stress $\sigma$ is positive in tension.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BreakageCriterion {
+    /// The bonds never break.
+    None,
+    /// Break when the normal stress exceeds the `tensile` strength.
+    NormalStress { tensile: f32 },
+    /// Break when the shear stress exceeds the `shear` strength.
+    ShearStress { shear: f32 },
+    /// Break when either of the normal or shear stress exceeds its strength.
+    MaxStress { tensile: f32, shear: f32 },
+    /// Break in tension when the normal stress exceeds the `tensile`
+    /// strength, or in shear when the shear stress exceeds the Mohr-Coulomb
+    /// strength $c - \sigma \tan \phi$, with `cohesion` c and
+    /// `friction_angle` $\phi$ in radians.
+    MohrCoulomb {
+        tensile: f32,
+        cohesion: f32,
+        friction_angle: f32,
+    },
+}
+
+impl BreakageCriterion {
+    /// Mode in which a bond with normal stress `sigma` and shear stress `tau`
+    /// fails, if it does.
+    pub fn failure(&self, sigma: f32, tau: f32) -> Option<BreakageMode> {
+        let (tensile, shear) = match *self {
+            BreakageCriterion::None => return None,
+            BreakageCriterion::NormalStress { tensile } => (tensile, f32::INFINITY),
+            BreakageCriterion::ShearStress { shear } => (f32::INFINITY, shear),
+            BreakageCriterion::MaxStress { tensile, shear } => (tensile, shear),
+            BreakageCriterion::MohrCoulomb {
+                tensile,
+                cohesion,
+                friction_angle,
+            } => (tensile, cohesion - sigma * friction_angle.tan()),
+        };
+        if sigma > tensile {
+            Some(BreakageMode::Tensile)
+        } else if tau > shear {
+            Some(BreakageMode::Shear)
+        } else {
+            None
         }
     }
 }
+
+/// Mode in which a bond failed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BreakageMode {
+    Tensile,
+    Shear,
+}
+
+/// Record of a broken bond between particles `i` and `j` of a bonded entity.
+#[derive(Clone, Debug)]
+pub struct BreakageEvent {
+    pub time: f32,
+    pub i: usize,
+    pub j: usize,
+    pub mode: BreakageMode,
+    /// position of the contact point of the bond when it broke
+    pub x: f32,
+    pub y: f32,
+}
+
 pub struct DemBonded {
     pub len: usize,
     pub m: Vec<f32>,
@@ -44,8 +176,14 @@ pub struct DemBonded {
     pub tauz: Vec<f32>,
     pub id: usize,
     pub name: String,
+    pub material_id: usize,
     pub bonds: Vec<HashMap<usize, Bond>>,
     pub bonds0: Vec<HashMap<usize, Bond>>,
+    pub breakage_events: Vec<BreakageEvent>,
+    pub tang_history: Vec<HashMap<usize, HashMap<usize, Vector3<f32>>>>,
+    pub tang_history0: Vec<HashMap<usize, HashMap<usize, Vector3<f32>>>>,
+    pub roll_history: Vec<HashMap<usize, HashMap<usize, f32>>>,
+    pub roll_history0: Vec<HashMap<usize, HashMap<usize, f32>>>,
 }
 
 impl DemBonded {
@@ -54,6 +192,7 @@ impl DemBonded {
             len,
             name,
             id,
+            material_id: 0,
             m: vec![0.; len],
             x: vec![0.; len],
             y: vec![0.; len],
@@ -75,8 +214,63 @@ impl DemBonded {
             tauz: vec![0.; len],
             bonds: vec![HashMap::new(); len],
             bonds0: vec![HashMap::new(); len],
+            breakage_events: vec![],
+            tang_history: vec![HashMap::new(); len],
+            tang_history0: vec![HashMap::new(); len],
+            roll_history: vec![HashMap::new(); len],
+            roll_history0: vec![HashMap::new(); len],
         }
     }
+
+    /// Remove the particles with the given indices together with their
+    /// bonds, and renumber the remaining ones. See
+    /// `DemDiscrete::remove_particles`.
+    pub fn remove_particles(&mut self, remove: &[usize]) -> Vec<Option<usize>> {
+        let new_index = indices_after_removal(self.len, remove);
+        retain_particles(&mut self.m, &new_index);
+        retain_particles(&mut self.x, &new_index);
+        retain_particles(&mut self.y, &new_index);
+        retain_particles(&mut self.u, &new_index);
+        retain_particles(&mut self.v, &new_index);
+        retain_particles(&mut self.omega_z, &new_index);
+        retain_particles(&mut self.x0, &new_index);
+        retain_particles(&mut self.y0, &new_index);
+        retain_particles(&mut self.u0, &new_index);
+        retain_particles(&mut self.v0, &new_index);
+        retain_particles(&mut self.omega_z0, &new_index);
+        retain_particles(&mut self.inertia, &new_index);
+        retain_particles(&mut self.h, &new_index);
+        retain_particles(&mut self.m_inv, &new_index);
+        retain_particles(&mut self.i_inv, &new_index);
+        retain_particles(&mut self.rad, &new_index);
+        retain_particles(&mut self.fx, &new_index);
+        retain_particles(&mut self.fy, &new_index);
+        retain_particles(&mut self.tauz, &new_index);
+        retain_particles(&mut self.tang_history, &new_index);
+        retain_particles(&mut self.tang_history0, &new_index);
+        retain_particles(&mut self.roll_history, &new_index);
+        retain_particles(&mut self.roll_history0, &new_index);
+        retain_particles(&mut self.bonds, &new_index);
+        retain_particles(&mut self.bonds0, &new_index);
+        self.len = self.x.len();
+
+        for i in 0..self.len {
+            remap_partners(&mut self.bonds[i], &new_index);
+            remap_partners(&mut self.bonds0[i], &new_index);
+        }
+        let id = self.id;
+        self.remap_contact_history(id, &new_index);
+        new_index
+    }
+
+    /// Renumber the partners from the entity `src_id` in the contact
+    /// histories, after particles have been removed from it.
+    pub fn remap_contact_history(&mut self, src_id: usize, new_index: &[Option<usize>]) {
+        remap_history(&mut self.tang_history, src_id, new_index);
+        remap_history(&mut self.tang_history0, src_id, new_index);
+        remap_history(&mut self.roll_history, src_id, new_index);
+        remap_history(&mut self.roll_history0, src_id, new_index);
+    }
 }
 
 pub struct DemBondedDstStrkt<'a> {
@@ -99,6 +293,7 @@ pub struct DemBondedDstStrkt<'a> {
     pub name: &'a mut String,
     pub bonds: &'a mut Vec<HashMap<usize, Bond>>,
     pub bonds0: &'a mut Vec<HashMap<usize, Bond>>,
+    pub breakage_events: &'a mut Vec<BreakageEvent>,
 }
 
 pub struct DemBondedSrcStrkt<'a> {
@@ -150,6 +345,7 @@ macro_rules! impl_DemBondedDstTrait{
                     name: &mut self.name,
                     bonds: &mut self.bonds,
                     bonds0: &mut self.bonds0,
+                    breakage_events: &mut self.breakage_events,
                 }
             }
         }
@@ -182,5 +378,11 @@ macro_rules! impl_DemBondedSrcTrait{
 }
 
 impl_nnps![DemBonded];
+impl_integrate![DemBonded];
 impl_DemBondedDstTrait![DemBonded];
 impl_DemBondedSrcTrait![DemBonded];
+
+// bonded entities take part in contacts with the non bonded entities, and
+// between the particles which are not bonded to each other
+impl_DemDiscreteDstTrait![DemBonded];
+impl_DemDiscreteSrcTrait![DemBonded];
@@ -1,5 +1,12 @@
-use super::DemBonded;
-use super::equations::setup_bonded_structure;
+use super::{BondProperties, BreakageCriterion, BreakageMode, DemBonded};
+use super::equations::{break_bonds_bonded_dem, internal_force_bonded_dem,
+                       linear_viscoelastic_model_bonded_dem_self,
+                       make_forces_zero_bonded_dem, setup_bonded_structure};
+use contact_search::{LinkedListGrid, NNPS};
+use integrate::{integrate_initialize, integrate_stage1, integrate_stage2};
+use physics::dem::DemDiscrete;
+use physics::dem::equations::linear_viscoelastic_model_dem_other;
+use physics::material::{Material, MaterialDatabase};
 
 fn setup_particle_properties(part1: &mut DemBonded, x: Vec<f32>, y: Vec<f32>, h: f32) {
     for i in 0..part1.len {
@@ -7,6 +14,8 @@ fn setup_particle_properties(part1: &mut DemBonded, x: Vec<f32>, y: Vec<f32>, h:
         part1.y[i] = y[i];
         part1.h[i] = 1.2 * h;
         part1.rad[i] = h;
+        part1.m[i] = 1.;
+        part1.m_inv[i] = 1.;
     }
 }
 
@@ -127,6 +136,225 @@ fn test_tensile_force_on_bonded_dem() {
     setup_particle_properties(&mut beam, x, y, radius);
 
     setup_bonded_structure(&mut beam, 1.2);
+    let grid = LinkedListGrid::new(&mut vec![&mut beam], 2.);
 
-    // apply some force on
+    // pull the right particle away from the left one with a constant velocity
+    let props = BondProperties::new(1e6, 1e5);
+    let dt = 1e-4;
+    beam.u[1] = 1.;
+    for _ in 0..100 {
+        beam.fx[0] = 0.;
+        beam.fx[1] = 0.;
+        internal_force_bonded_dem(&mut beam, &props, dt, 1, &grid);
+        beam.x[1] += beam.u[1] * dt;
+    }
+
+    // the bond is stretched by 100 * dt, and carries a tensile force of
+    // kn * A * elongation, with A = 2 * bond radius
+    let elongation = 100. * dt;
+    let (_, area, _) = props.section(radius, radius);
+    let expected = props.kn * area * elongation;
+    assert!((beam.bonds[0][&1].normal_force - expected).abs() / expected < 1e-3);
+    assert!((beam.bonds[1][&0].normal_force - expected).abs() / expected < 1e-3);
+
+    // the bond pulls the particles towards each other, the force is computed
+    // from the bond before it is incremented in the last step
+    let applied = expected * 99. / 100.;
+    assert!((beam.fx[0] - applied).abs() / applied < 1e-3);
+    assert!((beam.fx[1] + applied).abs() / applied < 1e-3);
+    assert!(beam.fy[0].abs() < 1e-3);
+    assert!(beam.tauz[0].abs() < 1e-3);
+}
+
+#[test]
+fn test_shear_force_on_bonded_dem() {
+    // move the right particle of a bonded pair upwards, the shear force pulls
+    // the left particle up and rotates it counter clockwise
+    let spacing = 1.0;
+    let radius = spacing / 2.;
+    let x = vec![0., 1.0];
+    let y = vec![0., 0.0];
+    let mut beam = DemBonded::new(x.len(), 0, "beam".to_string());
+    setup_particle_properties(&mut beam, x, y, radius);
+    setup_bonded_structure(&mut beam, 1.2);
+    let grid = LinkedListGrid::new(&mut vec![&mut beam], 2.);
+
+    let props = BondProperties::new(1e6, 1e5);
+    beam.v[1] = 1.;
+    internal_force_bonded_dem(&mut beam, &props, 1e-4, 1, &grid);
+    internal_force_bonded_dem(&mut beam, &props, 1e-4, 1, &grid);
+
+    assert!(beam.bonds[0][&1].shear_force > 0.);
+    assert!(beam.fy[0] > 0.);
+    assert!(beam.fy[1] < 0.);
+    assert!(beam.tauz[0] > 0.);
+    // both particles rotate in the same direction
+    assert!(beam.tauz[1] > 0.);
+}
+
+#[test]
+fn test_breakage_criteria() {
+    let tensile = BreakageCriterion::NormalStress { tensile: 10. };
+    assert_eq!(None, tensile.failure(5., 100.));
+    assert_eq!(Some(BreakageMode::Tensile), tensile.failure(11., 0.));
+
+    let shear = BreakageCriterion::ShearStress { shear: 10. };
+    assert_eq!(None, shear.failure(100., 5.));
+    assert_eq!(Some(BreakageMode::Shear), shear.failure(0., 11.));
+
+    // compression increases the shear strength of a Mohr-Coulomb bond
+    let mohr = BreakageCriterion::MohrCoulomb {
+        tensile: 10.,
+        cohesion: 10.,
+        friction_angle: ::std::f32::consts::PI / 4.,
+    };
+    assert_eq!(Some(BreakageMode::Shear), mohr.failure(0., 11.));
+    assert_eq!(None, mohr.failure(-5., 11.));
+    assert_eq!(Some(BreakageMode::Tensile), mohr.failure(11., 0.));
+}
+
+#[test]
+fn test_stretched_bond_breaks_in_tension() {
+    let spacing = 1.0;
+    let radius = spacing / 2.;
+    let x = vec![0., 1.0, 2.0];
+    let y = vec![0., 0.0, 0.0];
+    let mut beam = DemBonded::new(x.len(), 0, "beam".to_string());
+    setup_particle_properties(&mut beam, x, y, radius);
+    setup_bonded_structure(&mut beam, 1.2);
+    let grid = LinkedListGrid::new(&mut vec![&mut beam], 2.);
+
+    let mut props = BondProperties::new(1e6, 1e5);
+    props.criterion = BreakageCriterion::MaxStress {
+        tensile: 150.,
+        shear: 150.,
+    };
+
+    // pull the last particle away, only the bond between 1 and 2 is stretched
+    let dt = 1e-4;
+    beam.u[2] = 1.;
+    internal_force_bonded_dem(&mut beam, &props, dt, 1, &grid);
+    break_bonds_bonded_dem(&mut beam, &props, 0., &grid);
+    assert_eq!(0, beam.breakage_events.len());
+
+    // stress in the bond is kn * elongation, which is 100 after the first
+    // step and 200 after the second
+    internal_force_bonded_dem(&mut beam, &props, dt, 1, &grid);
+    break_bonds_bonded_dem(&mut beam, &props, dt, &grid);
+
+    assert_eq!(1, beam.breakage_events.len());
+    let event = &beam.breakage_events[0];
+    assert_eq!((1, 2), (event.i, event.j));
+    assert_eq!(BreakageMode::Tensile, event.mode);
+    assert!((event.x - 1.5).abs() < 1e-6);
+
+    // the bond is removed from both the particles
+    assert!(!beam.bonds[1].contains_key(&2));
+    assert!(!beam.bonds[2].contains_key(&1));
+    assert!(!beam.bonds0[1].contains_key(&2));
+    assert!(!beam.bonds0[2].contains_key(&1));
+    assert!(beam.bonds[0].contains_key(&1));
+    assert!(beam.bonds[1].contains_key(&0));
+}
+
+#[test]
+fn test_rk2_integration_of_bonded_dem() {
+    // two bonded particles moving away from each other are pulled back by the
+    // bond, while their total momentum is conserved
+    let spacing = 1.0;
+    let radius = spacing / 2.;
+    let x = vec![0., 1.0];
+    let y = vec![0., 0.0];
+    let mut beam = DemBonded::new(x.len(), 0, "beam".to_string());
+    setup_particle_properties(&mut beam, x, y, radius);
+    setup_bonded_structure(&mut beam, 1.2);
+    let grid = LinkedListGrid::new(&mut vec![&mut beam], 2.);
+    beam.u[0] = -0.1;
+    beam.u[1] = 0.1;
+
+    let props = BondProperties::new(1e4, 1e3);
+    let dt = 1e-3;
+    let mut max_gap: f32 = 0.;
+    for _ in 0..200 {
+        integrate_initialize(&mut vec![&mut beam], dt);
+
+        make_forces_zero_bonded_dem(&mut beam);
+        internal_force_bonded_dem(&mut beam, &props, dt, 1, &grid);
+        integrate_stage1(&mut vec![&mut beam], dt);
+
+        make_forces_zero_bonded_dem(&mut beam);
+        internal_force_bonded_dem(&mut beam, &props, dt, 2, &grid);
+        integrate_stage2(&mut vec![&mut beam], dt);
+
+        max_gap = max_gap.max(beam.x[1] - beam.x[0] - spacing);
+
+        // at the end of a step the bonds at time t + dt are saved in bonds0
+        let bond = &beam.bonds[0][&1];
+        let bond0 = &beam.bonds0[0][&1];
+        assert_eq!(bond.normal_force, bond0.normal_force);
+    }
+    assert!((beam.u[0] + beam.u[1]).abs() < 1e-5);
+    // the bond stretched and pulled the particles back together, with a
+    // period of 2 pi sqrt(m / (2 kn A)) = 0.044
+    assert!(max_gap > 0.);
+    assert!(max_gap < 2. * 0.1 * 0.044);
+    assert!(beam.x[1] - beam.x[0] - spacing < max_gap);
+}
+
+#[test]
+fn test_contact_between_bonded_and_discrete_entities() {
+    // a bonded pair whose right particle overlaps a free grain by 0.1
+    let radius = 0.5;
+    let mut beam = DemBonded::new(2, 0, "beam".to_string());
+    setup_particle_properties(&mut beam, vec![0., 1.], vec![0., 0.], radius);
+    setup_bonded_structure(&mut beam, 1.2);
+
+    let mut grain = DemDiscrete::new(1, 1, "grain".to_string());
+    grain.x[0] = 1.9;
+    grain.h[0] = 1.2 * radius;
+    grain.rad[0] = radius;
+    grain.m[0] = 1.;
+    grain.m_inv[0] = 1.;
+
+    let mut materials = MaterialDatabase::new();
+    materials.add(Material::new("grain".to_string(), 1000., 1e4, 0.3));
+
+    let grid = {
+        let mut world: Vec<&mut dyn NNPS> = vec![&mut beam, &mut grain];
+        LinkedListGrid::new(&mut world, 2.)
+    };
+    linear_viscoelastic_model_dem_other(&mut beam, &mut grain, &materials, 1e-4, 1, &grid);
+    linear_viscoelastic_model_dem_other(&mut grain, &mut beam, &materials, 1e-4, 1, &grid);
+
+    // the grain and the bonded particle repel each other
+    assert!((beam.fx[1] + 1e4 * 0.1).abs() < 1e-1);
+    assert!((grain.fx[0] - 1e4 * 0.1).abs() < 1e-1);
+    assert!(beam.fx[0].abs() < 1e-6);
+}
+
+#[test]
+fn test_self_contact_skips_bonded_particles() {
+    // particles 0 and 1 are bonded, 0 and 2 overlap after their bond broke
+    let radius = 0.5;
+    let x = vec![0., 0.9, 0.];
+    let y = vec![0., 0., 0.9];
+    let mut beam = DemBonded::new(x.len(), 0, "beam".to_string());
+    setup_particle_properties(&mut beam, x, y, radius);
+    setup_bonded_structure(&mut beam, 1.2);
+    for &(i, j) in &[(0, 2), (2, 0), (1, 2), (2, 1)] {
+        beam.bonds[i].remove(&j);
+        beam.bonds0[i].remove(&j);
+    }
+
+    let mut materials = MaterialDatabase::new();
+    materials.add(Material::new("rock".to_string(), 1000., 1e4, 0.3));
+
+    let grid = LinkedListGrid::new(&mut vec![&mut beam], 2.);
+    linear_viscoelastic_model_bonded_dem_self(&mut beam, &materials, 1e-4, 1, &grid);
+
+    // only the unbonded pair 0 - 2 is in contact
+    assert!(beam.fx[0].abs() < 1e-6);
+    assert!((beam.fy[0] + 1e4 * 0.1).abs() < 1e-1);
+    assert!((beam.fy[2] - 1e4 * 0.1).abs() < 1e-1);
+    assert!(beam.fx[1].abs() < 1e-6);
 }
@@ -1,21 +1,38 @@
 // external crates imports
 use cm::{dot, InnerSpace, Vector3 as V3, Zero};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 
 // local imports
 use super::DemDiscrete;
 use super::{DemDiscreteDstTrait, DemDiscreteSrcTrait};
 use contact_search::{get_neighbours_ll, LinkedListGrid};
-use integrate::RK2;
 use math::unit_vector_from_dx;
+use physics::material::{MaterialDatabase, PairProperties};
 use std::collections::HashMap;
 
 pub fn make_forces_zero(entity: &mut DemDiscrete) {
     for i in 0..entity.len {
         entity.fx[i] = 0.;
         entity.fy[i] = 0.;
+        entity.tauz[i] = 0.;
     }
 }
 
+/// Set the moment of inertia of the particles treating them as disks,
+/// $I = \frac{1}{2} m R^2$, from their mass and radius.
+pub fn set_disk_inertia_dem(entity: &mut DemDiscrete) {
+    for i in 0..entity.len {
+        entity.inertia[i] = 0.5 * entity.m[i] * entity.rad[i].powf(2.);
+        entity.i_inv[i] = if entity.inertia[i] > 0. {
+            1. / entity.inertia[i]
+        } else {
+            0.
+        };
+    }
+}
+
+#[cfg(not(feature = "parallel"))]
 pub fn body_force_dem(entity: &mut DemDiscrete, gx: f32, gy: f32) {
     for i in 0..entity.len {
         entity.fx[i] += entity.m[i] * gx;
@@ -23,6 +40,20 @@ pub fn body_force_dem(entity: &mut DemDiscrete, gx: f32, gy: f32) {
     }
 }
 
+#[cfg(feature = "parallel")]
+pub fn body_force_dem(entity: &mut DemDiscrete, gx: f32, gy: f32) {
+    let m = &entity.m;
+    entity
+        .fx
+        .par_iter_mut()
+        .zip(entity.fy.par_iter_mut())
+        .zip(m.par_iter())
+        .for_each(|((fx, fy), m)| {
+            *fx += m * gx;
+            *fy += m * gy;
+        });
+}
+
 /// Relative velocity between two particles
 ///
 /// Find relative velocity of particle i with respect to particle
@@ -71,779 +102,786 @@ pub fn relative_velocity(
     vi - vj + (rad_i * ang_v_i + rad_j * ang_v_j).cross(nij)
 }
 
-/// Linear dashpot model introduced by Cundall and Strack.
-pub fn linear_viscoelastic_model_dem_other<T, U>(
+/// Damping ratio corresponding to a coefficient of restitution `en`.
+///
+/// For a linear spring dashpot the ratio of the damping coefficient to its
+/// critical value is
+///
+/// $\beta = \frac{-\ln e_n}{\sqrt{\pi^2 + \ln^2 e_n}}$
+///
+/// A restitution of zero gives critical damping.
+pub fn damping_ratio_from_restitution(en: f32) -> f32 {
+    if en <= 0. {
+        1.
+    } else {
+        let ln_en = en.ln();
+        -ln_en / (::std::f32::consts::PI.powf(2.) + ln_en.powf(2.)).sqrt()
+    }
+}
+
+/// Effective mass of a pair of particles with masses `m_i` and `m_j`.
+pub fn effective_mass(m_i: f32, m_j: f32) -> f32 {
+    m_i * m_j / (m_i + m_j)
+}
+
+/// Force law of a contact.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ContactLaw {
+    /// Linear spring with constant normal stiffness `kn` and a tangential
+    /// stiffness of `kt_ratio * kn` of the pair.
+    LinearViscoelastic,
+    /// Hertz normal law and Mindlin tangential law with the effective
+    /// Young's and shear modulus of the pair.
+    HertzMindlin,
+}
+
+/// Stiffness and damping of a contact at the current overlap.
+struct ContactCoefficients {
+    /// magnitude of the elastic normal force
+    fn_magn: f32,
+    /// normal contact stiffness
+    kn: f32,
+    /// stiffness of the tangential spring
+    kt: f32,
+    /// normal damping coefficient
+    eta_n: f32,
+    /// tangential damping coefficient
+    eta_t: f32,
+}
+
+impl ContactLaw {
+    /// Given the properties of the pair, the normal overlap, the effective
+    /// radius and the effective mass of the pair, compute the elastic normal
+    /// force, tangential stiffness and the damping coefficients.
+    fn coefficients(
+        &self,
+        props: &PairProperties,
+        delta_n: f32,
+        rad_eff: f32,
+        m_eff: f32,
+    ) -> ContactCoefficients {
+        let beta = damping_ratio_from_restitution(props.en);
+        match *self {
+            ContactLaw::LinearViscoelastic => {
+                let kn = props.kn;
+                let kt = props.kt_ratio * kn;
+                // linear dashpot, 2 beta sqrt(m k)
+                ContactCoefficients {
+                    fn_magn: kn * delta_n,
+                    kn,
+                    kt,
+                    eta_n: 2. * beta * (m_eff * kn).sqrt(),
+                    eta_t: 2. * beta * (m_eff * kt).sqrt(),
+                }
+            }
+            ContactLaw::HertzMindlin => {
+                // effective Young's modulus and shear modulus of the pair
+                let yng_eff = props.yng_eff;
+                let shear_eff = props.shear_eff;
+
+                // contact radius
+                let a = (rad_eff * delta_n).sqrt();
+                // normal and tangential contact stiffness
+                let s_n = 2. * yng_eff * a;
+                let s_t = 8. * shear_eff * a;
+
+                // nonlinear dashpot of Tsuji et al., 2 sqrt(5 / 6) beta sqrt(m S)
+                let fac = 2. * (5_f32 / 6.).sqrt() * beta;
+                ContactCoefficients {
+                    fn_magn: 4. / 3. * yng_eff * a * delta_n,
+                    kn: s_n,
+                    kt: s_t,
+                    eta_n: fac * (m_eff * s_n).sqrt(),
+                    eta_t: fac * (m_eff * s_t).sqrt(),
+                }
+            }
+        }
+    }
+}
+
+/// Rolling resistance model of a contact.
+///
+/// The rolling resistance opposes the relative rotation of the two particles,
+/// $\omega_r = \omega_i - \omega_j$, and is limited by
+/// $M_m = \mu_r R^* |F_n|$, where $\mu_r$ is the rolling friction coefficient
+/// of the pair.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RollingModel {
+    /// No rolling resistance.
+    None,
+    /// Constant directional torque, $M_r = -M_m \omega_r / |\omega_r|$.
+    ConstantTorque,
+    /// Elastic-plastic spring-dashpot model of Ai et al. (2011). The spring
+    /// torque is accumulated in the rolling history with a stiffness of
+    /// $k_r = 2.25 k_n \mu_r^2 R^{*2}$ and limited by $M_m$. The damping is
+    /// active only while the spring is not fully mobilised.
+    ElasticPlastic,
+}
+
+/// Read only particle data of an entity taking part in a contact.
+pub(crate) struct ContactParticles<'a> {
+    pub(crate) x: &'a [f32],
+    pub(crate) y: &'a [f32],
+    pub(crate) u: &'a [f32],
+    pub(crate) v: &'a [f32],
+    pub(crate) omega_z: &'a [f32],
+    pub(crate) rad: &'a [f32],
+    pub(crate) m: &'a [f32],
+    pub(crate) inertia: &'a [f32],
+    pub(crate) id: usize,
+    pub(crate) material_id: usize,
+}
+
+/// Quantities of the destination entity updated by a contact.
+pub(crate) struct ContactAccumulator<'a> {
+    pub(crate) fx: &'a mut [f32],
+    pub(crate) fy: &'a mut [f32],
+    pub(crate) tauz: &'a mut [f32],
+    pub(crate) tang_history: &'a mut [HashMap<usize, HashMap<usize, V3<f32>>>],
+    pub(crate) tang_history0: &'a mut [HashMap<usize, HashMap<usize, V3<f32>>>],
+    pub(crate) roll_history: &'a mut [HashMap<usize, HashMap<usize, f32>>],
+    pub(crate) roll_history0: &'a mut [HashMap<usize, HashMap<usize, f32>>],
+}
+
+/// Quantities of a single particle of the destination entity updated by a
+/// contact.
+pub(crate) struct ParticleAccumulator<'a> {
+    pub(crate) fx: &'a mut f32,
+    pub(crate) fy: &'a mut f32,
+    pub(crate) tauz: &'a mut f32,
+    pub(crate) tang_history: &'a mut HashMap<usize, HashMap<usize, V3<f32>>>,
+    pub(crate) tang_history0: &'a mut HashMap<usize, HashMap<usize, V3<f32>>>,
+    pub(crate) roll_history: &'a mut HashMap<usize, HashMap<usize, f32>>,
+    pub(crate) roll_history0: &'a mut HashMap<usize, HashMap<usize, f32>>,
+}
+
+/// Tangential spring-dashpot of a contact.
+struct TangentialSpring {
+    /// tangential stiffness
+    kt: f32,
+    /// tangential damping coefficient
+    eta_t: f32,
+    /// limiting Coulomb force
+    f_t_max: f32,
+}
+
+/// Tangential force due to the history dependent tangential spring, with
+/// relative tangential velocity `v_t` and normal `nij`.
+///
+/// The tangential histories of particle i look like
+///
+/// hist = {'0': {'2': Vector3, '31': Vector3, '7': Vector3},
+///           '1': {'3': Vector3, '5': Vector3, '9': Vector3}}
+///
+/// meaning that particle i is in contact with particles [2, 31, 7] of entity
+/// '0' and particles [3, 5, 9] of entity '1'. `tang_overlap` is the spring of
+/// the contact at the current stage and `tang_overlap0` the one at time t.
+///
+/// The spring is projected on to the current tangential plane, the test force
+/// is limited by the Coulomb force and the spring is incremented for the next
+/// stage.
+/// http://www.piko.ovgu.de/piko_media/aktuelles/Siegen/LUDING2012PIKO_Contacts.pdf
+fn tangential_spring_force(
+    tang_overlap: &mut V3<f32>,
+    tang_overlap0: &mut V3<f32>,
+    nij: V3<f32>,
+    v_t: V3<f32>,
+    spring: &TangentialSpring,
+    dt: f32,
+    stage: usize,
+) -> V3<f32> {
+    let TangentialSpring { kt, eta_t, f_t_max } = *spring;
+
+    // Now project the spring onto current tangential plane
+    let tang_overlap_rotated = *tang_overlap - nij * dot(*tang_overlap, nij);
+
+    // Find tangential test force from the rotated spring
+    let f_t0 = -kt * tang_overlap_rotated - eta_t * v_t;
+    let f_t0_magn = f_t0.magnitude();
+
+    // Check for sliding
+    if f_t0_magn <= f_t_max {
+        // Increment the tangential spring for next time step
+        if stage == 1 {
+            *tang_overlap = tang_overlap_rotated + v_t * dt;
+        } else if stage == 2 {
+            // use the tangential overlap at time t i.e., tang_overlap0
+            // project it onto current orientaton, i.e., t + dt / 2
+            let tang_overlap_rotated0 = *tang_overlap0 - nij * dot(*tang_overlap0, nij);
+            *tang_overlap = tang_overlap_rotated0 + v_t * dt;
+            *tang_overlap0 = *tang_overlap;
+        }
+        f_t0
+    } else {
+        // So the particles slide. Set the tangential force to the maximum
+        // force allowed by Couloumb force
+        let t_ij = f_t0 / f_t0_magn;
+        let f_t = f_t_max * t_ij;
+
+        // Restrict the spring length such that the resultant tangential
+        // force equals Couloumb force, without stiffness there is no spring
+        *tang_overlap = if kt > 0. {
+            -(f_t + eta_t * v_t) / kt
+        } else {
+            V3::zero()
+        };
+        if stage == 2 {
+            *tang_overlap0 = *tang_overlap;
+        }
+        f_t
+    }
+}
+
+/// Rolling spring-dashpot of the elastic-plastic model.
+struct RollingSpring {
+    /// rolling stiffness
+    k_r: f32,
+    /// rolling damping coefficient
+    c_r: f32,
+    /// limiting torque
+    m_max: f32,
+}
+
+/// Rolling resistance torque of the elastic-plastic spring-dashpot model for
+/// the relative rolling velocity `w_r`.
+///
+/// The rolling histories of particle i are laid out the same way as the
+/// tangential history. `m_spring` is the spring torque of the contact at the
+/// current stage and `m_spring0` the one at time t.
+fn rolling_spring_torque(
+    m_spring: &mut f32,
+    m_spring0: &mut f32,
+    w_r: f32,
+    spring: &RollingSpring,
+    dt: f32,
+    stage: usize,
+) -> f32 {
+    let RollingSpring { k_r, c_r, m_max } = *spring;
+
+    // torque of the spring at the current stage, the dashpot only acts
+    // when the spring is not fully mobilised
+    let m_r = if m_spring.abs() < m_max {
+        *m_spring - c_r * w_r
+    } else {
+        *m_spring
+    };
+
+    // increment the spring for the next stage and limit it
+    let m_next = if stage == 1 {
+        *m_spring - k_r * w_r * dt
+    } else {
+        *m_spring0 - k_r * w_r * dt
+    };
+    *m_spring = m_next.max(-m_max).min(m_max);
+    if stage == 2 {
+        *m_spring0 = *m_spring;
+    }
+    m_r.max(-m_max).min(m_max)
+}
+
+/// Contact of particle i with a partner, which is either particle j of the
+/// entity `src_id` or element j of the wall `src_id`.
+pub(crate) struct ContactPoint {
+    pub(crate) src_id: usize,
+    pub(crate) j: usize,
+    /// normal passing from the partner to particle i
+    pub(crate) nij: V3<f32>,
+    /// normal overlap
+    pub(crate) delta_n: f32,
+    /// velocity of particle i relative to the partner at the contact point
+    pub(crate) v_ij: V3<f32>,
+    /// relative rolling velocity
+    pub(crate) w_r: f32,
+    pub(crate) rad_i: f32,
+    /// effective radius and mass of the pair
+    pub(crate) rad_eff: f32,
+    pub(crate) m_eff: f32,
+    /// effective rolling inertia of the pair about the contact point
+    pub(crate) i_r: f32,
+}
+
+/// Contact law and properties of a pair of entities, with the time step and
+/// the history stage of the force evaluation.
+pub(crate) struct ContactContext {
+    pub(crate) law: ContactLaw,
+    pub(crate) pair: PairProperties,
+    pub(crate) dt: f32,
+    pub(crate) stage: usize,
+}
+
+/// Force on particle i due to the given contact and the law of `ctx`,
+/// including the tangential force and the rolling resistance with their
+/// histories. The force and the torque are added to `acc`, and the force is
+/// returned.
+pub(crate) fn contact_force(
+    acc: &mut ParticleAccumulator,
+    c: &ContactPoint,
+    ctx: &ContactContext,
+) -> V3<f32> {
+    let (pair, dt, stage) = (&ctx.pair, ctx.dt, ctx.stage);
+    // relative  normal velocity
+    let v_n = c.v_ij.dot(c.nij) * c.nij; //this is vector
+    // relative  tangential velocity
+    let v_t = c.v_ij - v_n; //this is vector
+
+    let coeffs = ctx.law.coefficients(pair, c.delta_n, c.rad_eff, c.m_eff);
+
+    // ----------------------------------------------------
+    // Normal force with damping
+    let f_n = coeffs.fn_magn * c.nij - coeffs.eta_n * v_n;
+
+    // Add normal force to total force with damping in normal direction
+    let mut f = f_n;
+
+    // ----------------------------------------------------
+    // ----------------Tangential force -------------------
+    // Check for tangential contacts only if there is friction
+    if pair.mu != 0. {
+        let spring = TangentialSpring {
+            kt: coeffs.kt,
+            eta_t: coeffs.eta_t,
+            f_t_max: pair.mu * f_n.magnitude(),
+        };
+        let tang_overlap = acc.tang_history
+            .entry(c.src_id)
+            .or_default()
+            .entry(c.j)
+            .or_insert_with(V3::zero);
+        let tang_overlap0 = acc.tang_history0
+            .entry(c.src_id)
+            .or_default()
+            .entry(c.j)
+            .or_insert_with(V3::zero);
+        let f_t =
+            tangential_spring_force(tang_overlap, tang_overlap0, c.nij, v_t, &spring, dt, stage);
+        f += f_t;
+
+        // torque due to the tangential force acting at the contact point
+        let r_ic = -c.rad_i * c.nij;
+        *acc.tauz += r_ic.cross(f_t).z;
+    }
+
+    // ----------------------------------------------------
+    // ----------------Rolling resistance -----------------
+    if pair.mu_r != 0. {
+        // limiting torque
+        let m_max = pair.mu_r * c.rad_eff * f_n.magnitude();
+        match pair.rolling {
+            RollingModel::None => {}
+            RollingModel::ConstantTorque => {
+                if c.w_r != 0. {
+                    *acc.tauz -= m_max * c.w_r.signum();
+                }
+            }
+            RollingModel::ElasticPlastic => {
+                let k_r = 2.25 * coeffs.kn * (pair.mu_r * c.rad_eff).powf(2.);
+                // the rolling dashpot has the damping ratio of the normal
+                // dashpot
+                let eta_r = damping_ratio_from_restitution(pair.en);
+                let c_r = 2. * eta_r * (c.i_r * k_r).sqrt();
+
+                let spring = RollingSpring { k_r, c_r, m_max };
+                let m_spring = acc.roll_history
+                    .entry(c.src_id)
+                    .or_default()
+                    .entry(c.j)
+                    .or_insert(0.);
+                let m_spring0 = acc.roll_history0
+                    .entry(c.src_id)
+                    .or_default()
+                    .entry(c.j)
+                    .or_insert(0.);
+                *acc.tauz += rolling_spring_torque(m_spring, m_spring0, c.w_r, &spring, dt, stage);
+            }
+        }
+    }
+    *acc.fx += f[0];
+    *acc.fy += f[1];
+    f
+}
+
+/// Remove particle j of entity `src_id` from the tangential and rolling
+/// histories of particle i, if it is being tracked.
+pub(crate) fn remove_contact_history(acc: &mut ParticleAccumulator, src_id: usize, j: usize) {
+    if let Some(nbrs) = acc.tang_history.get_mut(&src_id) {
+        nbrs.remove(&j);
+    }
+    if let Some(nbrs) = acc.tang_history0.get_mut(&src_id) {
+        nbrs.remove(&j);
+    }
+    if let Some(nbrs) = acc.roll_history.get_mut(&src_id) {
+        nbrs.remove(&j);
+    }
+    if let Some(nbrs) = acc.roll_history0.get_mut(&src_id) {
+        nbrs.remove(&j);
+    }
+}
+
+/// Contact forces on the particles of `dest` due to the particles of `srce`.
+///
+/// Pairs of particles (i, j) for which `excluded` returns true do not
+/// interact, such as a particle with itself when `dest` and `srce` are the
+/// same entity.
+///
+/// With the `parallel` feature the particles of `dest` are distributed over
+/// threads. Every particle only updates its own force and history, in the
+/// same order as the serial loop, so the results are identical.
+#[cfg(feature = "parallel")]
+pub(crate) fn contact_force_dem<F>(
+    dest: &ContactParticles,
+    acc: &mut ContactAccumulator,
+    srce: &ContactParticles,
+    ctx: &ContactContext,
+    grid: &LinkedListGrid,
+    excluded: F,
+) where
+    F: Fn(usize, usize) -> bool + Sync,
+{
+    acc.fx
+        .par_iter_mut()
+        .zip(acc.fy.par_iter_mut())
+        .zip(acc.tauz.par_iter_mut())
+        .zip(acc.tang_history.par_iter_mut())
+        .zip(acc.tang_history0.par_iter_mut())
+        .zip(acc.roll_history.par_iter_mut())
+        .zip(acc.roll_history0.par_iter_mut())
+        .enumerate()
+        .for_each(|(i, ((((((fx, fy), tauz), th), th0), rh), rh0))| {
+            let mut acc_i = ParticleAccumulator {
+                fx,
+                fy,
+                tauz,
+                tang_history: th,
+                tang_history0: th0,
+                roll_history: rh,
+                roll_history0: rh0,
+            };
+            contact_force_on_particle(i, dest, &mut acc_i, srce, ctx, grid, &excluded);
+        });
+}
+
+/// Contact forces on the particles of `dest` due to the particles of `srce`.
+///
+/// Pairs of particles (i, j) for which `excluded` returns true do not
+/// interact, such as a particle with itself when `dest` and `srce` are the
+/// same entity.
+#[cfg(not(feature = "parallel"))]
+pub(crate) fn contact_force_dem<F>(
+    dest: &ContactParticles,
+    acc: &mut ContactAccumulator,
+    srce: &ContactParticles,
+    ctx: &ContactContext,
+    grid: &LinkedListGrid,
+    excluded: F,
+) where
+    F: Fn(usize, usize) -> bool + Sync,
+{
+    contact_force_dem_serial(dest, acc, srce, ctx, grid, excluded)
+}
+
+/// Serial loop over the particles of `dest`, see `contact_force_dem`.
+#[cfg(any(test, not(feature = "parallel")))]
+pub(crate) fn contact_force_dem_serial<F>(
+    dest: &ContactParticles,
+    acc: &mut ContactAccumulator,
+    srce: &ContactParticles,
+    ctx: &ContactContext,
+    grid: &LinkedListGrid,
+    excluded: F,
+) where
+    F: Fn(usize, usize) -> bool,
+{
+    for i in 0..dest.x.len() {
+        let mut acc_i = ParticleAccumulator {
+            fx: &mut acc.fx[i],
+            fy: &mut acc.fy[i],
+            tauz: &mut acc.tauz[i],
+            tang_history: &mut acc.tang_history[i],
+            tang_history0: &mut acc.tang_history0[i],
+            roll_history: &mut acc.roll_history[i],
+            roll_history0: &mut acc.roll_history0[i],
+        };
+        contact_force_on_particle(i, dest, &mut acc_i, srce, ctx, grid, &excluded);
+    }
+}
+
+/// Contact force on particle i of `dest` due to its neighbours in `srce`.
+///
+/// In a sheared domain the particles interact with the nearest images of
+/// their neighbours, which carry the shear velocity. The tangential history
+/// is thus incremented with the relative velocity of the images, and stays
+/// continuous when either particle crosses a sheared side and is moved onto
+/// its image.
+fn contact_force_on_particle<F>(
+    i: usize,
+    dest: &ContactParticles,
+    acc: &mut ParticleAccumulator,
+    srce: &ContactParticles,
+    ctx: &ContactContext,
+    grid: &LinkedListGrid,
+    excluded: &F,
+) where
+    F: Fn(usize, usize) -> bool,
+{
+    // position of particle i
+    let pos_i = V3::new(dest.x[i], dest.y[i], 0.);
+    // linear velocity of particle i
+    let vel_i = V3::new(dest.u[i], dest.v[i], 0.);
+    // angular velocity of particle i
+    let ang_vel_i = V3::new(0., 0., dest.omega_z[i]);
+
+    let nbrs = get_neighbours_ll([dest.x[i], dest.y[i], 0.], grid, &srce.id);
+
+    for sub_view in nbrs {
+        // neighbour indices j
+        for &j in sub_view {
+            if excluded(i, j) {
+                continue;
+            }
+            // position of particle j in source
+            let pos_j = V3::new(srce.x[j], srce.y[j], 0.);
+            // velocity of particle j, or of its image across the sides of a
+            // sheared domain
+            let du_j = grid.image_velocity(pos_i.y - pos_j.y);
+            let vel_j = V3::new(srce.u[j] + du_j, srce.v[j], 0.);
+            // angular velocity of particle j
+            let ang_vel_j = V3::new(0., 0., srce.omega_z[j]);
+
+            // find the unit vector from j to i, using the nearest image
+            // of j in a periodic domain
+            let (dx, dy) = grid.min_image(pos_i.x - pos_j.x, pos_i.y - pos_j.y);
+            let dz = pos_i.z - pos_j.z;
+
+            let distance = (dx.powf(2.) + dy.powf(2.) + dz.powf(2.)).sqrt();
+            // radius sum
+            let radsum = dest.rad[i] + srce.rad[j];
+
+            // overlap amount
+            let delta_n = radsum - distance;
+
+            // check if particles are in overlap
+            if delta_n > 0. {
+                // normal vector, passing from j to i
+                let nij = unit_vector_from_dx(dx, dy, dz, distance);
+
+                // relative velocity at the contact point, which needs the
+                // normal passing from i to j
+                let v_ij = relative_velocity(
+                    vel_i,
+                    vel_j,
+                    ang_vel_i,
+                    ang_vel_j,
+                    -nij,
+                    dest.rad[i],
+                    srce.rad[j],
+                ); // this is vector
+
+                // effective rolling inertia of the pair about the contact
+                // point
+                let i_i = dest.inertia[i] + dest.m[i] * dest.rad[i].powf(2.);
+                let i_j = srce.inertia[j] + srce.m[j] * srce.rad[j].powf(2.);
+
+                let contact = ContactPoint {
+                    src_id: srce.id,
+                    j,
+                    nij,
+                    delta_n,
+                    v_ij,
+                    w_r: ang_vel_i.z - ang_vel_j.z,
+                    rad_i: dest.rad[i],
+                    rad_eff: dest.rad[i] * srce.rad[j] / radsum,
+                    m_eff: effective_mass(dest.m[i], srce.m[j]),
+                    i_r: i_i * i_j / (i_i + i_j),
+                };
+                contact_force(acc, &contact, ctx);
+            }
+            // if they are not overlapping, remove the particle j of srce id
+            // from history of particle i
+            else {
+                remove_contact_history(acc, srce.id, j);
+            }
+        }
+    }
+}
+
+macro_rules! contact_particles {
+    ($e:expr) => {
+        ContactParticles {
+            x: $e.x,
+            y: $e.y,
+            u: $e.u,
+            v: $e.v,
+            omega_z: $e.omega_z,
+            rad: $e.rad,
+            m: $e.m,
+            inertia: $e.inertia,
+            id: *$e.id,
+            material_id: *$e.material_id,
+        }
+    };
+}
+
+macro_rules! contact_accumulator {
+    ($e:expr) => {
+        ContactAccumulator {
+            fx: $e.fx,
+            fy: $e.fy,
+            tauz: $e.tauz,
+            tang_history: $e.tang_history,
+            tang_history0: $e.tang_history0,
+            roll_history: $e.roll_history,
+            roll_history0: $e.roll_history0,
+        }
+    };
+}
+
+/// Contact force between particles of two different entities, `dst` and
+/// `src`, due to the given law. Only `dst` is updated.
+fn contact_force_dem_other<T, U>(
     dst: &mut T,
     src: &mut U,
-    kn: f32,
-    mu: f32,
+    law: ContactLaw,
+    materials: &MaterialDatabase,
     dt: f32,
     stage: usize,
     grid: &LinkedListGrid,
-    dim: usize,
 ) where
     T: DemDiscreteDstTrait,
     U: DemDiscreteSrcTrait,
 {
     let dest = dst.get_parts_mut();
     let srce = src.get_parts_mut();
-    // Select the neighbours function according to the dimention
-    let get_nbrs = get_neighbours_ll;
-
-    // Select the neighbours function according to the dimention
-    for i in 0..*dest.len {
-        // position of particle i
-        let pos_i = V3::new(dest.x[i], dest.y[i], 0.);
-        // linear velocity of particle i
-        let vel_i = V3::new(dest.u[i], dest.v[i], 0.);
-        // angular velocity of particle i
-        let ang_vel_i = V3::new(0., 0., dest.omega_z[i]);
-
-        let nbrs = get_nbrs([dest.x[i], dest.y[i], 0.], &grid, &srce.id);
-
-        for sub_view in nbrs {
-            // neighbour indices j
-            for &j in sub_view {
-                // position of particle j in source
-                let pos_j = V3::new(srce.x[j], srce.y[j], 0.);
-                // velocity of particle j
-                let vel_j = V3::new(srce.u[j], srce.v[j], 0.);
-                // angular velocity of particle j
-                let ang_vel_j = V3::new(0., 0., srce.omega_z[j]);
-
-                // find the unit vector from j to i
-                let dx = pos_i.x - pos_j.x;
-                let dy = pos_i.y - pos_j.y;
-                let dz = pos_i.z - pos_j.z;
-
-                let distance = (dx.powf(2.) + dy.powf(2.) + dz.powf(2.)).sqrt();
-                // radius sum
-                let radsum = dest.rad[i] + srce.rad[j];
-
-                // overlap amount
-                let delta_n = radsum - distance;
-
-                // check if particles are in overlap
-                if delta_n > 0. {
-                    // Define the force variables, total, tangential, torsion
-                    let mut f = V3::zero();
-                    let mut f_t: V3<f32> = V3::zero();
-                    let mut f_r: V3<f32> = V3::zero();
-
-                    // normal vector
-                    let nij = unit_vector_from_dx(dx, dy, dz, distance);
-
-                    // relative velocity
-                    let v_ij = relative_velocity(
-                        vel_i,
-                        vel_j,
-                        ang_vel_i,
-                        ang_vel_j,
-                        nij,
-                        dest.rad[i],
-                        srce.rad[j],
-                    ); // this is vector
-
-                    // relative  normal velocity
-                    let v_n = v_ij.dot(nij) * nij; //this is vector
-                    // relative  tangential velocity
-                    let v_t = v_ij - v_n; //this is vector
-
-                    // ----------------------------------------------------
-                    // Normal force with damping
-                    // FIX ME: Need to use real coefficients
-                    let f_n = kn * delta_n * nij - v_n * 0.001;
-
-                    // Add normal force to total force with damping in normal direction
-                    f += f_n;
-
-                    // ----------------------------------------------------
-                    // ----------------Tangential force -------------------
-                    // Check for tangential contacts only if there is friction
-                    if mu != 0. {
-                        // If tangential forces are present
-                        // get the history of all particles being tracked by
-                        // particle i
-                        let hist = &mut dest.tang_history[i];
-
-                        // -------------------------------------------------
-                        // Tangential overlap variable explanation
-                        // -------------------------------------------------
-
-                        // the variable above (hist) contains particles already in
-                        // overlap.
-
-                        // The particle i's neighbours looks like
-
-                        // hist = {'0': {'2': Vector3, '31': Vector3, '7': Vector3},
-                        //           '1': {'3': Vector3, '5': Vector3, '9': Vector3}}
-
-                        // The meaning of above format is, particle i is is already
-                        // contact with an entities with id's '0' and '1'.
-
-                        // diving little deep gives us the indices of those entites
-                        // as, particle of index '9' has neighbours [2, 31, 7] of
-                        // entity '0'. And also has neighbours [3, 5, 9] of entity
-                        // '1'.
-
-                        // So the type of hist would be
-                        // Vec<HashMap<usize, HashMap<usize, Vector3>>>
-                        // -------------------------------------------------
-                        // Tangential overlap variable explanation
-                        // -------------------------------------------------
-
-                        // Find the index j in hist of particle i with id of
-                        // srce.id
-
-                        // If j is already been tracked then remove it
-                        // If it is not been tracked then leave hist alone
-
-                        // Note: To do this operatio I am using match
-                        // match is provided by rust and it's awesome
-
-                        // this leaf is to check if the particle i has history
-                        // with the srce entity
-                        match hist.contains_key(&srce.id) {
-                            // If it has neighbours with srce, then
-                            // go ahead and check if it is tracking particle j
-                            true => {
-                                match hist[&srce.id].contains_key(&j) {
-                                    // If it already has particle j
-                                    // Then find the force due to its current
-                                    // tangential spring elongation
-                                    true => {
-                                        // Get the spring
-                                        let mut tang_overlap =
-                                            hist.get_mut(&srce.id).unwrap().get_mut(&j).unwrap();
-
-                                        // Now project the spring onto current tangential plane
-                                        // http://www.piko.ovgu.de/piko_media/aktuelles/Siegen/LUDING2012PIKO_Contacts.pdf
-                                        let tang_overlap_rotated =
-                                            *tang_overlap - nij * (dot(*tang_overlap, nij));
-
-                                        // Find tangential test force from the rotated spring
-                                        let f_t0 = -1e4 * tang_overlap_rotated - 0.001 * v_t;
-                                        let f_t0_magn = f_t0.magnitude();
-
-                                        // find the tangential unit vector
-                                        let t_ij = if f_t0_magn > 0. {
-                                            f_t0 / f_t0.magnitude()
-                                        } else {
-                                            V3::zero()
-                                        };
-
-                                        // Check for sliding
-                                        let fn_norm = f_n.magnitude();
-                                        if f_t0_magn <= mu * fn_norm {
-                                            // Set the tangential force to test tangential
-                                            // force
-                                            f_t = f_t0;
-
-                                            // Increment the tangential spring
-                                            // for next time step
-                                            // Note: dt changes for different stages
-                                            if stage == 1 {
-                                                *tang_overlap = tang_overlap_rotated + v_t * dt;
-                                            } else if stage == 2 {
-                                                // use the tangential overlap at time t i.e., tang_overlap0
-                                                // project it onto current orientaton, i.e., t + dt / 2
-                                                let hist0 = &mut dest.tang_history0[i];
-                                                let tang_overlap0 = hist0
-                                                    .get_mut(&srce.id)
-                                                    .unwrap()
-                                                    .get_mut(&j)
-                                                    .unwrap();
-                                                let tang_overlap_rotated0 = *tang_overlap0
-                                                    - nij * (dot(*tang_overlap, nij));
-                                                *tang_overlap = tang_overlap_rotated0 + v_t * dt;
-                                                *tang_overlap0 = *tang_overlap;
-                                            }
-                                        } else {
-                                            // So the particles slide.
-                                            // Set the tangential force to the maximum force allowed
-                                            // by Couloumb force
-
-                                            // FIX ME: Change friction coefficient to sliding
-                                            f_t = mu * fn_norm * t_ij;
-
-                                            // Restrict the spring length such that the resultant
-                                            // tangential force equals Couloumb force
-                                            // Note: dt changes for different stages
-                                            if stage == 1 {
-                                                *tang_overlap = (f_t + 0.001 * v_t) / 1e4;
-                                            } else if stage == 2 {
-                                                // use the tangential overlap at time t i.e., tang_overlap0
-                                                // project it onto current orientaton, i.e., t + dt / 2
-                                                let hist0 = &mut dest.tang_history0[i];
-                                                let tang_overlap0 = hist0
-                                                    .get_mut(&srce.id)
-                                                    .unwrap()
-                                                    .get_mut(&j)
-                                                    .unwrap();
-                                                *tang_overlap = (f_t + 0.001 * v_t) / 1e4;
-                                                *tang_overlap0 = *tang_overlap;
-                                            }
-                                        }
-                                    }
-
-                                    // if it doesn't have particle index j, then
-                                    // add the particle to the history
-                                    false => {
-                                        // create tangential_overlap0 and tangential_overlap
-                                        let tang_overlap0 = V3::zero();
-
-                                        // Since this is first time contact, we will not
-                                        // have any tangential force
-                                        // ----------Skip force calculation-------------
-
-                                        // Increment spring to next time step
-
-                                        if stage == 1 {
-                                            let tang_overlap = v_t * dt;
-                                            hist.get_mut(&srce.id).unwrap().insert(j, tang_overlap);
-                                            let hist0 = &mut dest.tang_history0[i];
-                                            hist0
-                                                .get_mut(&srce.id)
-                                                .unwrap()
-                                                .insert(j, tang_overlap0);
-                                        } else if stage == 2 {
-                                            // use the tangential overlap at time t i.e., tang_overlap0
-                                            // since this is first time contact there won't
-                                            // be any force calculation
-                                            let tang_overlap = v_t * dt;
-                                            hist.get_mut(&srce.id).unwrap().insert(j, tang_overlap);
-                                            let hist0 = &mut dest.tang_history0[i];
-                                            // Note the difference between stage 1 and stage 2
-                                            hist0
-                                                .get_mut(&srce.id)
-                                                .unwrap()
-                                                .insert(j, tang_overlap);
-                                        }
-                                    }
-                                };
-                            }
-
-                            // if it doesn't have srce id, add the srce id
-                            false => {
-                                // add srce id as source to tangential history
-                                hist.insert(*srce.id, HashMap::new());
-
-                                // And also in the temporary history
-                                let hist0 = &mut dest.tang_history0[i];
-                                hist0.insert(*srce.id, HashMap::new());
-
-                                // ------------------------------------
-                                // now add the particle j in srce_d hashmap
-
-                                // create tangential_overlap0 and tangential_overlap
-                                let tang_overlap0 = V3::zero();
-
-                                // Since this is first time contact, we will not
-                                // have any tangential force
-                                // ----------Skip force calculation-------------
-
-                                // Increment spring to next time step
-
-                                if stage == 1 {
-                                    let tang_overlap = v_t * dt;
-                                    hist.get_mut(&srce.id).unwrap().insert(j, tang_overlap);
-                                    hist0.get_mut(&srce.id).unwrap().insert(j, tang_overlap0);
-                                } else if stage == 2 {
-                                    // use the tangential overlap at time t i.e., tang_overlap0
-                                    // since this is first time contact there won't
-                                    // be any force calculation
-                                    let tang_overlap = v_t * dt;
-                                    hist.get_mut(&srce.id).unwrap().insert(j, tang_overlap);
-                                    // Note the difference between stage 1 and stage 2
-                                    hist0.get_mut(&srce.id).unwrap().insert(j, tang_overlap);
-                                }
-                            }
-                        };
-                    }
-                    dest.fx[i] += f[0];
-                    dest.fy[i] += f[1];
-                }
-                // if they are not overlapping, remove the particle j of srce id
-                // from history of particle i
-                else {
-                    // Check for tangential contacts only if there is friction
-                    if mu != 0. {
-                        // get the history of all particles being tracked by
-                        // particle i
-                        let hist = &mut dest.tang_history[i];
-                        let hist0 = &mut dest.tang_history0[i];
-
-                        // -------------------------------------------------
-
-                        // Tangential overlap variable explanation
-                        // -------------------------------------------------
-
-                        // the variable above (hist) contains particles already in
-                        // overlap.
-
-                        // The particle i's neighbours looks like
-
-                        // hist = {'0': {'2': Vector3, '31': Vector3, '7': Vector3},
-                        //           '1': {'3': Vector3, '5': Vector3, '9': Vector3}}
-
-                        // The meaning of above format is, particle i is is already
-                        // contact with an entities with id's '0' and '1'.
-
-                        // diving little deep gives us the indices of those entites
-                        // as, particle of index '9' has neighbours [2, 31, 7] of
-                        // entity '0'. And also has neighbours [3, 5, 9] of entity
-                        // '1'.
-
-                        // So the type of hist would be
-                        // Vec<HashMap<usize, HashMap<usize, Vector3>>>
-                        // -------------------------------------------------
-                        // Tangential overlap variable explanation
-                        // -------------------------------------------------
-
-                        // Find the index j in hist of particle i with id of
-                        // srce.id
-
-                        // If j is already been tracked then remove it
-                        // If it is not been tracked then leave hist alone
-
-                        // Note: To do this operatio I am using match
-                        // match is provided by rust and it's awesome
-
-                        // this leaf is to check if the particle i has history
-                        // with the srce
-                        match hist.contains_key(&srce.id) {
-                            // If it has neighbours with srce, then
-                            // go ahead and check if it is tracking particle j
-                            true => {
-                                match hist[&srce.id].contains_key(&j) {
-                                    // If it has particle j
-                                    // remove it
-                                    true => {
-                                        hist.get_mut(&srce.id).unwrap().remove(&j);
-                                        hist0.get_mut(&srce.id).unwrap().remove(&j);
-                                    }
-
-                                    // if it doesn't have particle index j, then
-                                    // leave it alone
-                                    false => {}
-                                };
-                            }
-
-                            // if it doesn't have srce id, then leave it alone
-                            false => {}
-                        };
-                    }
-                }
-            }
-        }
-    }
+    let dest_particles = contact_particles!(dest);
+    let srce_particles = contact_particles!(srce);
+    let mut acc = contact_accumulator!(dest);
+    let pair = materials
+        .pair(dest_particles.material_id, srce_particles.material_id)
+        .expect("missing material of a contact");
+    let ctx = ContactContext {
+        law,
+        pair,
+        dt,
+        stage,
+    };
+
+    contact_force_dem(&dest_particles, &mut acc, &srce_particles, &ctx, grid, |_, _| false);
+}
+
+/// Contact force between particles of the same entity due to the given law.
+fn contact_force_dem_self<T>(
+    dst: &mut T,
+    law: ContactLaw,
+    materials: &MaterialDatabase,
+    dt: f32,
+    stage: usize,
+    grid: &LinkedListGrid,
+) where
+    T: DemDiscreteDstTrait,
+{
+    let dest = dst.get_parts_mut();
+    let particles = contact_particles!(dest);
+    let mut acc = contact_accumulator!(dest);
+    let pair = materials
+        .pair(particles.material_id, particles.material_id)
+        .expect("missing material of a contact");
+    let ctx = ContactContext {
+        law,
+        pair,
+        dt,
+        stage,
+    };
+
+    contact_force_dem(&particles, &mut acc, &particles, &ctx, grid, |i, j| i == j);
 }
 
 /// Linear dashpot model introduced by Cundall and Strack.
+///
+/// The contact properties of the pair are taken from `materials` using the
+/// material ids of the entities. The tangential stiffness is `kt_ratio * kn`.
+/// The normal and tangential damping coefficients are computed from the
+/// coefficient of restitution `en` and the effective mass of the colliding
+/// pair.
+///
+/// Rolling resistance with the rolling friction coefficient `mu_r` is applied
+/// according to the `rolling` model of the pair. The rolling dashpot uses the
+/// same damping ratio as the normal dashpot.
+pub fn linear_viscoelastic_model_dem_other<T, U>(
+    dst: &mut T,
+    src: &mut U,
+    materials: &MaterialDatabase,
+    dt: f32,
+    stage: usize,
+    grid: &LinkedListGrid,
+) where
+    T: DemDiscreteDstTrait,
+    U: DemDiscreteSrcTrait,
+{
+    let law = ContactLaw::LinearViscoelastic;
+    contact_force_dem_other(dst, src, law, materials, dt, stage, grid);
+}
+
+/// Linear dashpot model introduced by Cundall and Strack.
+///
+/// See `linear_viscoelastic_model_dem_other`.
 pub fn linear_viscoelastic_model_dem_self<T>(
     dst: &mut T,
-    kn: f32,
-    mu: f32,
+    materials: &MaterialDatabase,
     dt: f32,
     stage: usize,
     grid: &LinkedListGrid,
-    dim: usize
 ) where
     T: DemDiscreteDstTrait,
 {
-    let dest = dst.get_parts_mut();
+    let law = ContactLaw::LinearViscoelastic;
+    contact_force_dem_self(dst, law, materials, dt, stage, grid);
+}
 
-    // Select the neighbours function according to the dimention
-    for i in 0..*dest.len {
-        // position of particle i
-        let pos_i = V3::new(dest.x[i], dest.y[i], 0.);
-        // linear velocity of particle i
-        let vel_i = V3::new(dest.u[i], dest.v[i], 0.);
-        // angular velocity of particle i
-        let ang_vel_i = V3::new(0., 0., dest.omega_z[i]);
-
-        let nbrs = get_neighbours_ll([dest.x[i], dest.y[i], 0.], &grid, &dest.id);
-
-        for sub_view in nbrs {
-            // neighbour indices j
-            for &j in sub_view {
-                if i != j {
-                    // position of particle j in source
-                    let pos_j = V3::new(dest.x[j], dest.y[j], 0.);
-                    // velocity of particle j
-                    let vel_j = V3::new(dest.u[j], dest.v[j], 0.);
-                    // angular velocity of particle j
-                    let ang_vel_j = V3::new(0., 0., dest.omega_z[j]);
-
-                    // find the unit vector from i to j
-                    let dx = pos_i.x - pos_j.x;
-                    let dy = pos_i.y - pos_j.y;
-                    let dz = pos_i.z - pos_j.z;
-
-                    let distance = (dx.powf(2.) + dy.powf(2.) + dz.powf(2.)).sqrt();
-                    // radius sum
-                    let radsum = dest.rad[i] + dest.rad[j];
-
-                    // overlap amount
-                    let delta_n = radsum - distance;
-
-                    // check if particles are in overlap
-                    if delta_n > 0. {
-                        // Define the force variables, total, tangential, torsion
-                        let mut f = V3::zero();
-                        let mut f_t: V3<f32> = V3::zero();
-                        let mut f_r: V3<f32> = V3::zero();
-
-                        // normal vector
-                        let nij = unit_vector_from_dx(dx, dy, dz, distance);
-
-                        // relative velocity
-                        let v_ij = relative_velocity(
-                            vel_i,
-                            vel_j,
-                            ang_vel_i,
-                            ang_vel_j,
-                            nij,
-                            dest.rad[i],
-                            dest.rad[j],
-                        ); // this is vector
-
-                        // relative  normal velocity
-                        let v_n = v_ij.dot(nij) * nij; //this is vector
-                                                       // relative  tangential velocity
-                        let v_t = v_ij - v_n; //this is vector
-
-                        // ----------------------------------------------------
-                        // Normal force with damping
-                        // FIX ME: Need to use real coefficients
-                        let f_n = kn * delta_n * nij - v_n * 0.001;
-
-                        // Add normal force to total force with damping in normal direction
-                        f += f_n;
-
-                        // ----------------------------------------------------
-                        // ----------------Tangential force -------------------
-                        // Check for tangential contacts only if there is friction
-                        if mu != 0. {
-                            // If tangential forces are present
-                            // get the history of all particles being tracked by
-                            // particle i
-                            let hist = &mut dest.tang_history[i];
-
-                            // -------------------------------------------------
-                            // Tangential overlap variable explanation
-                            // -------------------------------------------------
-
-                            // the variable above (hist) contains particles already in
-                            // overlap.
-
-                            // The particle i's neighbours looks like
-
-                            // hist = {'0': {'2': Vector3, '31': Vector3, '7': Vector3},
-                            //           '1': {'3': Vector3, '5': Vector3, '9': Vector3}}
-
-                            // The meaning of above format is, particle i is is already
-                            // contact with an entities with id's '0' and '1'.
-
-                            // diving little deep gives us the indices of those entites
-                            // as, particle of index '9' has neighbours [2, 31, 7] of
-                            // entity '0'. And also has neighbours [3, 5, 9] of entity
-                            // '1'.
-
-                            // So the type of hist would be
-                            // Vec<HashMap<usize, HashMap<usize, Vector3>>>
-                            // -------------------------------------------------
-                            // Tangential overlap variable explanation
-                            // -------------------------------------------------
-
-                            // Find the index j in hist of particle i with id of
-                            // dest.id
-
-                            // If j is already been tracked then remove it
-                            // If it is not been tracked then leave hist alone
-
-                            // Note: To do this operatio I am using match
-                            // match is provided by rust and it's awesome
-
-                            // this leaf is to check if the particle i has history
-                            // with the dest entity
-                            match hist.contains_key(&dest.id) {
-                                // If it has neighbours with dest, then
-                                // go ahead and check if it is tracking particle j
-                                true => {
-                                    match hist[&dest.id].contains_key(&j) {
-                                        // If it already has particle j
-                                        // Then find the force due to its current
-                                        // tangential spring elongation
-                                        true => {
-                                            // Get the spring
-                                            let mut tang_overlap = hist.get_mut(&dest.id)
-                                                .unwrap()
-                                                .get_mut(&j)
-                                                .unwrap();
-
-                                            // Now project the spring onto current tangential plane
-                                            // http://www.piko.ovgu.de/piko_media/aktuelles/Siegen/LUDING2012PIKO_Contacts.pdf
-                                            let tang_overlap_rotated =
-                                                *tang_overlap - nij * (dot(*tang_overlap, nij));
-
-                                            // Find tangential test force from the rotated spring
-                                            let f_t0 = -1e4 * tang_overlap_rotated - 0.001 * v_t;
-                                            let f_t0_magn = f_t0.magnitude();
-
-                                            // find the tangential unit vector
-                                            let t_ij = if f_t0_magn > 0. {
-                                                f_t0 / f_t0.magnitude()
-                                            } else {
-                                                V3::zero()
-                                            };
-
-                                            // Check for sliding
-                                            let fn_norm = f_n.magnitude();
-                                            if f_t0_magn <= mu * fn_norm {
-                                                // Set the tangential force to test tangential
-                                                // force
-                                                f_t = f_t0;
-
-                                                // Increment the tangential spring
-                                                // for next time step
-                                                // Note: dt changes for different stages
-                                                if stage == 1 {
-                                                    *tang_overlap = tang_overlap_rotated + v_t * dt;
-                                                } else if stage == 2 {
-                                                    // use the tangential overlap at time t i.e., tang_overlap0
-                                                    // project it onto current orientaton, i.e., t + dt / 2
-                                                    let hist0 = &mut dest.tang_history0[i];
-                                                    let tang_overlap0 = hist0
-                                                        .get_mut(&dest.id)
-                                                        .unwrap()
-                                                        .get_mut(&j)
-                                                        .unwrap();
-                                                    let tang_overlap_rotated0 = *tang_overlap0
-                                                        - nij * (dot(*tang_overlap, nij));
-                                                    *tang_overlap =
-                                                        tang_overlap_rotated0 + v_t * dt;
-                                                    *tang_overlap0 = *tang_overlap;
-                                                }
-                                            } else {
-                                                // So the particles slide.
-                                                // Set the tangential force to the maximum force allowed
-                                                // by Couloumb force
-
-                                                // FIX ME: Change friction coefficient to sliding
-                                                f_t = mu * fn_norm * t_ij;
-
-                                                // Restrict the spring length such that the resultant
-                                                // tangential force equals Couloumb force
-                                                // Note: dt changes for different stages
-                                                if stage == 1 {
-                                                    *tang_overlap = (f_t + 0.001 * v_t) / 1e4;
-                                                } else if stage == 2 {
-                                                    // use the tangential overlap at time t i.e., tang_overlap0
-                                                    // project it onto current orientaton, i.e., t + dt / 2
-                                                    let hist0 = &mut dest.tang_history0[i];
-                                                    let tang_overlap0 = hist0
-                                                        .get_mut(&dest.id)
-                                                        .unwrap()
-                                                        .get_mut(&j)
-                                                        .unwrap();
-                                                    *tang_overlap = (f_t + 0.001 * v_t) / 1e4;
-                                                    *tang_overlap0 = *tang_overlap;
-                                                }
-                                            }
-                                        }
-
-                                        // if it doesn't have particle index j, then
-                                        // add the particle to the history
-                                        false => {
-                                            // create tangential_overlap0 and tangential_overlap
-                                            let tang_overlap0 = V3::zero();
-
-                                            // Since this is first time contact, we will not
-                                            // have any tangential force
-                                            // ----------Skip force calculation-------------
-
-                                            // Increment spring to next time step
-
-                                            if stage == 1 {
-                                                let tang_overlap = v_t * dt;
-                                                hist.get_mut(&dest.id)
-                                                    .unwrap()
-                                                    .insert(j, tang_overlap);
-                                                let hist0 = &mut dest.tang_history0[i];
-                                                hist0
-                                                    .get_mut(&dest.id)
-                                                    .unwrap()
-                                                    .insert(j, tang_overlap0);
-                                            } else if stage == 2 {
-                                                // use the tangential overlap at time t i.e., tang_overlap0
-                                                // since this is first time contact there won't
-                                                // be any force calculation
-                                                let tang_overlap = v_t * dt;
-                                                hist.get_mut(&dest.id)
-                                                    .unwrap()
-                                                    .insert(j, tang_overlap);
-                                                let hist0 = &mut dest.tang_history0[i];
-                                                // Note the difference between stage 1 and stage 2
-                                                hist0
-                                                    .get_mut(&dest.id)
-                                                    .unwrap()
-                                                    .insert(j, tang_overlap);
-                                            }
-                                        }
-                                    };
-                                }
-
-                                // if it doesn't have dest id, add the dest id
-                                false => {
-                                    // add dest id as source to tangential history
-                                    hist.insert(*dest.id, HashMap::new());
-
-                                    // And also in the temporary history
-                                    let hist0 = &mut dest.tang_history0[i];
-                                    hist0.insert(*dest.id, HashMap::new());
-
-                                    // ------------------------------------
-                                    // now add the particle j in dest_d hashmap
-
-                                    // create tangential_overlap0 and tangential_overlap
-                                    let tang_overlap0 = V3::zero();
-
-                                    // Since this is first time contact, we will not
-                                    // have any tangential force
-                                    // ----------Skip force calculation-------------
-
-                                    // Increment spring to next time step
-
-                                    if stage == 1 {
-                                        let tang_overlap = v_t * dt;
-                                        hist.get_mut(&dest.id).unwrap().insert(j, tang_overlap);
-                                        hist0.get_mut(&dest.id).unwrap().insert(j, tang_overlap0);
-                                    } else if stage == 2 {
-                                        // use the tangential overlap at time t i.e., tang_overlap0
-                                        // since this is first time contact there won't
-                                        // be any force calculation
-                                        let tang_overlap = v_t * dt;
-                                        hist.get_mut(&dest.id).unwrap().insert(j, tang_overlap);
-                                        // Note the difference between stage 1 and stage 2
-                                        hist0.get_mut(&dest.id).unwrap().insert(j, tang_overlap);
-                                    }
-                                }
-                            };
-                        }
-
-                        dest.fx[i] += f[0];
-                        dest.fy[i] += f[1];
-                    }
-                    // if they are not overlapping, remove the particle j of dest id
-                    // from history of particle i
-                    else {
-                        // Check for tangential contacts only if there is friction
-                        if mu != 0. {
-                            // get the history of all particles being tracked by
-                            // particle i
-                            let hist = &mut dest.tang_history[i];
-                            let hist0 = &mut dest.tang_history0[i];
-
-                            // -------------------------------------------------
-
-                            // Tangential overlap variable explanation
-                            // -------------------------------------------------
-
-                            // the variable above (hist) contains particles already in
-                            // overlap.
-
-                            // The particle i's neighbours looks like
-
-                            // hist = {'0': {'2': Vector3, '31': Vector3, '7': Vector3},
-                            //           '1': {'3': Vector3, '5': Vector3, '9': Vector3}}
-
-                            // The meaning of above format is, particle i is is already
-                            // contact with an entities with id's '0' and '1'.
-
-                            // diving little deep gives us the indices of those entites
-                            // as, particle of index '9' has neighbours [2, 31, 7] of
-                            // entity '0'. And also has neighbours [3, 5, 9] of entity
-                            // '1'.
-
-                            // So the type of hist would be
-                            // Vec<HashMap<usize, HashMap<usize, Vector3>>>
-                            // -------------------------------------------------
-                            // Tangential overlap variable explanation
-                            // -------------------------------------------------
-
-                            // Find the index j in hist of particle i with id of
-                            // dest.id
-
-                            // If j is already been tracked then remove it
-                            // If it is not been tracked then leave hist alone
-
-                            // Note: To do this operatio I am using match
-                            // match is provided by rust and it's awesome
-
-                            // this leaf is to check if the particle i has history
-                            // with the dest
-                            match hist.contains_key(&dest.id) {
-                                // If it has neighbours with dest, then
-                                // go ahead and check if it is tracking particle j
-                                true => {
-                                    match hist[&dest.id].contains_key(&j) {
-                                        // If it has particle j
-                                        // remove it
-                                        true => {
-                                            hist.get_mut(&dest.id).unwrap().remove(&j);
-                                            hist0.get_mut(&dest.id).unwrap().remove(&j);
-                                        }
-
-                                        // if it doesn't have particle index j, then
-                                        // leave it alone
-                                        false => {}
-                                    };
-                                }
-
-                                // if it doesn't have dest id, then leave it alone
-                                false => {}
-                            };
-                        }
-                    }
-                }
-            }
-        }
-    }
+/// Hertz-Mindlin nonlinear contact model.
+///
+/// The normal force follows Hertz theory
+///
+/// $F_n = \frac{4}{3} E^* \sqrt{R^*} \delta_n^{3/2}$
+///
+/// and the tangential spring has the Mindlin stiffness
+///
+/// $k_t = 8 G^* \sqrt{R^* \delta_n}$
+///
+/// where $E^*$, $G^*$ and $R^*$ are the effective Young's modulus, shear
+/// modulus and radius of the pair, taken from `materials`. The tangential
+/// spring is tracked in the tangential history, same as in the linear model.
+///
+/// Damping follows Tsuji et al. with the damping ratio derived from the
+/// coefficient of restitution of the pair.
+pub fn hertz_mindlin_model_dem_other<T, U>(
+    dst: &mut T,
+    src: &mut U,
+    materials: &MaterialDatabase,
+    dt: f32,
+    stage: usize,
+    grid: &LinkedListGrid,
+) where
+    T: DemDiscreteDstTrait,
+    U: DemDiscreteSrcTrait,
+{
+    let law = ContactLaw::HertzMindlin;
+    contact_force_dem_other(dst, src, law, materials, dt, stage, grid);
 }
 
-impl RK2 for DemDiscrete {
-    fn initialize(&mut self, dt: f32) {
-        for i in 0..self.x.len() {
-            self.x0[i] = self.x[i];
-            self.y0[i] = self.y[i];
-            self.u0[i] = self.u[i];
-            self.v0[i] = self.v[i];
-            self.omega_z0[i] = self.omega_z[i];
-        }
-    }
-    fn stage1(&mut self, dt: f32) {
-        let dtb2 = dt / 2.;
-        for i in 0..self.x.len() {
-            // propagate particles to next half time step
-            self.x[i] = self.x0[i] + self.u[i] * dtb2;
-            self.y[i] = self.y0[i] + self.v[i] * dtb2;
-            self.u[i] = self.u0[i] + self.fx[i] * self.m_inv[i] * dtb2;
-            self.v[i] = self.v0[i] + self.fy[i] * self.m_inv[i] * dtb2;
-            self.omega_z[i] = self.omega_z0[i] + self.tauz[i] * self.i_inv[i] * dtb2;
-        }
-    }
-    fn stage2(&mut self, dt: f32) {
-        for i in 0..self.x.len() {
-            // propagate particles to next time step
-            self.x[i] = self.x0[i] + self.u[i] * dt;
-            self.y[i] = self.y0[i] + self.v[i] * dt;
-            self.u[i] = self.u0[i] + self.fx[i] * self.m_inv[i] * dt;
-            self.v[i] = self.v0[i] + self.fy[i] * self.m_inv[i] * dt;
-            self.omega_z[i] = self.omega_z0[i] + self.tauz[i] * self.i_inv[i] * dt;
-        }
-    }
+/// Hertz-Mindlin nonlinear contact model between particles of the same
+/// entity. See `hertz_mindlin_model_dem_other`.
+pub fn hertz_mindlin_model_dem_self<T>(
+    dst: &mut T,
+    materials: &MaterialDatabase,
+    dt: f32,
+    stage: usize,
+    grid: &LinkedListGrid,
+) where
+    T: DemDiscreteDstTrait,
+{
+    let law = ContactLaw::HertzMindlin;
+    contact_force_dem_self(dst, law, materials, dt, stage, grid);
 }
 
+
 #[test]
 fn test_unit_vector() {}
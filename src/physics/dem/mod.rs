@@ -1,8 +1,11 @@
 #[macro_use]
 pub mod equations;
+#[cfg(test)]
+mod tests;
 
 // local imports
 use contact_search::{NNPSMutParts, NNPS};
+use integrate::{Integrate, IntegrateMutParts};
 use std::collections::HashMap;
 
 // external crate imports
@@ -32,8 +35,11 @@ pub struct DemDiscrete {
     pub tauz: Vec<f32>,
     pub id: usize,
     pub name: String,
+    pub material_id: usize,
     pub tang_history: Vec<HashMap<usize, HashMap<usize, Vector3<f32>>>>,
     pub tang_history0: Vec<HashMap<usize, HashMap<usize, Vector3<f32>>>>,
+    pub roll_history: Vec<HashMap<usize, HashMap<usize, f32>>>,
+    pub roll_history0: Vec<HashMap<usize, HashMap<usize, f32>>>,
 }
 
 impl DemDiscrete {
@@ -42,6 +48,7 @@ impl DemDiscrete {
             len,
             name,
             id,
+            material_id: 0,
             m: vec![0.; len],
             x: vec![0.; len],
             y: vec![0.; len],
@@ -63,6 +70,141 @@ impl DemDiscrete {
             tauz: vec![0.; len],
             tang_history: vec![HashMap::new(); len],
             tang_history0: vec![HashMap::new(); len],
+            roll_history: vec![HashMap::new(); len],
+            roll_history0: vec![HashMap::new(); len],
+        }
+    }
+
+    /// Append `no_particles` particles with zero properties, as in `new`,
+    /// and return the index of the first of them. The new particles have no
+    /// contact history.
+    pub fn add_particles(&mut self, no_particles: usize) -> usize {
+        let first = self.len;
+        let len = first + no_particles;
+        self.m.resize(len, 0.);
+        self.x.resize(len, 0.);
+        self.y.resize(len, 0.);
+        self.u.resize(len, 0.);
+        self.v.resize(len, 0.);
+        self.omega_z.resize(len, 0.);
+        self.x0.resize(len, 0.);
+        self.y0.resize(len, 0.);
+        self.u0.resize(len, 0.);
+        self.v0.resize(len, 0.);
+        self.omega_z0.resize(len, 0.);
+        self.inertia.resize(len, 0.);
+        self.h.resize(len, 0.);
+        self.m_inv.resize(len, 0.);
+        self.i_inv.resize(len, 0.);
+        self.rad.resize(len, 0.);
+        self.fx.resize(len, 0.);
+        self.fy.resize(len, 0.);
+        self.tauz.resize(len, 0.);
+        self.tang_history.resize(len, HashMap::new());
+        self.tang_history0.resize(len, HashMap::new());
+        self.roll_history.resize(len, HashMap::new());
+        self.roll_history0.resize(len, HashMap::new());
+        self.len = len;
+        first
+    }
+
+    /// Remove the particles with the given indices, and renumber the
+    /// remaining ones. The new index of every particle is returned, `None`
+    /// for the removed ones.
+    ///
+    /// The contact histories of this entity are renumbered, the histories of
+    /// the other entities have to be renumbered with `remap_contact_history`.
+    pub fn remove_particles(&mut self, remove: &[usize]) -> Vec<Option<usize>> {
+        let new_index = indices_after_removal(self.len, remove);
+        retain_particles(&mut self.m, &new_index);
+        retain_particles(&mut self.x, &new_index);
+        retain_particles(&mut self.y, &new_index);
+        retain_particles(&mut self.u, &new_index);
+        retain_particles(&mut self.v, &new_index);
+        retain_particles(&mut self.omega_z, &new_index);
+        retain_particles(&mut self.x0, &new_index);
+        retain_particles(&mut self.y0, &new_index);
+        retain_particles(&mut self.u0, &new_index);
+        retain_particles(&mut self.v0, &new_index);
+        retain_particles(&mut self.omega_z0, &new_index);
+        retain_particles(&mut self.inertia, &new_index);
+        retain_particles(&mut self.h, &new_index);
+        retain_particles(&mut self.m_inv, &new_index);
+        retain_particles(&mut self.i_inv, &new_index);
+        retain_particles(&mut self.rad, &new_index);
+        retain_particles(&mut self.fx, &new_index);
+        retain_particles(&mut self.fy, &new_index);
+        retain_particles(&mut self.tauz, &new_index);
+        retain_particles(&mut self.tang_history, &new_index);
+        retain_particles(&mut self.tang_history0, &new_index);
+        retain_particles(&mut self.roll_history, &new_index);
+        retain_particles(&mut self.roll_history0, &new_index);
+        self.len = self.x.len();
+
+        let id = self.id;
+        self.remap_contact_history(id, &new_index);
+        new_index
+    }
+
+    /// Renumber the partners from the entity `src_id` in the contact
+    /// histories, after particles have been removed from it.
+    pub fn remap_contact_history(&mut self, src_id: usize, new_index: &[Option<usize>]) {
+        remap_history(&mut self.tang_history, src_id, new_index);
+        remap_history(&mut self.tang_history0, src_id, new_index);
+        remap_history(&mut self.roll_history, src_id, new_index);
+        remap_history(&mut self.roll_history0, src_id, new_index);
+    }
+}
+
+/// New index of every particle of an entity of `len` particles after the
+/// particles `remove` have been removed, `None` for the removed ones.
+pub fn indices_after_removal(len: usize, remove: &[usize]) -> Vec<Option<usize>> {
+    let mut keep = vec![true; len];
+    for &i in remove {
+        keep[i] = false;
+    }
+    let mut next = 0;
+    keep.iter()
+        .map(|&keep| {
+            if keep {
+                next += 1;
+                Some(next - 1)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Remove the values of the removed particles from a per particle property.
+pub fn retain_particles<T>(values: &mut Vec<T>, new_index: &[Option<usize>]) {
+    let mut i = 0;
+    values.retain(|_| {
+        i += 1;
+        new_index[i - 1].is_some()
+    });
+}
+
+/// Renumber the keys of a map from partner particles, dropping the removed
+/// ones.
+pub fn remap_partners<V>(partners: &mut HashMap<usize, V>, new_index: &[Option<usize>]) {
+    let old: Vec<(usize, V)> = partners.drain().collect();
+    for (j, value) in old {
+        if let Some(j) = new_index[j] {
+            partners.insert(j, value);
+        }
+    }
+}
+
+/// Renumber the partners from the entity `src_id` in a contact history.
+pub fn remap_history<V>(
+    history: &mut [HashMap<usize, HashMap<usize, V>>],
+    src_id: usize,
+    new_index: &[Option<usize>],
+) {
+    for hist in history.iter_mut() {
+        if let Some(partners) = hist.get_mut(&src_id) {
+            remap_partners(partners, new_index);
         }
     }
 }
@@ -85,8 +227,11 @@ pub struct DemDiscreteDstStrkt<'a> {
     pub tauz: &'a mut Vec<f32>,
     pub id: &'a mut usize,
     pub name: &'a mut String,
+    pub material_id: &'a mut usize,
     pub tang_history: &'a mut Vec<HashMap<usize, HashMap<usize, Vector3<f32>>>>,
     pub tang_history0: &'a mut Vec<HashMap<usize, HashMap<usize, Vector3<f32>>>>,
+    pub roll_history: &'a mut Vec<HashMap<usize, HashMap<usize, f32>>>,
+    pub roll_history0: &'a mut Vec<HashMap<usize, HashMap<usize, f32>>>,
 }
 
 pub struct DemDiscreteSrcStrkt<'a> {
@@ -103,6 +248,7 @@ pub struct DemDiscreteSrcStrkt<'a> {
     pub rad: &'a mut Vec<f32>,
     pub id: &'a mut usize,
     pub name: &'a mut String,
+    pub material_id: &'a mut usize,
 }
 
 pub trait DemDiscreteDstTrait: NNPS {
@@ -136,8 +282,11 @@ macro_rules! impl_DemDiscreteDstTrait{
                     tauz: &mut self.tauz,
                     id: &mut self.id,
                     name: &mut self.name,
+                    material_id: &mut self.material_id,
                     tang_history: &mut self.tang_history,
                     tang_history0: &mut self.tang_history0,
+                    roll_history: &mut self.roll_history,
+                    roll_history0: &mut self.roll_history0,
                 }
             }
         }
@@ -163,6 +312,7 @@ macro_rules! impl_DemDiscreteSrcTrait{
                     rad: &mut self.rad,
                     id: &mut self.id,
                     name: &mut self.name,
+                    material_id: &mut self.material_id,
                 }
             }
         }
@@ -170,5 +320,6 @@ macro_rules! impl_DemDiscreteSrcTrait{
 }
 
 impl_nnps![DemDiscrete];
+impl_integrate![DemDiscrete];
 impl_DemDiscreteDstTrait![DemDiscrete];
 impl_DemDiscreteSrcTrait![DemDiscrete];
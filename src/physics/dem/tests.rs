@@ -0,0 +1,380 @@
+use super::equations::{
+    hertz_mindlin_model_dem_other, linear_viscoelastic_model_dem_other,
+    linear_viscoelastic_model_dem_self, make_forces_zero, set_disk_inertia_dem, RollingModel,
+};
+use super::DemDiscrete;
+use cm::Vector3;
+use contact_search::LinkedListGrid;
+use integrate::{integrate_initialize, integrate_stage1, integrate_stage2};
+use physics::material::{Material, MaterialDatabase};
+
+fn setup_particle_properties(part1: &mut DemDiscrete, x: Vec<f32>, y: Vec<f32>, h: f32, mass: f32) {
+    for i in 0..part1.len {
+        part1.x[i] = x[i];
+        part1.y[i] = y[i];
+        part1.h[i] = h;
+        part1.rad[i] = h;
+        part1.m[i] = mass;
+        part1.m_inv[i] = 1. / mass;
+    }
+}
+
+/// Database with a single material of Poisson ratio 0.3, whose linear
+/// stiffness equals its Young's modulus.
+fn single_material(
+    yng_m: f32,
+    en: f32,
+    mu: f32,
+    mu_r: f32,
+    rolling: RollingModel,
+) -> MaterialDatabase {
+    let mut material = Material::new("grain".to_string(), 1000., yng_m, 0.3);
+    material.en = en;
+    material.mu = mu;
+    material.mu_r = mu_r;
+
+    let mut materials = MaterialDatabase::new();
+    materials.add(material);
+    materials.rolling = rolling;
+    materials
+}
+
+#[test]
+fn test_linear_normal_force_on_overlapping_particles() {
+    // two particles of radius 0.5 placed at a distance of 0.9 from each other,
+    // so that they overlap by 0.1
+    let mut left = DemDiscrete::new(1, 0, "left".to_string());
+    let mut right = DemDiscrete::new(1, 1, "right".to_string());
+    setup_particle_properties(&mut left, vec![0.], vec![0.], 0.5, 1.);
+    setup_particle_properties(&mut right, vec![0.9], vec![0.], 0.5, 1.);
+
+    let grid = LinkedListGrid::new(&mut vec![&mut left, &mut right], 2.);
+    let materials = single_material(1e4, 1., 0., 0., RollingModel::None);
+    linear_viscoelastic_model_dem_other(
+        &mut left,
+        &mut right,
+        &materials,
+        1e-4,
+        1,
+        &grid,
+    );
+
+    // the left particle is pushed to the left
+    assert!((left.fx[0] + 1e4 * 0.1).abs() < 1e-1);
+    assert!(left.fy[0].abs() < 1e-6);
+}
+
+#[test]
+fn test_hertz_normal_force_on_overlapping_particles() {
+    let mut left = DemDiscrete::new(1, 0, "left".to_string());
+    let mut right = DemDiscrete::new(1, 1, "right".to_string());
+    setup_particle_properties(&mut left, vec![0.], vec![0.], 0.5, 1.);
+    setup_particle_properties(&mut right, vec![0.9], vec![0.], 0.5, 1.);
+
+    let (yng_m, poisson) = (1e6, 0.3);
+    let grid = LinkedListGrid::new(&mut vec![&mut left, &mut right], 2.);
+    let materials = single_material(yng_m, 1., 0., 0., RollingModel::None);
+    hertz_mindlin_model_dem_other(
+        &mut left,
+        &mut right,
+        &materials,
+        1e-4,
+        1,
+        &grid,
+    );
+
+    // F_n = 4 / 3 E* sqrt(R*) delta^(3 / 2)
+    let yng_eff = yng_m / (2. * (1. - poisson * poisson));
+    let rad_eff: f32 = 0.25;
+    let delta: f32 = 0.1;
+    let expected = 4. / 3. * yng_eff * rad_eff.sqrt() * delta.powf(1.5);
+    assert!((left.fx[0] + expected).abs() / expected < 1e-3);
+}
+
+#[test]
+fn test_hertz_tangential_history_is_tracked_during_contact() {
+    let mut left = DemDiscrete::new(1, 0, "left".to_string());
+    let mut right = DemDiscrete::new(1, 1, "right".to_string());
+    setup_particle_properties(&mut left, vec![0.], vec![0.], 0.5, 1.);
+    setup_particle_properties(&mut right, vec![0.9], vec![0.], 0.5, 1.);
+    // slide the left particle upwards
+    left.v[0] = 1.;
+
+    let grid = LinkedListGrid::new(&mut vec![&mut left, &mut right], 2.);
+    let materials = single_material(1e6, 0.9, 0.5, 0., RollingModel::None);
+    hertz_mindlin_model_dem_other(
+        &mut left,
+        &mut right,
+        &materials,
+        1e-4,
+        1,
+        &grid,
+    );
+    assert!(left.tang_history[0][&1].contains_key(&0));
+
+    // separate the particles, the history has to be removed
+    right.x[0] = 1.5;
+    let grid = LinkedListGrid::new(&mut vec![&mut left, &mut right], 2.);
+    hertz_mindlin_model_dem_other(
+        &mut left,
+        &mut right,
+        &materials,
+        1e-4,
+        1,
+        &grid,
+    );
+    assert!(!left.tang_history[0][&1].contains_key(&0));
+}
+
+#[test]
+fn test_restitution_of_head_on_collision() {
+    // two particles moving towards each other, the ratio of the relative
+    // velocities after and before the collision is the restitution
+    let mut grains = DemDiscrete::new(2, 0, "grains".to_string());
+    setup_particle_properties(&mut grains, vec![0., 1.1], vec![0., 0.], 0.5, 1.);
+    grains.u[0] = 1.;
+    grains.u[1] = -1.;
+
+    let (kn, en, dt) = (1e5, 0.5, 1e-5);
+    let materials = single_material(kn, en, 0., 0., RollingModel::None);
+    for _ in 0..20000 {
+        let grid = LinkedListGrid::new(&mut vec![&mut grains], 2.);
+        integrate_initialize(&mut vec![&mut grains], dt);
+
+        make_forces_zero(&mut grains);
+        linear_viscoelastic_model_dem_self(
+            &mut grains,
+            &materials,
+            dt,
+            1,
+            &grid,
+        );
+        integrate_stage1(&mut vec![&mut grains], dt);
+
+        make_forces_zero(&mut grains);
+        linear_viscoelastic_model_dem_self(
+            &mut grains,
+            &materials,
+            dt,
+            2,
+            &grid,
+        );
+        integrate_stage2(&mut vec![&mut grains], dt);
+    }
+    let rel_vel = grains.u[1] - grains.u[0];
+    assert!((rel_vel - 2. * en).abs() < 0.05 * 2. * en);
+}
+
+#[test]
+fn test_tangential_force_produces_torque() {
+    // the left particle slides upwards on the right particle, friction acts
+    // downwards at the contact point and rotates the left particle clockwise
+    let mut left = DemDiscrete::new(1, 0, "left".to_string());
+    let mut right = DemDiscrete::new(1, 1, "right".to_string());
+    setup_particle_properties(&mut left, vec![0.], vec![0.], 0.5, 1.);
+    setup_particle_properties(&mut right, vec![0.9], vec![0.], 0.5, 1.);
+    left.v[0] = 1.;
+
+    let grid = LinkedListGrid::new(&mut vec![&mut left, &mut right], 2.);
+    let materials = single_material(1e4, 0.5, 0.5, 0., RollingModel::None);
+    linear_viscoelastic_model_dem_other(
+        &mut left,
+        &mut right,
+        &materials,
+        1e-4,
+        1,
+        &grid,
+    );
+
+    assert!(left.fy[0] < 0.);
+    assert!(left.tauz[0] < 0.);
+    // torque is the tangential force times the radius
+    assert!((left.tauz[0] - 0.5 * left.fy[0]).abs() < 1e-4);
+
+    make_forces_zero(&mut left);
+    assert_eq!(left.tauz[0], 0.);
+}
+
+#[test]
+fn test_disk_inertia() {
+    let mut grains = DemDiscrete::new(1, 0, "grains".to_string());
+    setup_particle_properties(&mut grains, vec![0.], vec![0.], 0.5, 2.);
+    set_disk_inertia_dem(&mut grains);
+    assert!((grains.inertia[0] - 0.25).abs() < 1e-6);
+    assert!((grains.i_inv[0] - 4.).abs() < 1e-4);
+}
+
+#[test]
+fn test_constant_rolling_torque_opposes_rotation() {
+    let mut left = DemDiscrete::new(1, 0, "left".to_string());
+    let mut right = DemDiscrete::new(1, 1, "right".to_string());
+    setup_particle_properties(&mut left, vec![0.], vec![0.], 0.5, 1.);
+    setup_particle_properties(&mut right, vec![0.9], vec![0.], 0.5, 1.);
+    left.omega_z[0] = 1.;
+
+    let (kn, mu_r) = (1e4, 0.1);
+    let grid = LinkedListGrid::new(&mut vec![&mut left, &mut right], 2.);
+    let materials = single_material(kn, 1., 0., mu_r, RollingModel::ConstantTorque);
+    linear_viscoelastic_model_dem_other(
+        &mut left,
+        &mut right,
+        &materials,
+        1e-4,
+        1,
+        &grid,
+    );
+    // M_r = - mu_r R* F_n
+    let expected = -mu_r * 0.25 * kn * 0.1;
+    assert!((left.tauz[0] - expected).abs() < 1e-2);
+}
+
+#[test]
+fn test_elastic_plastic_rolling_torque_is_limited() {
+    let mut left = DemDiscrete::new(1, 0, "left".to_string());
+    let mut right = DemDiscrete::new(1, 1, "right".to_string());
+    setup_particle_properties(&mut left, vec![0.], vec![0.], 0.5, 1.);
+    setup_particle_properties(&mut right, vec![0.9], vec![0.], 0.5, 1.);
+    set_disk_inertia_dem(&mut left);
+    set_disk_inertia_dem(&mut right);
+    left.omega_z[0] = 1.;
+
+    let (kn, mu_r) = (1e4, 0.1);
+    let m_max = mu_r * 0.25 * kn * 0.1;
+    let grid = LinkedListGrid::new(&mut vec![&mut left, &mut right], 2.);
+    let materials = single_material(kn, 0.5, 0., mu_r, RollingModel::ElasticPlastic);
+    for stage in &[1, 2, 1, 2, 1, 2] {
+        left.tauz[0] = 0.;
+        linear_viscoelastic_model_dem_other(
+            &mut left,
+            &mut right,
+            &materials,
+            1.,
+            *stage,
+            &grid,
+        );
+        // the torque opposes the rotation and never exceeds the limit
+        assert!(left.tauz[0] < 0.);
+        assert!(left.tauz[0].abs() <= m_max + 1e-4);
+    }
+    // the spring is tracked in the rolling history and is fully mobilised
+    let spring = left.roll_history[0][&1][&0];
+    assert!((spring + m_max).abs() < 1e-4);
+}
+
+#[test]
+fn test_add_particles_keeps_arrays_consistent() {
+    let mut grains = DemDiscrete::new(2, 0, "grains".to_string());
+    grains.x = vec![1., 2.];
+    grains.tang_history[1].entry(0).or_default().insert(0, Vector3::new(1., 0., 0.));
+
+    assert_eq!(2, grains.add_particles(3));
+    assert_eq!(5, grains.len);
+    assert_eq!(vec![1., 2., 0., 0., 0.], grains.x);
+    for len in &[grains.m.len(), grains.x0.len(), grains.omega_z0.len(), grains.tauz.len()] {
+        assert_eq!(5, *len);
+    }
+    assert_eq!(5, grains.roll_history0.len());
+    // the history of the old particles is kept, the new ones have none
+    assert!(grains.tang_history[1][&0].contains_key(&0));
+    assert!(grains.tang_history[4].is_empty());
+}
+
+#[test]
+fn test_remove_particles_renumbers_contact_history() {
+    // particles 1 and 3 are in contact with particle 0 of another entity
+    let mut grains = DemDiscrete::new(4, 0, "grains".to_string());
+    let mut other = DemDiscrete::new(1, 1, "other".to_string());
+    for &j in &[1, 3] {
+        grains.tang_history[j].entry(1).or_default().insert(0, Vector3::new(1., 0., 0.));
+        other.tang_history[0].entry(0).or_default().insert(j, Vector3::new(j as f32, 0., 0.));
+    }
+    // contact between particles 2 and 3 of the same entity
+    grains.roll_history[3].entry(0).or_default().insert(2, 0.5);
+    grains.x = vec![0., 1., 2., 3.];
+
+    let new_index = grains.remove_particles(&[1, 2]);
+    other.remap_contact_history(grains.id, &new_index);
+
+    assert_eq!(vec![Some(0), None, None, Some(1)], new_index);
+    assert_eq!(2, grains.len);
+    assert_eq!(vec![0., 3.], grains.x);
+    assert_eq!(2, grains.tang_history.len());
+    // the history of the removed particles is purged, the rest renumbered
+    assert!(grains.tang_history[1][&1].contains_key(&0));
+    assert!(grains.roll_history[1][&0].is_empty());
+    let partners = &other.tang_history[0][&0];
+    assert_eq!(1, partners.len());
+    assert_eq!(3., partners[&1].x);
+}
+
+/// Overlapping particles on a perturbed lattice, moving in different
+/// directions.
+#[cfg(feature = "parallel")]
+fn lattice_grains() -> DemDiscrete {
+    let mut grains = DemDiscrete::new(400, 0, "grains".to_string());
+    for i in 0..400 {
+        grains.x[i] = 0.18 * (i % 20) as f32 + 0.01 * ((i * 7) % 5) as f32;
+        grains.y[i] = 0.18 * (i / 20) as f32 + 0.01 * ((i * 3) % 4) as f32;
+        grains.u[i] = 0.1 * ((i * 13) % 7) as f32 - 0.3;
+        grains.v[i] = 0.1 * ((i * 11) % 5) as f32 - 0.2;
+        grains.omega_z[i] = ((i * 5) % 3) as f32 - 1.;
+        grains.rad[i] = 0.1;
+        grains.h[i] = 0.1;
+        grains.m[i] = 1.;
+        grains.inertia[i] = 0.005;
+    }
+    grains
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn test_parallel_contact_force_matches_serial() {
+    use super::equations::{contact_force_dem, contact_force_dem_serial, ContactAccumulator,
+                           ContactContext, ContactLaw, ContactParticles};
+    use super::DemDiscreteDstTrait;
+
+    // with friction and rolling friction the contacts update the histories
+    let mut glass = Material::new("glass".to_string(), 2500., 1e7, 0.25);
+    glass.mu = 0.5;
+    glass.mu_r = 0.1;
+    let mut materials = MaterialDatabase::new();
+    materials.add(glass);
+    materials.rolling = RollingModel::ElasticPlastic;
+    let mut serial = lattice_grains();
+    let mut parallel = lattice_grains();
+    let grid = LinkedListGrid::new(&mut vec![&mut serial], 2.);
+
+    for law in &[ContactLaw::LinearViscoelastic, ContactLaw::HertzMindlin] {
+        for stage in 1..3 {
+            let ctx = ContactContext {
+                law: *law,
+                pair: materials.pair(0, 0).unwrap(),
+                dt: 1e-4,
+                stage,
+            };
+            for (grains, use_serial) in [(&mut serial, true), (&mut parallel, false)] {
+                let ent = grains.get_parts_mut();
+                let particles = contact_particles!(ent);
+                let mut acc = contact_accumulator!(ent);
+                if use_serial {
+                    contact_force_dem_serial(&particles, &mut acc, &particles, &ctx, &grid,
+                                             |i, j| i == j);
+                } else {
+                    contact_force_dem(&particles, &mut acc, &particles, &ctx, &grid,
+                                      |i, j| i == j);
+                }
+            }
+        }
+    }
+
+    assert!(serial.fx.iter().any(|&f| f != 0.));
+    assert!(serial.tang_history.iter().any(|hist| !hist.is_empty()));
+    assert!(serial.roll_history.iter().any(|hist| !hist.is_empty()));
+    assert_eq!(serial.fx, parallel.fx);
+    assert_eq!(serial.fy, parallel.fy);
+    assert_eq!(serial.tauz, parallel.tauz);
+    assert_eq!(serial.tang_history, parallel.tang_history);
+    assert_eq!(serial.tang_history0, parallel.tang_history0);
+    assert_eq!(serial.roll_history, parallel.roll_history);
+    assert_eq!(serial.roll_history0, parallel.roll_history0);
+}
@@ -0,0 +1,197 @@
+// local imports
+use physics::dem::equations::RollingModel;
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+/// Material of the particles of an entity.
+///
+/// Entities refer to a material by its id in a `MaterialDatabase`, which
+/// provides the contact properties of every pair of materials.
+#[derive(Clone, Debug)]
+pub struct Material {
+    pub name: String,
+    pub density: f32,
+    /// Young's modulus
+    pub yng_m: f32,
+    pub poisson: f32,
+    /// coefficient of restitution
+    pub en: f32,
+    /// sliding friction coefficient
+    pub mu: f32,
+    /// rolling friction coefficient
+    pub mu_r: f32,
+    /// normal stiffness used by the linear viscoelastic model. Per unit
+    /// thickness of a disk it is of the order of Young's modulus, which is
+    /// the default.
+    pub kn: f32,
+    /// ratio of tangential to normal stiffness used by the linear
+    /// viscoelastic model
+    pub kt_ratio: f32,
+}
+
+impl Material {
+    pub fn new(name: String, density: f32, yng_m: f32, poisson: f32) -> Self {
+        Material {
+            name,
+            density,
+            yng_m,
+            poisson,
+            en: 1.,
+            mu: 0.,
+            mu_r: 0.,
+            kn: yng_m,
+            kt_ratio: 2. / 7.,
+        }
+    }
+
+    /// Shear modulus of the material.
+    pub fn shear_m(&self) -> f32 {
+        self.yng_m / (2. * (1. + self.poisson))
+    }
+
+    /// Mass of a disk of unit thickness and radius `rad`.
+    pub fn disk_mass(&self, rad: f32) -> f32 {
+        self.density * PI * rad.powf(2.)
+    }
+}
+
+/// Contact properties of a pair of materials, consumed by the contact models.
+#[derive(Clone, Copy, Debug)]
+pub struct PairProperties {
+    /// normal stiffness of the linear viscoelastic model
+    pub kn: f32,
+    /// ratio of tangential to normal stiffness of the linear model
+    pub kt_ratio: f32,
+    /// effective Young's modulus of the Hertz-Mindlin model
+    pub yng_eff: f32,
+    /// effective shear modulus of the Hertz-Mindlin model
+    pub shear_eff: f32,
+    /// coefficient of restitution
+    pub en: f32,
+    /// sliding friction coefficient
+    pub mu: f32,
+    /// rolling friction coefficient
+    pub mu_r: f32,
+    pub rolling: RollingModel,
+}
+
+impl PairProperties {
+    /// Mix the properties of two materials.
+    ///
+    /// The effective moduli follow Hertz-Mindlin theory,
+    ///
+    /// $\frac{1}{E^*} = \frac{1 - \nu_i^2}{E_i} + \frac{1 - \nu_j^2}{E_j}$,
+    /// $\frac{1}{G^*} = \frac{2 - \nu_i}{G_i} + \frac{2 - \nu_j}{G_j}$,
+    ///
+    /// the linear stiffness is the harmonic mean of the two stiffnesses, and
+    /// the restitution, friction and stiffness ratio are the arithmetic means.
+    pub fn mix(mat_i: &Material, mat_j: &Material, rolling: RollingModel) -> Self {
+        let yng_eff = 1.
+            / ((1. - mat_i.poisson.powf(2.)) / mat_i.yng_m
+                + (1. - mat_j.poisson.powf(2.)) / mat_j.yng_m);
+        let shear_eff = 1.
+            / ((2. - mat_i.poisson) / mat_i.shear_m() + (2. - mat_j.poisson) / mat_j.shear_m());
+        PairProperties {
+            kn: 2. * mat_i.kn * mat_j.kn / (mat_i.kn + mat_j.kn),
+            kt_ratio: 0.5 * (mat_i.kt_ratio + mat_j.kt_ratio),
+            yng_eff,
+            shear_eff,
+            en: 0.5 * (mat_i.en + mat_j.en),
+            mu: 0.5 * (mat_i.mu + mat_j.mu),
+            mu_r: 0.5 * (mat_i.mu_r + mat_j.mu_r),
+            rolling,
+        }
+    }
+}
+
+/// Collection of materials and the interaction properties of their pairs.
+///
+/// The properties of a pair are mixed from the two materials, unless they
+/// are overridden with `set_pair`.
+pub struct MaterialDatabase {
+    pub materials: Vec<Material>,
+    /// rolling resistance model used for pairs which are not overridden
+    pub rolling: RollingModel,
+    overrides: HashMap<(usize, usize), PairProperties>,
+}
+
+impl MaterialDatabase {
+    pub fn new() -> Self {
+        MaterialDatabase {
+            materials: vec![],
+            rolling: RollingModel::None,
+            overrides: HashMap::new(),
+        }
+    }
+
+    /// Add a material and return its id, which is to be set as the
+    /// `material_id` of the entities made of it.
+    pub fn add(&mut self, material: Material) -> usize {
+        self.materials.push(material);
+        self.materials.len() - 1
+    }
+
+    /// Override the properties of the pair of materials `mat_i` and `mat_j`.
+    pub fn set_pair(&mut self, mat_i: usize, mat_j: usize, props: PairProperties) {
+        self.overrides.insert(Self::key(mat_i, mat_j), props);
+    }
+
+    /// Contact properties of the pair of materials `mat_i` and `mat_j`, or
+    /// `None` if the pair is not overridden and a material is missing.
+    pub fn pair(&self, mat_i: usize, mat_j: usize) -> Option<PairProperties> {
+        match self.overrides.get(&Self::key(mat_i, mat_j)) {
+            Some(props) => Some(*props),
+            None => {
+                let (mat_i, mat_j) = (self.materials.get(mat_i)?, self.materials.get(mat_j)?);
+                Some(PairProperties::mix(mat_i, mat_j, self.rolling))
+            }
+        }
+    }
+
+    fn key(mat_i: usize, mat_j: usize) -> (usize, usize) {
+        if mat_i < mat_j {
+            (mat_i, mat_j)
+        } else {
+            (mat_j, mat_i)
+        }
+    }
+}
+
+impl Default for MaterialDatabase {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[test]
+fn test_mixing_of_identical_materials() {
+    let mut glass = Material::new("glass".to_string(), 2500., 1e7, 0.3);
+    glass.en = 0.9;
+    glass.mu = 0.4;
+    let props = PairProperties::mix(&glass, &glass, RollingModel::None);
+
+    assert!((props.kn - glass.kn).abs() / glass.kn < 1e-6);
+    assert!((props.en - 0.9).abs() < 1e-6);
+    assert!((props.mu - 0.4).abs() < 1e-6);
+    let yng_eff = glass.yng_m / (2. * (1. - 0.3_f32.powf(2.)));
+    assert!((props.yng_eff - yng_eff).abs() / yng_eff < 1e-5);
+    let shear_eff = glass.shear_m() / (2. * (2. - 0.3));
+    assert!((props.shear_eff - shear_eff).abs() / shear_eff < 1e-5);
+}
+
+#[test]
+fn test_pair_override_is_symmetric() {
+    let mut db = MaterialDatabase::new();
+    let glass = db.add(Material::new("glass".to_string(), 2500., 1e7, 0.3));
+    let steel = db.add(Material::new("steel".to_string(), 7800., 2e8, 0.3));
+
+    let mut props = db.pair(glass, steel).unwrap();
+    assert!((props.mu - 0.).abs() < 1e-6);
+    props.mu = 0.7;
+    db.set_pair(steel, glass, props);
+    assert!((db.pair(glass, steel).unwrap().mu - 0.7).abs() < 1e-6);
+    assert!((db.pair(steel, glass).unwrap().mu - 0.7).abs() < 1e-6);
+    assert!((db.pair(glass, glass).unwrap().mu - 0.).abs() < 1e-6);
+    // a missing material has no contact properties
+    assert!(db.pair(glass, 2).is_none());
+}
@@ -2,3 +2,6 @@
 pub mod dem;
 
 pub mod bonded_dem;
+pub mod material;
+pub mod timestep;
+pub mod wall;
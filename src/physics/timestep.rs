@@ -0,0 +1,171 @@
+// local imports
+use physics::dem::DemDiscreteSrcTrait;
+use physics::material::MaterialDatabase;
+use std::f32::consts::PI;
+use std::fmt;
+
+/// Critical time steps of a set of entities, from the criteria commonly used
+/// in DEM. Explicit integration is stable only for time steps smaller than
+/// the critical one, scaled by a safety factor.
+#[derive(Clone, Copy, Debug)]
+pub struct TimeStepEstimate {
+    /// Spring-mass criterion, `2 sqrt(m_eff / kn)` of the stiffest contact of
+    /// the lightest particle, with `m_eff = m / 2` for two equal particles.
+    /// Governs the linear viscoelastic model.
+    pub spring_mass: f32,
+    /// Rayleigh criterion, the time taken by a Rayleigh wave to travel
+    /// across a particle, `pi R sqrt(rho / G) / (0.1631 nu + 0.8766)`.
+    /// Governs the Hertz-Mindlin model, whose stiffness grows with the
+    /// overlap.
+    pub rayleigh: f32,
+}
+
+impl TimeStepEstimate {
+    /// The smallest of the critical time steps.
+    pub fn critical(&self) -> f32 {
+        self.spring_mass.min(self.rayleigh)
+    }
+
+    /// Time step to be used in a simulation. The safety factor is usually
+    /// between 0.1 and 0.3, to account for particles with many contacts.
+    pub fn stable(&self, safety_factor: f32) -> f32 {
+        safety_factor * self.critical()
+    }
+}
+
+/// Estimate the critical time step of all the particles of `world`, with
+/// the contact properties from `materials`. Particles without mass are not
+/// integrated in time and are skipped. Returns `None` if the material of an
+/// entity is missing from `materials`.
+pub fn estimate_time_step<T: DemDiscreteSrcTrait + ?Sized>(
+    world: &mut Vec<&mut T>,
+    materials: &MaterialDatabase,
+) -> Option<TimeStepEstimate> {
+    let mut spring_mass = f32::MAX;
+    let mut rayleigh = f32::MAX;
+
+    for entity in world.iter_mut() {
+        let ent = entity.get_parts_mut();
+        let material = materials.materials.get(*ent.material_id)?;
+
+        // stiffest contact of this material with any material
+        let kn = (0..materials.materials.len())
+            .filter_map(|other| materials.pair(*ent.material_id, other))
+            .map(|pair| pair.kn)
+            .fold(0., f32::max);
+
+        let rho_g = material.density / material.shear_m();
+        let rayleigh_factor = PI / (0.1631 * material.poisson + 0.8766);
+
+        for i in 0..ent.m.len() {
+            if ent.m[i] <= 0. {
+                continue;
+            }
+            let m_eff = ent.m[i] / 2.;
+            spring_mass = spring_mass.min(2. * (m_eff / kn).sqrt());
+            rayleigh = rayleigh.min(rayleigh_factor * ent.rad[i] * rho_g.sqrt());
+        }
+    }
+
+    Some(TimeStepEstimate {
+        spring_mass,
+        rayleigh,
+    })
+}
+
+/// Action taken by `check_time_step` when the time step exceeds the
+/// stable one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimeStepCheck {
+    Ignore,
+    /// Print a warning to the standard error and continue.
+    Warn,
+    /// Return an error.
+    Error,
+}
+
+/// Time step larger than the stable time step.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimeStepError {
+    pub dt: f32,
+    pub stable: f32,
+}
+
+impl fmt::Display for TimeStepError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "time step {:e} exceeds the stable time step {:e}",
+            self.dt, self.stable
+        )
+    }
+}
+
+impl ::std::error::Error for TimeStepError {}
+
+/// Check that `dt` does not exceed the stable time step `stable`, see
+/// `TimeStepEstimate::stable`.
+pub fn check_time_step(dt: f32, stable: f32, check: TimeStepCheck) -> Result<(), TimeStepError> {
+    if dt <= stable {
+        return Ok(());
+    }
+    let error = TimeStepError { dt, stable };
+    match check {
+        TimeStepCheck::Ignore => Ok(()),
+        TimeStepCheck::Warn => {
+            eprintln!("warning: {}", error);
+            Ok(())
+        }
+        TimeStepCheck::Error => Err(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_time_step, estimate_time_step, TimeStepCheck};
+    use physics::dem::DemDiscrete;
+    use physics::material::{Material, MaterialDatabase};
+    use std::f32::consts::PI;
+
+    #[test]
+    fn test_spring_mass_and_rayleigh_time_steps() {
+        let mut grains = DemDiscrete::new(2, 0, "grains".to_string());
+        let mut materials = MaterialDatabase::new();
+        let material = Material::new("glass".to_string(), 2500., 1e7, 0.25);
+        let (rho, shear_m) = (material.density, material.shear_m());
+        materials.add(material);
+        for i in 0..2 {
+            grains.rad[i] = 0.1 * (i + 1) as f32;
+            grains.m[i] = materials.materials[0].disk_mass(grains.rad[i]);
+        }
+
+        let estimate = estimate_time_step(&mut vec![&mut grains], &materials).unwrap();
+
+        // the smallest particle limits the time step
+        let spring_mass = 2. * (grains.m[0] / 2. / 1e7).sqrt();
+        assert!((estimate.spring_mass - spring_mass).abs() / spring_mass < 1e-5);
+        let rayleigh = PI * 0.1 * (rho / shear_m).sqrt() / (0.1631 * 0.25 + 0.8766);
+        assert!((estimate.rayleigh - rayleigh).abs() / rayleigh < 1e-5);
+
+        assert_eq!(spring_mass.min(rayleigh), estimate.critical());
+        assert!((estimate.stable(0.2) - 0.2 * estimate.critical()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_no_estimate_without_the_material() {
+        let mut grains = DemDiscrete::new(1, 0, "grains".to_string());
+        grains.m[0] = 1.;
+        grains.rad[0] = 0.1;
+        let materials = MaterialDatabase::new();
+        assert!(estimate_time_step(&mut vec![&mut grains], &materials).is_none());
+    }
+
+    #[test]
+    fn test_check_time_step() {
+        assert!(check_time_step(1e-5, 1e-4, TimeStepCheck::Error).is_ok());
+        assert!(check_time_step(1e-3, 1e-4, TimeStepCheck::Warn).is_ok());
+        assert!(check_time_step(1e-3, 1e-4, TimeStepCheck::Ignore).is_ok());
+        let error = check_time_step(1e-3, 1e-4, TimeStepCheck::Error).unwrap_err();
+        assert_eq!((1e-3, 1e-4), (error.dt, error.stable));
+    }
+}
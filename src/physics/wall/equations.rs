@@ -0,0 +1,126 @@
+// local imports
+use super::Wall;
+use physics::dem::equations::{contact_force, relative_velocity, remove_contact_history,
+                              ContactContext, ContactLaw, ContactPoint, ParticleAccumulator};
+use physics::dem::DemDiscreteDstTrait;
+use physics::material::MaterialDatabase;
+
+// external crate imports
+use cm::{Vector3 as V3, Zero};
+
+pub fn make_forces_zero_wall(wall: &mut Wall) {
+    wall.fx = 0.;
+    wall.fy = 0.;
+}
+
+/// Contact force on the particles of `dst` due to a wall, with the given
+/// law.
+///
+/// The wall behaves as a particle of infinite radius and mass, so the
+/// effective radius and mass of a contact are those of the particle. The
+/// velocity of a moving wall is taken at the contact point. The
+/// contact properties of the pair are taken from `materials` using the
+/// material ids of the entity and the wall. The reaction of every contact
+/// is added to the force on the wall.
+pub fn contact_force_wall<T: DemDiscreteDstTrait>(
+    dst: &mut T,
+    wall: &mut Wall,
+    law: ContactLaw,
+    materials: &MaterialDatabase,
+    dt: f32,
+    stage: usize,
+) {
+    let dest = dst.get_parts_mut();
+    let pair = materials
+        .pair(*dest.material_id, wall.material_id)
+        .expect("missing material of a contact");
+    let ctx = ContactContext {
+        law,
+        pair,
+        dt,
+        stage,
+    };
+
+    for i in 0..dest.x.len() {
+        let pos_i = V3::new(dest.x[i], dest.y[i], 0.);
+        let vel_i = V3::new(dest.u[i], dest.v[i], 0.);
+        let ang_vel_i = V3::new(0., 0., dest.omega_z[i]);
+        let mut acc = ParticleAccumulator {
+            fx: &mut dest.fx[i],
+            fy: &mut dest.fy[i],
+            tauz: &mut dest.tauz[i],
+            tang_history: &mut dest.tang_history[i],
+            tang_history0: &mut dest.tang_history0[i],
+            roll_history: &mut dest.roll_history[i],
+            roll_history0: &mut dest.roll_history0[i],
+        };
+
+        for j in 0..wall.no_elements() {
+            let (nij, distance) = match wall.normal_and_distance(j, pos_i) {
+                Some(normal) => normal,
+                None => {
+                    remove_contact_history(&mut acc, wall.id, j);
+                    continue;
+                }
+            };
+            let delta_n = dest.rad[i] - distance;
+            if delta_n <= 0. {
+                remove_contact_history(&mut acc, wall.id, j);
+                continue;
+            }
+
+            let vel_j = wall.kinematics.velocity_at(pos_i - distance * nij);
+            let v_ij = relative_velocity(
+                vel_i,
+                vel_j,
+                ang_vel_i,
+                V3::zero(),
+                -nij,
+                dest.rad[i],
+                0.,
+            );
+            let contact = ContactPoint {
+                src_id: wall.id,
+                j,
+                nij,
+                delta_n,
+                v_ij,
+                w_r: ang_vel_i.z - wall.kinematics.omega,
+                rad_i: dest.rad[i],
+                rad_eff: dest.rad[i],
+                m_eff: dest.m[i],
+                i_r: dest.inertia[i] + dest.m[i] * dest.rad[i].powf(2.),
+            };
+            let f = contact_force(&mut acc, &contact, &ctx);
+            wall.fx -= f.x;
+            wall.fy -= f.y;
+        }
+    }
+}
+
+/// Linear dashpot model between the particles of `dst` and a wall.
+///
+/// See `linear_viscoelastic_model_dem_other` and `contact_force_wall`.
+pub fn linear_viscoelastic_model_wall<T: DemDiscreteDstTrait>(
+    dst: &mut T,
+    wall: &mut Wall,
+    materials: &MaterialDatabase,
+    dt: f32,
+    stage: usize,
+) {
+    contact_force_wall(dst, wall, ContactLaw::LinearViscoelastic, materials, dt, stage);
+}
+
+/// Hertz-Mindlin nonlinear contact model between the particles of `dst` and
+/// a wall.
+///
+/// See `hertz_mindlin_model_dem_other` and `contact_force_wall`.
+pub fn hertz_mindlin_model_wall<T: DemDiscreteDstTrait>(
+    dst: &mut T,
+    wall: &mut Wall,
+    materials: &MaterialDatabase,
+    dt: f32,
+    stage: usize,
+) {
+    contact_force_wall(dst, wall, ContactLaw::HertzMindlin, materials, dt, stage);
+}
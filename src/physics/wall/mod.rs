@@ -0,0 +1,230 @@
+pub mod equations;
+#[cfg(test)]
+mod tests;
+
+// local imports
+use motion::{Motion, RigidMotion};
+
+// external crate imports
+use cm::{dot, InnerSpace, Vector3 as V3};
+use std::f32::consts::PI;
+
+/// Geometry of an analytic wall. Points are in the xy plane, with a zero z
+/// component.
+#[derive(Clone, Debug, PartialEq)]
+pub enum WallShape {
+    /// Infinite line through `point`. The particles are on the side the
+    /// `normal` points to, and are pushed back to it when they cross the
+    /// line.
+    Line { point: V3<f32>, normal: V3<f32> },
+    /// Line segment from `start` to `end`, which is in contact on both its
+    /// sides.
+    Segment { start: V3<f32>, end: V3<f32> },
+    /// Arc of the circle with the given `centre` and `radius`, running
+    /// counter-clockwise from `start_angle` to `end_angle` in radians. It is
+    /// in contact on both its sides.
+    Arc {
+        centre: V3<f32>,
+        radius: f32,
+        start_angle: f32,
+        end_angle: f32,
+    },
+    /// Chain of segments through `points`. Every segment is an element of
+    /// the wall with its own contact history.
+    Polyline { points: Vec<V3<f32>> },
+}
+
+/// Analytic wall in contact with the particles of the entities, without
+/// the cost of neighbour search and the bumpiness of a wall made of
+/// particles.
+///
+/// The contact history of a particle with a wall is stored in the
+/// histories of the particle with the wall id as the entity id, so the ids
+/// of the walls and the entities must differ.
+///
+/// A wall with a prescribed motion is moved from its initial shape at every
+/// force evaluation, and its velocity enters the relative velocity of its
+/// contacts.
+pub struct Wall {
+    pub id: usize,
+    pub name: String,
+    pub shape: WallShape,
+    /// shape of the wall at rest, which is moved by the motion
+    shape0: WallShape,
+    pub motion: Option<Motion>,
+    /// displacement and velocity of the wall at the last call to `move_to`
+    pub kinematics: RigidMotion,
+    pub material_id: usize,
+    /// total force exerted by the particles on the wall, at the last force
+    /// evaluation
+    pub fx: f32,
+    pub fy: f32,
+}
+
+impl Wall {
+    pub fn new(shape: WallShape, id: usize, name: String) -> Self {
+        let shape = match shape {
+            WallShape::Line { point, normal } => WallShape::Line {
+                point,
+                normal: normal.normalize(),
+            },
+            shape => shape,
+        };
+        Wall {
+            id,
+            name,
+            shape0: shape.clone(),
+            shape,
+            motion: None,
+            kinematics: RigidMotion::rest(),
+            material_id: 0,
+            fx: 0.,
+            fy: 0.,
+        }
+    }
+
+    /// Give the wall a prescribed motion, starting from its current shape.
+    pub fn moving(mut self, motion: Motion) -> Self {
+        self.shape0 = self.shape.clone();
+        self.motion = Some(motion);
+        self
+    }
+
+    /// Move the wall to its position at time `t`, if it has a motion.
+    pub fn move_to(&mut self, t: f32) {
+        let kinematics = match self.motion {
+            Some(ref motion) => motion.at(t),
+            None => return,
+        };
+        self.shape = match self.shape0 {
+            WallShape::Line { point, normal } => WallShape::Line {
+                point: kinematics.position(point),
+                normal: kinematics.rotate(normal),
+            },
+            WallShape::Segment { start, end } => WallShape::Segment {
+                start: kinematics.position(start),
+                end: kinematics.position(end),
+            },
+            WallShape::Arc {
+                centre,
+                radius,
+                start_angle,
+                end_angle,
+            } => WallShape::Arc {
+                centre: kinematics.position(centre),
+                radius,
+                start_angle: start_angle + kinematics.angle,
+                end_angle: end_angle + kinematics.angle,
+            },
+            WallShape::Polyline { ref points } => WallShape::Polyline {
+                points: points.iter().map(|&p| kinematics.position(p)).collect(),
+            },
+        };
+        self.kinematics = kinematics;
+    }
+
+    /// Number of elements of the wall, which are the segments of a polyline
+    /// and the whole wall otherwise.
+    pub fn no_elements(&self) -> usize {
+        match self.shape {
+            WallShape::Polyline { ref points } => points.len().saturating_sub(1),
+            _ => 1,
+        }
+    }
+
+    /// Normal passing from element `j` of the wall to the point `pos`, and
+    /// the distance of the point from the element along the normal, which
+    /// is negative for points behind a line.
+    ///
+    /// There is no normal for points on a segment or an arc, or at the
+    /// centre of an arc. A point close to a corner of a polyline is only
+    /// attributed to one of the segments meeting there.
+    pub fn normal_and_distance(&self, j: usize, pos: V3<f32>) -> Option<(V3<f32>, f32)> {
+        match self.shape {
+            WallShape::Line { point, normal } => Some((normal, dot(pos - point, normal))),
+            WallShape::Segment { start, end } => {
+                let t = segment_parameter(start, end, pos);
+                from_closest_point(start + t * (end - start), pos)
+            }
+            WallShape::Arc {
+                centre,
+                radius,
+                start_angle,
+                end_angle,
+            } => from_closest_point(
+                closest_point_on_arc(centre, radius, start_angle, end_angle, pos)?,
+                pos,
+            ),
+            WallShape::Polyline { ref points } => {
+                let (start, end) = (points[j], points[j + 1]);
+                let t = segment_parameter(start, end, pos);
+                // a point closest to a corner belongs to the segment before
+                // it, unless its projection falls inside the next segment
+                if t <= 0. && j > 0 && segment_parameter(points[j - 1], start, pos) > 0. {
+                    return None;
+                }
+                if t >= 1. && j + 2 < points.len() {
+                    let t_next = segment_parameter(end, points[j + 2], pos);
+                    if t_next > 0. && t_next < 1. {
+                        return None;
+                    }
+                }
+                from_closest_point(start + t * (end - start), pos)
+            }
+        }
+    }
+}
+
+/// Parameter of the point of the segment from `start` to `end` closest to
+/// `pos`, between 0 at the start and 1 at the end.
+fn segment_parameter(start: V3<f32>, end: V3<f32>, pos: V3<f32>) -> f32 {
+    let edge = end - start;
+    let length_sq = edge.magnitude2();
+    if length_sq == 0. {
+        return 0.;
+    }
+    (dot(pos - start, edge) / length_sq).clamp(0., 1.)
+}
+
+/// Point of an arc closest to `pos`, which is either the projection on the
+/// circle or one of the ends of the arc.
+fn closest_point_on_arc(
+    centre: V3<f32>,
+    radius: f32,
+    start_angle: f32,
+    end_angle: f32,
+    pos: V3<f32>,
+) -> Option<V3<f32>> {
+    let r = pos - centre;
+    if r.magnitude2() == 0. {
+        return None;
+    }
+    let on_circle = |angle: f32| centre + radius * V3::new(angle.cos(), angle.sin(), 0.);
+
+    let span = if end_angle - start_angle >= 2. * PI {
+        2. * PI
+    } else {
+        (end_angle - start_angle).rem_euclid(2. * PI)
+    };
+    let angle = r.y.atan2(r.x);
+    if (angle - start_angle).rem_euclid(2. * PI) <= span {
+        Some(on_circle(angle))
+    } else {
+        let (start, end) = (on_circle(start_angle), on_circle(end_angle));
+        if (pos - start).magnitude2() <= (pos - end).magnitude2() {
+            Some(start)
+        } else {
+            Some(end)
+        }
+    }
+}
+
+fn from_closest_point(closest: V3<f32>, pos: V3<f32>) -> Option<(V3<f32>, f32)> {
+    let r = pos - closest;
+    let distance = r.magnitude();
+    if distance == 0. {
+        None
+    } else {
+        Some((r / distance, distance))
+    }
+}
@@ -0,0 +1,172 @@
+use super::equations::{linear_viscoelastic_model_wall, make_forces_zero_wall};
+use super::{Wall, WallShape};
+use motion::Motion;
+use cm::Vector3;
+use physics::dem::DemDiscrete;
+use physics::material::{Material, MaterialDatabase};
+use std::f32::consts::PI;
+
+fn point(x: f32, y: f32) -> Vector3<f32> {
+    Vector3::new(x, y, 0.)
+}
+
+fn assert_close(expected: Vector3<f32>, found: Vector3<f32>) {
+    assert!((expected - found).x.abs() < 1e-5 && (expected - found).y.abs() < 1e-5);
+}
+
+#[test]
+fn test_line_is_one_sided() {
+    let line = WallShape::Line {
+        point: point(0., 1.),
+        normal: point(0., 2.),
+    };
+    let wall = Wall::new(line, 10, "floor".to_string());
+    let (normal, distance) = wall.normal_and_distance(0, point(3., 1.45)).unwrap();
+    assert_close(point(0., 1.), normal);
+    assert!((distance - 0.45).abs() < 1e-6);
+
+    // a particle which crossed the line is pushed back
+    let (normal, distance) = wall.normal_and_distance(0, point(3., 0.9)).unwrap();
+    assert_close(point(0., 1.), normal);
+    assert!((distance + 0.1).abs() < 1e-6);
+}
+
+#[test]
+fn test_segment_and_arc_closest_points() {
+    let segment = WallShape::Segment {
+        start: point(0., 0.),
+        end: point(2., 0.),
+    };
+    let wall = Wall::new(segment, 10, "segment".to_string());
+    let (normal, distance) = wall.normal_and_distance(0, point(1., -0.5)).unwrap();
+    assert_close(point(0., -1.), normal);
+    assert!((distance - 0.5).abs() < 1e-6);
+    // beyond the ends the closest point is the end
+    let (normal, distance) = wall.normal_and_distance(0, point(3., 1.)).unwrap();
+    assert_close(point(1., 1.) / 2_f32.sqrt(), normal);
+    assert!((distance - 2_f32.sqrt()).abs() < 1e-6);
+    assert!(wall.normal_and_distance(0, point(1., 0.)).is_none());
+
+    // quarter of the unit circle in the first quadrant
+    let arc = WallShape::Arc {
+        centre: point(0., 0.),
+        radius: 1.,
+        start_angle: 0.,
+        end_angle: PI / 2.,
+    };
+    let wall = Wall::new(arc, 11, "arc".to_string());
+    let (normal, distance) = wall.normal_and_distance(0, point(2., 2.)).unwrap();
+    assert_close(point(1., 1.) / 2_f32.sqrt(), normal);
+    assert!((distance - (8_f32.sqrt() - 1.)).abs() < 1e-5);
+    // inside the circle the normal points to the centre
+    let (normal, distance) = wall.normal_and_distance(0, point(0., 0.5)).unwrap();
+    assert_close(point(0., -1.), normal);
+    assert!((distance - 0.5).abs() < 1e-6);
+    // outside the span of the arc the closest point is an end
+    let (normal, distance) = wall.normal_and_distance(0, point(1., -1.)).unwrap();
+    assert_close(point(0., -1.), normal);
+    assert!((distance - 1.).abs() < 1e-6);
+}
+
+#[test]
+fn test_polyline_corners() {
+    let polyline = WallShape::Polyline {
+        points: vec![point(-1., 0.), point(0., 0.), point(0., -1.)],
+    };
+    let wall = Wall::new(polyline, 10, "corner".to_string());
+    assert_eq!(2, wall.no_elements());
+
+    // outside the convex corner the particle touches the corner once
+    let pos = point(0.3, 0.3);
+    assert!(wall.normal_and_distance(0, pos).is_some());
+    assert!(wall.normal_and_distance(1, pos).is_none());
+
+    // inside the corner the particle touches both segments
+    let pos = point(-0.3, -0.2);
+    let (normal, distance) = wall.normal_and_distance(0, pos).unwrap();
+    assert_close(point(0., -1.), normal);
+    assert!((distance - 0.2).abs() < 1e-6);
+    let (normal, distance) = wall.normal_and_distance(1, pos).unwrap();
+    assert_close(point(-1., 0.), normal);
+    assert!((distance - 0.3).abs() < 1e-6);
+
+    // above the first segment, the corner belongs to the first segment
+    let pos = point(-0.3, 0.2);
+    assert!(wall.normal_and_distance(0, pos).is_some());
+    assert!(wall.normal_and_distance(1, pos).is_none());
+}
+
+#[test]
+fn test_contact_force_and_reaction_of_a_wall() {
+    // a particle of radius 0.5 overlapping a floor by 0.1, sliding along it,
+    // the tangential dashpot resists the sliding at the first step
+    let mut particle = DemDiscrete::new(1, 0, "particle".to_string());
+    particle.x[0] = 0.;
+    particle.y[0] = 0.4;
+    particle.u[0] = 1.;
+    particle.rad[0] = 0.5;
+    particle.m[0] = 1.;
+    particle.inertia[0] = 0.125;
+
+    let mut materials = MaterialDatabase::new();
+    let mut grain = Material::new("grain".to_string(), 1000., 1e4, 0.3);
+    grain.mu = 0.5;
+    grain.en = 0.5;
+    let glass = grain.clone();
+    materials.add(grain);
+    let glass = materials.add(glass);
+
+    let floor = WallShape::Segment {
+        start: point(-1., 0.),
+        end: point(1., 0.),
+    };
+    let mut wall = Wall::new(floor, 10, "floor".to_string());
+    wall.material_id = glass;
+    linear_viscoelastic_model_wall(&mut particle, &mut wall, &materials, 1e-4, 1);
+
+    // pushed up and slowed down by friction
+    assert!((particle.fy[0] - 1e4 * 0.1).abs() < 1e-1);
+    assert!(particle.fx[0] < 0.);
+    assert!(particle.tang_history[0][&wall.id].contains_key(&0));
+    // the reaction on the wall
+    assert_eq!((-particle.fx[0], -particle.fy[0]), (wall.fx, wall.fy));
+
+    // the history is removed when the particle leaves the wall
+    particle.y[0] = 0.6;
+    make_forces_zero_wall(&mut wall);
+    linear_viscoelastic_model_wall(&mut particle, &mut wall, &materials, 1e-4, 1);
+    assert!(!particle.tang_history[0][&wall.id].contains_key(&0));
+    assert_eq!((0., 0.), (wall.fx, wall.fy));
+}
+
+#[test]
+fn test_rotating_wall() {
+    // half of a drum of radius 2 rotating counter-clockwise about its centre
+    let arc = WallShape::Arc {
+        centre: point(1., 1.),
+        radius: 2.,
+        start_angle: PI,
+        end_angle: 2. * PI,
+    };
+    let motion = Motion::Rotation {
+        pivot: point(1., 1.),
+        omega: 1.,
+    };
+    let mut wall = Wall::new(arc, 10, "drum".to_string()).moving(motion);
+    wall.move_to(PI / 2.);
+    match wall.shape {
+        WallShape::Arc {
+            centre,
+            start_angle,
+            end_angle,
+            ..
+        } => {
+            assert_close(point(1., 1.), centre);
+            assert!((start_angle - 1.5 * PI).abs() < 1e-6);
+            assert!((end_angle - 2.5 * PI).abs() < 1e-6);
+        }
+        _ => unreachable!(),
+    }
+    // the wall at the bottom of the drum moves to the right
+    assert_close(point(2., 0.), wall.kinematics.velocity_at(point(1., -1.)));
+}
@@ -0,0 +1,85 @@
+/// Free surface of a granular bed, as the top of the highest particle in
+/// each of `no_bins` vertical bins of equal width between `x_min` and
+/// `x_max`. Returns the centre of every bin which holds a particle, with the
+/// height of its surface.
+pub fn free_surface(
+    x: &[f32],
+    y: &[f32],
+    rad: &[f32],
+    x_min: f32,
+    x_max: f32,
+    no_bins: usize,
+) -> Vec<(f32, f32)> {
+    let width = (x_max - x_min) / no_bins as f32;
+    let mut top: Vec<Option<f32>> = vec![None; no_bins];
+    for i in 0..x.len() {
+        if x[i] < x_min || x[i] >= x_max {
+            continue;
+        }
+        let bin = (((x[i] - x_min) / width) as usize).min(no_bins - 1);
+        let height = y[i] + rad[i];
+        top[bin] = Some(top[bin].map_or(height, |h| h.max(height)));
+    }
+    top.iter()
+        .enumerate()
+        .filter_map(|(bin, &h)| h.map(|h| (x_min + (bin as f32 + 0.5) * width, h)))
+        .collect()
+}
+
+/// Dynamic angle of repose of the bed in a rotating drum, in radians.
+///
+/// A least squares line is fitted to the free surface between
+/// `centre_x - half_width` and `centre_x + half_width`, away from the walls
+/// of the drum. The angle is positive when the surface rises towards
+/// positive x, as in a drum rotating counter-clockwise. Returns `None` when
+/// fewer than two bins hold particles.
+pub fn angle_of_repose(
+    x: &[f32],
+    y: &[f32],
+    rad: &[f32],
+    centre_x: f32,
+    half_width: f32,
+    no_bins: usize,
+) -> Option<f32> {
+    let surface = free_surface(x, y, rad, centre_x - half_width, centre_x + half_width, no_bins);
+    if surface.len() < 2 {
+        return None;
+    }
+    let n = surface.len() as f32;
+    let x_mean = surface.iter().map(|&(x, _)| x).sum::<f32>() / n;
+    let h_mean = surface.iter().map(|&(_, h)| h).sum::<f32>() / n;
+    let (mut sxh, mut sxx) = (0., 0.);
+    for &(x, h) in &surface {
+        sxh += (x - x_mean) * (h - h_mean);
+        sxx += (x - x_mean) * (x - x_mean);
+    }
+    Some((sxh / sxx).atan())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{angle_of_repose, free_surface};
+    use std::f32::consts::PI;
+
+    #[test]
+    fn test_free_surface_of_inclined_bed() {
+        // two layers of particles on a slope of 30 degrees
+        let slope = (PI / 6.).tan();
+        let (mut x, mut y) = (vec![], vec![]);
+        for i in 0..20 {
+            let xi = -1. + 0.1 * i as f32 + 0.05;
+            x.extend_from_slice(&[xi, xi]);
+            y.extend_from_slice(&[slope * xi - 0.1, slope * xi]);
+        }
+        let rad = vec![0.05; x.len()];
+
+        let surface = free_surface(&x, &y, &rad, -1., 1., 20);
+        assert_eq!(20, surface.len());
+        assert!((surface[0].0 + 0.95).abs() < 1e-6);
+        assert!((surface[0].1 - (slope * -0.95 + 0.05)).abs() < 1e-6);
+
+        let angle = angle_of_repose(&x, &y, &rad, 0., 0.5, 5).unwrap();
+        assert!((angle - PI / 6.).abs() < 1e-4);
+        assert!(angle_of_repose(&x, &y, &rad, 5., 0.5, 5).is_none());
+    }
+}
@@ -1,3 +1,4 @@
+use super::physics::bonded_dem::DemBonded;
 use super::physics::dem::DemDiscrete;
 use std::fs;
 use std::fs::File;
@@ -36,102 +37,109 @@ pub trait DumpData {
     fn write_vtk_xml(&self, &str, usize);
 }
 
-impl DumpData for DemDiscrete {
-    fn save_data(&self, output_folder_name: &str, time_step_number: usize) {
-        let file_name = format!(
-            "{}/{}_{}.vtk",
-            output_folder_name, self.name, time_step_number
-        );
-
-        // create the file
-        let mut file = File::create(file_name).expect("Could not create file!");
-        writeln!(&mut file, "# vtk DataFile Version 2.0").unwrap();
-        writeln!(&mut file, "Data values of grains").unwrap();
-        writeln!(&mut file, "ASCII").unwrap();
-        writeln!(&mut file, "").unwrap();
-        writeln!(&mut file, "DATASET POLYDATA").unwrap();
-
-        // write header of positions of the entity
-        let np = self.x.len();
-        writeln!(&mut file, "POINTS {} float", np).unwrap();
-
-        // write the positions
-        for i in 0..np {
-            writeln!(&mut file, "{} {} {}", self.x[i], self.y[i], 0.).unwrap();
-        }
-        // write diameter
-        writeln!(&mut file, "Point_DATA {}", np).unwrap();
-        writeln!(&mut file, "SCALARS Diameter float 1").unwrap();
-        writeln!(&mut file, "LOOKUP_TABLE default").unwrap();
-        for i in 0..np {
-            writeln!(&mut file, "{}", 2. * self.rad[i]).unwrap();
-        }
-        // write mass
-        writeln!(&mut file, "SCALARS Mass float 1").unwrap();
-        writeln!(&mut file, "LOOKUP_TABLE default").unwrap();
-        for i in 0..np {
-            writeln!(&mut file, "{}", self.m[i]).unwrap();
-        }
-        // write velocity
-        writeln!(&mut file, "VECTORS Velocity float").unwrap();
-        for i in 0..np {
-            writeln!(&mut file, "{} {} {}", self.u[i], self.v[i], 0.).unwrap();
-        }
-        // write forces
-        writeln!(&mut file, "VECTORS Force float").unwrap();
-        for i in 0..np {
-            writeln!(&mut file, "{} {} {}", self.fx[i], self.fy[i], 0.).unwrap();
-        }
-    }
+// writes the particles of an entity as vtk poly data
+macro_rules! impl_dump_data{
+    ($($t:ty)*) => ($(
+        impl DumpData for $t {
+            fn save_data(&self, output_folder_name: &str, time_step_number: usize) {
+                let file_name = format!(
+                    "{}/{}_{}.vtk",
+                    output_folder_name, self.name, time_step_number
+                );
 
-    fn write_vtk_xml(&self, output_folder_name: &str, time_step_number: usize) {
-        let file_name = format!(
-            "{}/{}_{}.vtu",
-            output_folder_name, self.name, time_step_number
-        );
-
-        // create the file
-        let mut file = File::create(file_name).expect("Could not create file!");
-        writeln!(&mut file, "# vtk DataFile Version 2.0").unwrap();
-        writeln!(&mut file, "Data values of grains").unwrap();
-        writeln!(&mut file, "ASCII").unwrap();
-        writeln!(&mut file, "").unwrap();
-        writeln!(&mut file, "DATASET POLYDATA").unwrap();
-
-        // write header of positions of the entity
-        let np = self.x.len();
-        writeln!(&mut file, "POINTS {} float", np).unwrap();
-
-        // write the positions
-        for i in 0..np {
-            writeln!(&mut file, "{} {} 0", self.x[i], self.y[i]).unwrap();
-        }
-        // write diameter
-        writeln!(&mut file, "Point_DATA {}", np).unwrap();
-        writeln!(&mut file, "SCALARS Diameter float 1").unwrap();
-        writeln!(&mut file, "LOOKUP_TABLE default").unwrap();
-        for i in 0..np {
-            writeln!(&mut file, "{}", 2. * self.rad[i]).unwrap();
-        }
-        // write mass
-        writeln!(&mut file, "SCALARS Mass float 1").unwrap();
-        writeln!(&mut file, "LOOKUP_TABLE default").unwrap();
-        for i in 0..np {
-            writeln!(&mut file, "{}", self.m[i]).unwrap();
-        }
-        // write velocity
-        writeln!(&mut file, "VECTORS Velocity float").unwrap();
-        for i in 0..np {
-            writeln!(&mut file, "{} {} 0.0", self.u[i], self.v[i]).unwrap();
-        }
-        // write forces
-        writeln!(&mut file, "VECTORS Force float").unwrap();
-        for i in 0..np {
-            writeln!(&mut file, "{} {} 0.0", self.fx[i], self.fy[i]).unwrap();
+                // create the file
+                let mut file = File::create(file_name).expect("Could not create file!");
+                writeln!(&mut file, "# vtk DataFile Version 2.0").unwrap();
+                writeln!(&mut file, "Data values of grains").unwrap();
+                writeln!(&mut file, "ASCII").unwrap();
+                writeln!(&mut file, "").unwrap();
+                writeln!(&mut file, "DATASET POLYDATA").unwrap();
+
+                // write header of positions of the entity
+                let np = self.x.len();
+                writeln!(&mut file, "POINTS {} float", np).unwrap();
+
+                // write the positions
+                for i in 0..np {
+                    writeln!(&mut file, "{} {} {}", self.x[i], self.y[i], 0.).unwrap();
+                }
+                // write diameter
+                writeln!(&mut file, "Point_DATA {}", np).unwrap();
+                writeln!(&mut file, "SCALARS Diameter float 1").unwrap();
+                writeln!(&mut file, "LOOKUP_TABLE default").unwrap();
+                for i in 0..np {
+                    writeln!(&mut file, "{}", 2. * self.rad[i]).unwrap();
+                }
+                // write mass
+                writeln!(&mut file, "SCALARS Mass float 1").unwrap();
+                writeln!(&mut file, "LOOKUP_TABLE default").unwrap();
+                for i in 0..np {
+                    writeln!(&mut file, "{}", self.m[i]).unwrap();
+                }
+                // write velocity
+                writeln!(&mut file, "VECTORS Velocity float").unwrap();
+                for i in 0..np {
+                    writeln!(&mut file, "{} {} {}", self.u[i], self.v[i], 0.).unwrap();
+                }
+                // write forces
+                writeln!(&mut file, "VECTORS Force float").unwrap();
+                for i in 0..np {
+                    writeln!(&mut file, "{} {} {}", self.fx[i], self.fy[i], 0.).unwrap();
+                }
+            }
+
+            fn write_vtk_xml(&self, output_folder_name: &str, time_step_number: usize) {
+                let file_name = format!(
+                    "{}/{}_{}.vtu",
+                    output_folder_name, self.name, time_step_number
+                );
+
+                // create the file
+                let mut file = File::create(file_name).expect("Could not create file!");
+                writeln!(&mut file, "# vtk DataFile Version 2.0").unwrap();
+                writeln!(&mut file, "Data values of grains").unwrap();
+                writeln!(&mut file, "ASCII").unwrap();
+                writeln!(&mut file, "").unwrap();
+                writeln!(&mut file, "DATASET POLYDATA").unwrap();
+
+                // write header of positions of the entity
+                let np = self.x.len();
+                writeln!(&mut file, "POINTS {} float", np).unwrap();
+
+                // write the positions
+                for i in 0..np {
+                    writeln!(&mut file, "{} {} 0", self.x[i], self.y[i]).unwrap();
+                }
+                // write diameter
+                writeln!(&mut file, "Point_DATA {}", np).unwrap();
+                writeln!(&mut file, "SCALARS Diameter float 1").unwrap();
+                writeln!(&mut file, "LOOKUP_TABLE default").unwrap();
+                for i in 0..np {
+                    writeln!(&mut file, "{}", 2. * self.rad[i]).unwrap();
+                }
+                // write mass
+                writeln!(&mut file, "SCALARS Mass float 1").unwrap();
+                writeln!(&mut file, "LOOKUP_TABLE default").unwrap();
+                for i in 0..np {
+                    writeln!(&mut file, "{}", self.m[i]).unwrap();
+                }
+                // write velocity
+                writeln!(&mut file, "VECTORS Velocity float").unwrap();
+                for i in 0..np {
+                    writeln!(&mut file, "{} {} 0.0", self.u[i], self.v[i]).unwrap();
+                }
+                // write forces
+                writeln!(&mut file, "VECTORS Force float").unwrap();
+                for i in 0..np {
+                    writeln!(&mut file, "{} {} 0.0", self.fx[i], self.fy[i]).unwrap();
+                }
+            }
         }
-    }
+    )*)
 }
 
+impl_dump_data![DemDiscrete DemBonded];
+
 pub fn dump_output<T: DumpData>(
     entities: &mut Vec<&mut T>,
     time_step_number: usize,
@@ -141,3 +149,20 @@ pub fn dump_output<T: DumpData>(
         entity.save_data(&dir_name, time_step_number);
     }
 }
+
+/// Write the breakage events of a bonded entity as comma separated values,
+/// one broken bond per line.
+pub fn write_breakage_events(entity: &DemBonded, output_folder_name: &str) {
+    let file_name = format!("{}/{}_breakage.csv", output_folder_name, entity.name);
+
+    // create the file
+    let mut file = File::create(file_name).expect("Could not create file!");
+    writeln!(&mut file, "time,i,j,mode,x,y").unwrap();
+    for event in &entity.breakage_events {
+        writeln!(
+            &mut file,
+            "{},{},{},{:?},{},{}",
+            event.time, event.i, event.j, event.mode, event.x, event.y
+        ).unwrap();
+    }
+}
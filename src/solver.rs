@@ -0,0 +1,1396 @@
+// local imports
+use contact_search::{Domain, LinkedListGrid, NNPSMutParts, VerletGrid, NNPS};
+use inlet::Inlet;
+use integrate::{advance_stage, initialize_step, wrap_positions, Integrate, IntegrateMutParts,
+                Integrator};
+use motion::Motion;
+use outlet::Outlet;
+use physics::bonded_dem::equations::{body_force_bonded_dem, break_bonds_bonded_dem,
+                                     hertz_mindlin_model_bonded_dem_self,
+                                     internal_force_bonded_dem,
+                                     linear_viscoelastic_model_bonded_dem_self,
+                                     make_forces_zero_bonded_dem};
+use physics::bonded_dem::{BondProperties, DemBonded};
+use physics::dem::equations::{body_force_dem, hertz_mindlin_model_dem_other,
+                              hertz_mindlin_model_dem_self, linear_viscoelastic_model_dem_other,
+                              linear_viscoelastic_model_dem_self, make_forces_zero, ContactLaw};
+use physics::dem::{DemDiscrete, DemDiscreteSrcStrkt, DemDiscreteSrcTrait};
+use physics::material::MaterialDatabase;
+use physics::timestep::{check_time_step, estimate_time_step, TimeStepCheck, TimeStepError};
+use physics::wall::equations::{contact_force_wall, make_forces_zero_wall};
+use physics::wall::Wall;
+use save_data::{create_output_directory, dump_output, write_breakage_events, DumpData};
+
+// external crate imports
+use cm::Vector3 as V3;
+
+/// An entity owned by the solver.
+pub enum Entity {
+    Discrete(DemDiscrete),
+    Bonded(DemBonded),
+}
+
+impl From<DemDiscrete> for Entity {
+    fn from(entity: DemDiscrete) -> Self {
+        Entity::Discrete(entity)
+    }
+}
+
+impl From<DemBonded> for Entity {
+    fn from(entity: DemBonded) -> Self {
+        Entity::Bonded(entity)
+    }
+}
+
+impl Entity {
+    pub fn name(&self) -> &str {
+        match *self {
+            Entity::Discrete(ref ent) => &ent.name,
+            Entity::Bonded(ref ent) => &ent.name,
+        }
+    }
+
+    pub fn as_discrete(&self) -> Option<&DemDiscrete> {
+        match *self {
+            Entity::Discrete(ref ent) => Some(ent),
+            _ => None,
+        }
+    }
+
+    pub fn as_discrete_mut(&mut self) -> Option<&mut DemDiscrete> {
+        match *self {
+            Entity::Discrete(ref mut ent) => Some(ent),
+            _ => None,
+        }
+    }
+
+    pub fn as_bonded(&self) -> Option<&DemBonded> {
+        match *self {
+            Entity::Bonded(ref ent) => Some(ent),
+            _ => None,
+        }
+    }
+
+    pub fn as_bonded_mut(&mut self) -> Option<&mut DemBonded> {
+        match *self {
+            Entity::Bonded(ref mut ent) => Some(ent),
+            _ => None,
+        }
+    }
+
+    pub fn id(&self) -> usize {
+        match *self {
+            Entity::Discrete(ref ent) => ent.id,
+            Entity::Bonded(ref ent) => ent.id,
+        }
+    }
+
+    /// Remove particles from the entity, see `DemDiscrete::remove_particles`.
+    pub fn remove_particles(&mut self, remove: &[usize]) -> Vec<Option<usize>> {
+        match *self {
+            Entity::Discrete(ref mut ent) => ent.remove_particles(remove),
+            Entity::Bonded(ref mut ent) => ent.remove_particles(remove),
+        }
+    }
+
+    pub fn remap_contact_history(&mut self, src_id: usize, new_index: &[Option<usize>]) {
+        match *self {
+            Entity::Discrete(ref mut ent) => ent.remap_contact_history(src_id, new_index),
+            Entity::Bonded(ref mut ent) => ent.remap_contact_history(src_id, new_index),
+        }
+    }
+
+    fn make_forces_zero(&mut self) {
+        match *self {
+            Entity::Discrete(ref mut ent) => make_forces_zero(ent),
+            Entity::Bonded(ref mut ent) => make_forces_zero_bonded_dem(ent),
+        }
+    }
+}
+
+/// Remove the particles with the given indices from the entity `k` of
+/// `entities`, and renumber the contact histories of all the entities which
+/// refer to them. The new index of every particle of the entity is returned,
+/// `None` for the removed ones.
+///
+/// The entity must not be a moving entity of a solver, whose boundary motion
+/// refers to the particles by their original indices.
+pub fn remove_particles(
+    entities: &mut [Entity],
+    k: usize,
+    remove: &[usize],
+) -> Vec<Option<usize>> {
+    let id = entities[k].id();
+    let new_index = entities[k].remove_particles(remove);
+    for (l, entity) in entities.iter_mut().enumerate() {
+        if l != k {
+            entity.remap_contact_history(id, &new_index);
+        }
+    }
+    new_index
+}
+
+impl NNPS for Entity {
+    fn get_parts_mut_nnps(&mut self) -> NNPSMutParts {
+        match *self {
+            Entity::Discrete(ref mut ent) => ent.get_parts_mut_nnps(),
+            Entity::Bonded(ref mut ent) => ent.get_parts_mut_nnps(),
+        }
+    }
+    fn get_x(&self) -> &Vec<f32> {
+        match *self {
+            Entity::Discrete(ref ent) => &ent.x,
+            Entity::Bonded(ref ent) => &ent.x,
+        }
+    }
+    fn get_y(&self) -> &Vec<f32> {
+        match *self {
+            Entity::Discrete(ref ent) => &ent.y,
+            Entity::Bonded(ref ent) => &ent.y,
+        }
+    }
+}
+
+impl Integrate for Entity {
+    fn get_parts_mut_integrate(&mut self) -> IntegrateMutParts {
+        match *self {
+            Entity::Discrete(ref mut ent) => ent.get_parts_mut_integrate(),
+            Entity::Bonded(ref mut ent) => ent.get_parts_mut_integrate(),
+        }
+    }
+}
+
+impl DemDiscreteSrcTrait for Entity {
+    fn get_parts_mut(&mut self) -> DemDiscreteSrcStrkt {
+        match *self {
+            Entity::Discrete(ref mut ent) => DemDiscreteSrcTrait::get_parts_mut(ent),
+            Entity::Bonded(ref mut ent) => DemDiscreteSrcTrait::get_parts_mut(ent),
+        }
+    }
+}
+
+impl DumpData for Entity {
+    fn save_data(&self, output_folder_name: &str, time_step_number: usize) {
+        match *self {
+            Entity::Discrete(ref ent) => ent.save_data(output_folder_name, time_step_number),
+            Entity::Bonded(ref ent) => ent.save_data(output_folder_name, time_step_number),
+        }
+    }
+    fn write_vtk_xml(&self, output_folder_name: &str, time_step_number: usize) {
+        match *self {
+            Entity::Discrete(ref ent) => ent.write_vtk_xml(output_folder_name, time_step_number),
+            Entity::Bonded(ref ent) => ent.write_vtk_xml(output_folder_name, time_step_number),
+        }
+    }
+}
+
+/// A force computed at every force evaluation of a time step. Entities are
+/// referred to by their index in the solver.
+#[derive(Clone, Debug)]
+pub enum Equation {
+    /// Uniform body force, such as gravity, on the particles of an entity.
+    BodyForce { entity: usize, gx: f32, gy: f32 },
+    /// Contact between the particles of an entity. The particles of a
+    /// bonded entity which are bonded to each other are not in contact.
+    SelfContact { entity: usize, law: ContactLaw },
+    /// Contact force on the particles of `dst` due to the particles of
+    /// `src`. Add the reverse equation for the reaction on `src`.
+    Contact {
+        dst: usize,
+        src: usize,
+        law: ContactLaw,
+    },
+    /// Parallel bond forces of a bonded entity. The bonds are broken at the
+    /// end of every time step according to the breakage criterion.
+    Bonds { entity: usize, props: BondProperties },
+    /// Contact force on the particles of an entity due to a wall, referred
+    /// to by its index in the solver. The reaction is added to the force on
+    /// the wall.
+    WallContact {
+        entity: usize,
+        wall: usize,
+        law: ContactLaw,
+    },
+}
+
+/// Action taken on the particles which leave the fixed domain of a solver.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutOfDomain {
+    /// Move the particles back onto the boundary of the domain, and stop
+    /// their motion out of it.
+    Clamp,
+    /// Remove the particles from their entities, renumbering the contact
+    /// histories of all the entities. The particles of fixed and moving
+    /// entities are reported instead, see `Report`.
+    Delete,
+    /// Leave the particles out of the contact search, list them in
+    /// `Solver::outside` and print a warning when particles leave.
+    Report,
+}
+
+/// Time, time step and number of the current time step, passed to the hooks.
+#[derive(Clone, Copy, Debug)]
+pub struct StepInfo {
+    pub t: f32,
+    pub dt: f32,
+    pub time_step_number: usize,
+}
+
+pub type Hook = Box<dyn FnMut(&mut [Entity], &StepInfo)>;
+
+/// Prescribed motion of a boundary entity, with the positions of its
+/// particles at rest.
+struct BoundaryMotion {
+    entity: usize,
+    motion: Motion,
+    x0: Vec<f32>,
+    y0: Vec<f32>,
+}
+
+/// Owns the entities of a simulation and advances them in time.
+///
+/// Every time step the neighbour grid is updated, the forces are computed
+/// from the `equations` in the order they were added, at every force
+/// evaluation of the `integrator`, and the entities which are not fixed are
+/// advanced in time.
+pub struct Solver<I: Integrator> {
+    pub entities: Vec<Entity>,
+    /// entities which are not advanced in time, such as static boundaries
+    fixed: Vec<bool>,
+    /// fixed entities which move with a prescribed motion
+    boundary_motions: Vec<BoundaryMotion>,
+    pub walls: Vec<Wall>,
+    /// inlets pouring particles into the entities at the start of every
+    /// time step
+    pub inlets: Vec<Inlet>,
+    /// outlets removing particles from the entities at the start of every
+    /// time step, before the inlets
+    pub outlets: Vec<Outlet>,
+    pub equations: Vec<Equation>,
+    pub integrator: I,
+    pub materials: MaterialDatabase,
+    pub dt: f32,
+    pub tf: f32,
+    pub t: f32,
+    pub time_step_number: usize,
+    /// the entities are written every `output_frequency` time steps
+    output_frequency: usize,
+    /// no output is written if not set
+    pub output_folder: Option<String>,
+    /// scale of the largest particle size giving the neighbour grid cell size
+    pub scale: f32,
+    /// skin distance of the neighbour grid, which is rebuilt only when a
+    /// particle moves by more than half the skin. With no skin the grid is
+    /// rebuilt whenever a particle moves.
+    pub skin: f32,
+    grid: Option<VerletGrid>,
+    /// fixed domain of the neighbour grid. If not set the grid covers all
+    /// the particles and is resized whenever it is rebuilt. Particles
+    /// leaving a periodic domain are moved back in at every stage.
+    pub domain: Option<Domain>,
+    /// action taken on the particles outside the domain
+    pub out_of_domain: OutOfDomain,
+    /// entity and particle indices of the particles outside the domain, at
+    /// the beginning of the last time step
+    pub outside: Vec<(usize, usize)>,
+    /// action taken by `run` when `dt` exceeds the stable time step
+    pub time_step_check: TimeStepCheck,
+    /// fraction of the critical time step giving the stable time step
+    pub safety_factor: f32,
+    pre_step: Vec<Hook>,
+    post_step: Vec<Hook>,
+    on_output: Vec<Hook>,
+}
+
+impl<I: Integrator> Solver<I> {
+    pub fn new(integrator: I, materials: MaterialDatabase, dt: f32, tf: f32) -> Self {
+        Solver {
+            entities: vec![],
+            fixed: vec![],
+            boundary_motions: vec![],
+            walls: vec![],
+            inlets: vec![],
+            outlets: vec![],
+            equations: vec![],
+            integrator,
+            materials,
+            dt,
+            tf,
+            t: 0.,
+            time_step_number: 0,
+            output_frequency: 100,
+            output_folder: None,
+            scale: 2.,
+            skin: 0.,
+            grid: None,
+            domain: None,
+            out_of_domain: OutOfDomain::Report,
+            outside: vec![],
+            time_step_check: TimeStepCheck::Ignore,
+            safety_factor: 0.2,
+            pre_step: vec![],
+            post_step: vec![],
+            on_output: vec![],
+        }
+    }
+
+    /// Write the entities every `output_frequency` time steps.
+    pub fn set_output_frequency(&mut self, output_frequency: usize) {
+        assert!(output_frequency > 0, "the output frequency has to be positive");
+        self.output_frequency = output_frequency;
+    }
+
+    /// Add an entity which is advanced in time, returning its index. The id
+    /// of the entity has to differ from the ids of the walls.
+    pub fn add_entity<E: Into<Entity>>(&mut self, entity: E) -> usize {
+        self.push_entity(entity.into(), false)
+    }
+
+    /// Add an entity which does not move, returning its index.
+    pub fn add_fixed_entity<E: Into<Entity>>(&mut self, entity: E) -> usize {
+        self.push_entity(entity.into(), true)
+    }
+
+    // the contact histories of the particles are keyed by the ids of both
+    // the entities and the walls, which thus have to be distinct
+    fn push_entity(&mut self, entity: Entity, fixed: bool) -> usize {
+        assert!(
+            self.walls.iter().all(|wall| wall.id != entity.id()),
+            "entity id {} is already used by a wall",
+            entity.id()
+        );
+        self.entities.push(entity);
+        self.fixed.push(fixed);
+        self.entities.len() - 1
+    }
+
+    /// Add a boundary entity which is not advanced in time but moves with a
+    /// prescribed motion, such as a vibrating bed or a hopper gate, returning
+    /// its index. The particles are moved rigidly from their current
+    /// positions, and given the velocity of the motion.
+    pub fn add_moving_entity<E: Into<Entity>>(&mut self, entity: E, motion: Motion) -> usize {
+        let index = self.add_fixed_entity(entity);
+        let (x0, y0) = {
+            let ent = self.entities[index].get_parts_mut_integrate();
+            (ent.x.clone(), ent.y.clone())
+        };
+        self.boundary_motions.push(BoundaryMotion {
+            entity: index,
+            motion,
+            x0,
+            y0,
+        });
+        index
+    }
+
+    /// Move the walls and the boundary entities with a prescribed motion to
+    /// their positions at time `t`.
+    fn move_boundaries(&mut self, t: f32) {
+        for wall in self.walls.iter_mut() {
+            wall.move_to(t);
+        }
+        for boundary in &self.boundary_motions {
+            let kinematics = boundary.motion.at(t);
+            let ent = self.entities[boundary.entity].get_parts_mut_integrate();
+            for i in 0..ent.x.len() {
+                let pos = kinematics.position(V3::new(boundary.x0[i], boundary.y0[i], 0.));
+                let vel = kinematics.velocity_at(pos);
+                ent.x[i] = pos.x;
+                ent.y[i] = pos.y;
+                ent.u[i] = vel.x;
+                ent.v[i] = vel.y;
+                ent.omega_z[i] = kinematics.omega;
+            }
+        }
+    }
+
+    /// Add a wall, returning its index. The id of the wall has to differ
+    /// from the ids of the entities.
+    pub fn add_wall(&mut self, wall: Wall) -> usize {
+        assert!(
+            self.entities.iter().all(|entity| entity.id() != wall.id),
+            "wall id {} is already used by an entity",
+            wall.id
+        );
+        self.walls.push(wall);
+        self.walls.len() - 1
+    }
+
+    /// Add an inlet, returning its index. Particles can only be inserted into
+    /// discrete entities advanced in time.
+    pub fn add_inlet(&mut self, inlet: Inlet) -> usize {
+        assert!(
+            self.entities[inlet.entity].as_discrete().is_some(),
+            "an inlet inserts particles into a discrete entity"
+        );
+        assert!(
+            !self.fixed[inlet.entity],
+            "an inlet can't insert particles into a fixed or moving entity"
+        );
+        self.inlets.push(inlet);
+        self.inlets.len() - 1
+    }
+
+    /// Add an outlet, returning its index. Only the particles of entities
+    /// advanced in time can be removed.
+    pub fn add_outlet(&mut self, outlet: Outlet) -> usize {
+        assert!(
+            !self.fixed[outlet.entity],
+            "an outlet can't remove the particles of a fixed or moving entity"
+        );
+        self.outlets.push(outlet);
+        self.outlets.len() - 1
+    }
+
+    pub fn add_equation(&mut self, equation: Equation) {
+        self.equations.push(equation);
+    }
+
+    /// Hook called at the beginning of every time step.
+    pub fn add_pre_step<F: FnMut(&mut [Entity], &StepInfo) + 'static>(&mut self, hook: F) {
+        self.pre_step.push(Box::new(hook));
+    }
+
+    /// Hook called at the end of every time step.
+    pub fn add_post_step<F: FnMut(&mut [Entity], &StepInfo) + 'static>(&mut self, hook: F) {
+        self.post_step.push(Box::new(hook));
+    }
+
+    /// Hook called every time the output is written, even if there is no
+    /// output folder.
+    pub fn add_on_output<F: FnMut(&mut [Entity], &StepInfo) + 'static>(&mut self, hook: F) {
+        self.on_output.push(Box::new(hook));
+    }
+
+    pub fn step_info(&self) -> StepInfo {
+        StepInfo {
+            t: self.t,
+            dt: self.dt,
+            time_step_number: self.time_step_number,
+        }
+    }
+
+    /// Advance all the entities by a single time step.
+    pub fn step(&mut self) {
+        let info = self.step_info();
+        for hook in self.pre_step.iter_mut() {
+            hook(&mut self.entities, &info);
+        }
+
+        let (dt, t) = (self.dt, self.t);
+        let no_stages = self.integrator.force_evaluations();
+        // the grid refers to the particles by their indices, which change
+        // whenever particles are removed or inserted
+        let mut changed = self.handle_out_of_domain();
+        for outlet in self.outlets.iter_mut() {
+            changed |= outlet.remove(&mut self.entities, t) > 0;
+        }
+        for inlet in self.inlets.iter_mut() {
+            changed |= inlet.insert(&mut self.entities, &self.walls, &self.materials, t, dt) > 0;
+        }
+        self.update_grid(changed);
+
+        // the first half kick of some schemes uses the forces left from the
+        // previous time step, which the first one lacks. The histories are
+        // incremented but not committed
+        if self.time_step_number == 0 && self.integrator.needs_initial_forces() {
+            self.compute_forces(t, 1);
+        }
+
+        initialize_step(&self.integrator, &mut moving(&mut self.entities, &self.fixed), dt);
+        for stage in 1..no_stages + 1 {
+            let history_stage = self.integrator.history_stage(stage);
+            let stage_time = t + self.integrator.stage_time(stage) * dt;
+            self.compute_forces(stage_time, history_stage);
+            advance_stage(
+                &self.integrator,
+                stage,
+                &mut moving(&mut self.entities, &self.fixed),
+                dt,
+            );
+            if let Some(ref domain) = self.domain {
+                // time elapsed to the positions after this stage
+                let elapsed = if stage < no_stages {
+                    self.integrator.stage_time(stage + 1) * dt
+                } else {
+                    dt
+                };
+                wrap_positions(
+                    &mut moving(&mut self.entities, &self.fixed),
+                    domain,
+                    t + elapsed,
+                    elapsed,
+                );
+            }
+        }
+
+        let grid = &mut self.grid.as_mut().unwrap().grid;
+        if let Some(ref domain) = self.domain {
+            grid.shear_offset = domain.shear_offset(t + dt);
+        }
+        for equation in &self.equations {
+            if let Equation::Bonds { entity, ref props } = *equation {
+                if let Some(bonded) = self.entities[entity].as_bonded_mut() {
+                    break_bonds_bonded_dem(bonded, props, t + dt, grid);
+                }
+            }
+        }
+
+        self.t += dt;
+        self.time_step_number += 1;
+        // the output and the neighbour grid see the boundaries at the end of
+        // the step
+        self.move_boundaries(t + dt);
+
+        let info = self.step_info();
+        for hook in self.post_step.iter_mut() {
+            hook(&mut self.entities, &info);
+        }
+    }
+
+    /// Evaluate the forces of all the equations with the boundaries and the
+    /// images of a sheared domain at `stage_time`.
+    fn compute_forces(&mut self, stage_time: f32, history_stage: usize) {
+        self.move_boundaries(stage_time);
+        let grid = &mut self.grid.as_mut().unwrap().grid;
+        // displace the images of a sheared domain to the time of the
+        // particle positions
+        if let Some(ref domain) = self.domain {
+            grid.shear_offset = domain.shear_offset(stage_time);
+        }
+        for entity in self.entities.iter_mut() {
+            entity.make_forces_zero();
+        }
+        for wall in self.walls.iter_mut() {
+            make_forces_zero_wall(wall);
+        }
+        for equation in &self.equations {
+            apply_equation(
+                equation,
+                &mut self.entities,
+                &mut self.walls,
+                &self.materials,
+                self.dt,
+                history_stage,
+                grid,
+            );
+        }
+    }
+
+    /// Build the neighbour grid, or rebuild it if the particles have moved
+    /// out of its skin or the particles of the entities have `changed`.
+    fn update_grid(&mut self, changed: bool) {
+        let mut world: Vec<&mut Entity> = self.entities.iter_mut().collect();
+        let rebuild = match self.grid {
+            Some(ref grid) => {
+                grid.scale != self.scale || grid.skin != self.skin || grid.domain != self.domain
+            }
+            None => true,
+        };
+        if rebuild {
+            self.grid = Some(match self.domain {
+                Some(domain) => VerletGrid::new_in_domain(&mut world, self.scale, self.skin, domain),
+                None => VerletGrid::new(&mut world, self.scale, self.skin),
+            });
+        } else if let Some(ref mut grid) = self.grid {
+            if changed {
+                grid.rebuild(&mut world);
+            } else {
+                grid.update(&mut world);
+            }
+        }
+    }
+
+    /// Find the particles outside the domain, and clamp, delete or report
+    /// them. Returns true if any particle was deleted.
+    fn handle_out_of_domain(&mut self) -> bool {
+        let domain = match self.domain {
+            Some(domain) => domain,
+            None => return false,
+        };
+        let mut deleted = false;
+        let no_outside = self.outside.len();
+        self.outside.clear();
+
+        for k in 0..self.entities.len() {
+            let remove: Vec<usize> = {
+                let ent = self.entities[k].get_parts_mut_integrate();
+                let mut remove = vec![];
+                for i in 0..ent.x.len() {
+                    if domain.contains(ent.x[i], ent.y[i]) {
+                        continue;
+                    }
+                    match self.out_of_domain {
+                        OutOfDomain::Clamp => {
+                            let (x, y) = domain.clamp(ent.x[i], ent.y[i]);
+                            // stop the motion out of the domain
+                            if x != ent.x[i] {
+                                ent.u[i] = 0.;
+                            }
+                            if y != ent.y[i] {
+                                ent.v[i] = 0.;
+                            }
+                            ent.x[i] = x;
+                            ent.y[i] = y;
+                        }
+                        // the boundary motions keep the reference positions
+                        // of all the particles of a moving entity
+                        OutOfDomain::Delete if !self.fixed[k] => remove.push(i),
+                        OutOfDomain::Delete | OutOfDomain::Report => self.outside.push((k, i)),
+                    }
+                }
+                remove
+            };
+
+            if !remove.is_empty() {
+                remove_particles(&mut self.entities, k, &remove);
+                deleted = true;
+            }
+        }
+
+        if self.outside.len() > no_outside {
+            eprintln!(
+                "warning: {} particles are outside the domain at t = {}",
+                self.outside.len(),
+                self.t
+            );
+        }
+        deleted
+    }
+
+    /// Number of times the neighbour grid has been built.
+    pub fn grid_builds(&self) -> usize {
+        self.grid.as_ref().map_or(0, |grid| grid.builds)
+    }
+
+    /// Write all the entities to the output folder, and call the output
+    /// hooks.
+    pub fn output(&mut self) {
+        if let Some(ref dir_name) = self.output_folder {
+            create_output_directory(dir_name);
+            dump_output(
+                &mut self.entities.iter_mut().collect(),
+                self.time_step_number,
+                dir_name,
+            );
+            for entity in &self.entities {
+                if let Entity::Bonded(ref bonded) = *entity {
+                    write_breakage_events(bonded, dir_name);
+                }
+            }
+        }
+        let info = self.step_info();
+        for hook in self.on_output.iter_mut() {
+            hook(&mut self.entities, &info);
+        }
+    }
+
+    /// Run the simulation up to the final time, writing the output at the
+    /// start and every `output_frequency` time steps. The number of time
+    /// steps is the final time divided by `dt`, rounded to the nearest
+    /// integer.
+    pub fn run(&mut self) -> Result<(), TimeStepError> {
+        if self.time_step_check != TimeStepCheck::Ignore {
+            // without the materials of the entities there are no contacts
+            // limiting the time step
+            let mut world = self.entities.iter_mut().collect();
+            if let Some(estimate) = estimate_time_step(&mut world, &self.materials) {
+                let stable = estimate.stable(self.safety_factor);
+                check_time_step(self.dt, stable, self.time_step_check)?;
+            }
+        }
+
+        if self.time_step_number == 0 {
+            self.output();
+        }
+        let steps = (self.tf / self.dt).round() as usize;
+        while self.time_step_number < steps {
+            self.step();
+            // `is_multiple_of` would raise the minimum supported Rust version
+            #[allow(clippy::manual_is_multiple_of)]
+            if self.time_step_number % self.output_frequency == 0 {
+                self.output();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Entities which are advanced in time.
+fn moving<'a>(entities: &'a mut [Entity], fixed: &[bool]) -> Vec<&'a mut Entity> {
+    entities
+        .iter_mut()
+        .zip(fixed)
+        .filter(|&(_, fixed)| !*fixed)
+        .map(|(entity, _)| entity)
+        .collect()
+}
+
+/// Mutable references to two distinct entities.
+fn pair_mut(entities: &mut [Entity], i: usize, j: usize) -> (&mut Entity, &mut Entity) {
+    assert!(i != j, "an entity can't be in contact with itself, use SelfContact");
+    if i < j {
+        let (left, right) = entities.split_at_mut(j);
+        (&mut left[i], &mut right[0])
+    } else {
+        let (left, right) = entities.split_at_mut(i);
+        (&mut right[0], &mut left[j])
+    }
+}
+
+// calls a contact function generic over the destination and the source,
+// for every combination of entity types
+macro_rules! dispatch_contact {
+    ($func:ident, $dst:expr, $src:expr, $($arg:expr),*) => {
+        match ($dst, $src) {
+            (&mut Entity::Discrete(ref mut d), &mut Entity::Discrete(ref mut s)) => $func(d, s, $($arg),*),
+            (&mut Entity::Discrete(ref mut d), &mut Entity::Bonded(ref mut s)) => $func(d, s, $($arg),*),
+            (&mut Entity::Bonded(ref mut d), &mut Entity::Discrete(ref mut s)) => $func(d, s, $($arg),*),
+            (&mut Entity::Bonded(ref mut d), &mut Entity::Bonded(ref mut s)) => $func(d, s, $($arg),*),
+        }
+    };
+}
+
+fn apply_equation(
+    equation: &Equation,
+    entities: &mut [Entity],
+    walls: &mut [Wall],
+    materials: &MaterialDatabase,
+    dt: f32,
+    stage: usize,
+    grid: &LinkedListGrid,
+) {
+    match *equation {
+        Equation::BodyForce { entity, gx, gy } => match entities[entity] {
+            Entity::Discrete(ref mut ent) => body_force_dem(ent, gx, gy),
+            Entity::Bonded(ref mut ent) => body_force_bonded_dem(ent, gx, gy),
+        },
+        Equation::SelfContact { entity, law } => match (&mut entities[entity], law) {
+            (&mut Entity::Discrete(ref mut ent), ContactLaw::LinearViscoelastic) => {
+                linear_viscoelastic_model_dem_self(ent, materials, dt, stage, grid)
+            }
+            (&mut Entity::Discrete(ref mut ent), ContactLaw::HertzMindlin) => {
+                hertz_mindlin_model_dem_self(ent, materials, dt, stage, grid)
+            }
+            (&mut Entity::Bonded(ref mut ent), ContactLaw::LinearViscoelastic) => {
+                linear_viscoelastic_model_bonded_dem_self(ent, materials, dt, stage, grid)
+            }
+            (&mut Entity::Bonded(ref mut ent), ContactLaw::HertzMindlin) => {
+                hertz_mindlin_model_bonded_dem_self(ent, materials, dt, stage, grid)
+            }
+        },
+        Equation::Contact { dst, src, law } => {
+            let (dst, src) = pair_mut(entities, dst, src);
+            match law {
+                ContactLaw::LinearViscoelastic => dispatch_contact!(
+                    linear_viscoelastic_model_dem_other,
+                    dst,
+                    src,
+                    materials,
+                    dt,
+                    stage,
+                    grid
+                ),
+                ContactLaw::HertzMindlin => dispatch_contact!(
+                    hertz_mindlin_model_dem_other,
+                    dst,
+                    src,
+                    materials,
+                    dt,
+                    stage,
+                    grid
+                ),
+            }
+        }
+        Equation::Bonds { entity, ref props } => {
+            if let Some(bonded) = entities[entity].as_bonded_mut() {
+                internal_force_bonded_dem(bonded, props, dt, stage, grid);
+            }
+        }
+        Equation::WallContact { entity, wall, law } => {
+            let wall = &mut walls[wall];
+            match entities[entity] {
+                Entity::Discrete(ref mut ent) => {
+                    contact_force_wall(ent, wall, law, materials, dt, stage)
+                }
+                Entity::Bonded(ref mut ent) => {
+                    contact_force_wall(ent, wall, law, materials, dt, stage)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Entity, Equation, OutOfDomain, Solver};
+    use contact_search::Domain;
+    use geometry::SizeDistribution;
+    use inlet::{FlowRate, Inlet};
+    use integrate::{SymplecticEuler, VelocityVerlet, RK2};
+    use outlet::{Outlet, OutletRegion};
+    use motion::Motion;
+    use physics::dem::equations::{set_disk_inertia_dem, ContactLaw};
+    use cm::{InnerSpace, Vector3};
+    use physics::bonded_dem::{Bond, BondProperties, DemBonded};
+    use physics::dem::DemDiscrete;
+    use physics::material::{Material, MaterialDatabase};
+    use physics::timestep::TimeStepCheck;
+    use physics::wall::{Wall, WallShape};
+    use std::cell::Cell;
+    use std::f32::consts::PI;
+    use std::rc::Rc;
+
+    fn single_material() -> MaterialDatabase {
+        let mut materials = MaterialDatabase::new();
+        let mut material = Material::new("grain".to_string(), 1000., 1e5, 0.3);
+        material.en = 0.5;
+        materials.add(material);
+        materials
+    }
+
+    fn grain(x: f32, y: f32, id: usize) -> DemDiscrete {
+        let mut grain = DemDiscrete::new(1, id, format!("grain_{}", id));
+        grain.x[0] = x;
+        grain.y[0] = y;
+        grain.h[0] = 0.5;
+        grain.rad[0] = 0.5;
+        grain.m[0] = 1.;
+        grain.m_inv[0] = 1.;
+        grain
+    }
+
+    #[test]
+    fn test_free_fall_with_hooks() {
+        let mut solver = Solver::new(RK2, single_material(), 1e-3, 0.1);
+        let ball = solver.add_entity(grain(0., 0., 0));
+        solver.add_equation(Equation::BodyForce {
+            entity: ball,
+            gx: 0.,
+            gy: -10.,
+        });
+        solver.set_output_frequency(10);
+
+        let (pre, post, outputs) = (Rc::new(Cell::new(0)), Rc::new(Cell::new(0)), Rc::new(Cell::new(0)));
+        let (pre_c, post_c, outputs_c) = (pre.clone(), post.clone(), outputs.clone());
+        solver.add_pre_step(move |_, _| pre_c.set(pre_c.get() + 1));
+        solver.add_post_step(move |_, _| post_c.set(post_c.get() + 1));
+        solver.add_on_output(move |_, _| outputs_c.set(outputs_c.get() + 1));
+        solver.run().unwrap();
+
+        let steps = solver.time_step_number;
+        assert!((solver.t - 0.1).abs() < 2e-3);
+        assert_eq!((steps, steps), (pre.get(), post.get()));
+        // initial output and every 10 steps
+        assert_eq!(1 + steps / 10, outputs.get());
+
+        // y = - g t^2 / 2
+        let y = solver.entities[ball].as_discrete().unwrap().y[0];
+        let t = solver.t;
+        assert!((y + 5. * t * t).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_run_without_materials() {
+        // a falling grain has no contacts, and needs no materials
+        let mut solver = Solver::new(SymplecticEuler, MaterialDatabase::new(), 1e-3, 0.01);
+        let ball = solver.add_entity(grain(0., 0., 0));
+        solver.add_equation(Equation::BodyForce {
+            entity: ball,
+            gx: 0.,
+            gy: -10.,
+        });
+        solver.run().unwrap();
+        solver.time_step_check = TimeStepCheck::Error;
+        solver.tf = 0.02;
+        solver.run().unwrap();
+        assert_eq!(20, solver.time_step_number);
+    }
+
+    #[test]
+    fn test_first_velocity_verlet_step() {
+        let dt = 1e-3;
+        let mut solver = Solver::new(VelocityVerlet, single_material(), dt, 1.);
+        let ball = solver.add_entity(grain(0., 0., 0));
+        solver.add_equation(Equation::BodyForce {
+            entity: ball,
+            gx: 0.,
+            gy: -10.,
+        });
+        solver.step();
+
+        // both half kicks of the first step use the gravity
+        let ball = solver.entities[ball].as_discrete().unwrap();
+        assert!((ball.v[0] + 10. * dt).abs() < 1e-7);
+        assert!((ball.y[0] + 0.5 * 10. * dt * dt).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_contact_between_moving_and_fixed_entity() {
+        // a grain falls on a fixed grain and bounces off it
+        let mut solver = Solver::new(SymplecticEuler, single_material(), 1e-4, 1.);
+        let ball = solver.add_entity(grain(0., 1.2, 0));
+        let floor = solver.add_fixed_entity(grain(0., 0., 1));
+        solver.add_equation(Equation::BodyForce {
+            entity: ball,
+            gx: 0.,
+            gy: -10.,
+        });
+        solver.add_equation(Equation::Contact {
+            dst: ball,
+            src: floor,
+            law: ContactLaw::LinearViscoelastic,
+        });
+        solver.add_equation(Equation::Contact {
+            dst: floor,
+            src: ball,
+            law: ContactLaw::LinearViscoelastic,
+        });
+        solver.run().unwrap();
+
+        let floor = solver.entities[floor].as_discrete().unwrap();
+        assert_eq!((0., 0.), (floor.x[0], floor.y[0]));
+        // the ball comes to rest on the floor, which carries its weight
+        let ball = solver.entities[ball].as_discrete().unwrap();
+        assert!(ball.y[0] > 0.9 && ball.y[0] < 1.);
+        assert!(ball.v[0].abs() < 1e-2);
+        assert!((floor.fy[0] + 10.).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_ball_resting_on_a_wall() {
+        let mut solver = Solver::new(SymplecticEuler, single_material(), 1e-4, 1.);
+        let ball = solver.add_entity(grain(0., 0.7, 0));
+        let floor = solver.add_wall(Wall::new(
+            WallShape::Line {
+                point: Vector3::new(0., 0., 0.),
+                normal: Vector3::new(0., 1., 0.),
+            },
+            1,
+            "floor".to_string(),
+        ));
+        solver.add_equation(Equation::BodyForce {
+            entity: ball,
+            gx: 0.,
+            gy: -10.,
+        });
+        solver.add_equation(Equation::WallContact {
+            entity: ball,
+            wall: floor,
+            law: ContactLaw::LinearViscoelastic,
+        });
+        solver.run().unwrap();
+
+        // the ball comes to rest on the wall, which carries its weight
+        let ball = solver.entities[ball].as_discrete().unwrap();
+        assert!(ball.y[0] > 0.4 && ball.y[0] < 0.5);
+        assert!(ball.v[0].abs() < 1e-2);
+        assert!((solver.walls[floor].fy + 10.).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_ball_dragged_by_a_moving_wall() {
+        // a conveyor belt, moving along itself, drags a ball resting on it by
+        // friction until it rolls without slipping
+        let mut materials = MaterialDatabase::new();
+        let mut material = Material::new("grain".to_string(), 1000., 1e5, 0.3);
+        material.en = 0.5;
+        material.mu = 0.5;
+        materials.add(material);
+        let mut solver = Solver::new(SymplecticEuler, materials, 1e-4, 1.);
+        let mut ball = grain(0., 0.49, 0);
+        set_disk_inertia_dem(&mut ball);
+        let ball = solver.add_entity(ball);
+        let belt = Wall::new(
+            WallShape::Line {
+                point: Vector3::new(0., 0., 0.),
+                normal: Vector3::new(0., 1., 0.),
+            },
+            1,
+            "belt".to_string(),
+        );
+        let velocity = Vector3::new(1., 0., 0.);
+        let belt = solver.add_wall(belt.moving(Motion::Translation { velocity }));
+        solver.add_equation(Equation::BodyForce {
+            entity: ball,
+            gx: 0.,
+            gy: -10.,
+        });
+        solver.add_equation(Equation::WallContact {
+            entity: ball,
+            wall: belt,
+            law: ContactLaw::LinearViscoelastic,
+        });
+        solver.run().unwrap();
+
+        // a disk rolling without slipping keeps a third of the belt velocity
+        let ball = solver.entities[ball].as_discrete().unwrap();
+        let slip = ball.u[0] + ball.omega_z[0] * 0.5 - 1.;
+        assert!((ball.u[0] - 1. / 3.).abs() < 2e-2, "{}", ball.u[0]);
+        assert!(slip.abs() < 2e-2);
+        // the belt has moved with its velocity
+        match solver.walls[belt].shape {
+            WallShape::Line { point, .. } => assert!((point.x - solver.t).abs() < 1e-5),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_vibrating_boundary_entity() {
+        let mut solver = Solver::new(RK2, single_material(), 1e-3, 0.125);
+        let amplitude = Vector3::new(0., 0.1, 0.);
+        let bed = solver.add_moving_entity(
+            grain(1., 0., 0),
+            Motion::Sinusoidal {
+                amplitude,
+                frequency: 2.,
+            },
+        );
+        solver.run().unwrap();
+
+        // a quarter of a period, at the top of the vibration with no velocity
+        let bed = solver.entities[bed].as_discrete().unwrap();
+        let t = solver.t;
+        assert!((bed.y[0] - 0.1 * (4. * PI * t).sin()).abs() < 1e-6);
+        assert!((bed.v[0] - 0.1 * 4. * PI * (4. * PI * t).cos()).abs() < 1e-4);
+        assert_eq!(1., bed.x[0]);
+    }
+
+    #[test]
+    fn test_skin_reduces_grid_builds() {
+        let run = |skin: f32| {
+            let mut solver = Solver::new(RK2, single_material(), 1e-3, 0.1);
+            let ball = solver.add_entity(grain(0., 0., 0));
+            solver.add_entity(grain(5., 0., 1));
+            solver.add_equation(Equation::BodyForce {
+                entity: ball,
+                gx: 0.,
+                gy: -10.,
+            });
+            solver.skin = skin;
+            solver.run().unwrap();
+            solver.grid_builds()
+        };
+        // without a skin the grid is rebuilt at every step. The ball falls
+        // by 5 cm in total, it is rebuilt after falling by 2 and 4 cm with a
+        // skin of 4 cm, and never with a skin of 12 cm
+        assert_eq!(100, run(0.));
+        assert_eq!(3, run(0.04));
+        assert_eq!(1, run(0.12));
+    }
+
+    /// A grain thrown to the right out of the domain [-1, 1] x [-1, 1],
+    /// next to a grain at rest.
+    fn thrown_grain(out_of_domain: OutOfDomain) -> Solver<RK2> {
+        let mut solver = Solver::new(RK2, single_material(), 1e-2, 0.2);
+        let mut grains = DemDiscrete::new(2, 0, "grains".to_string());
+        for i in 0..2 {
+            grains.x[i] = 0.5 * i as f32;
+            grains.h[i] = 0.1;
+            grains.rad[i] = 0.1;
+            grains.m[i] = 1.;
+            grains.m_inv[i] = 1.;
+        }
+        grains.u[0] = 10.;
+        grains.v[0] = 1.;
+        solver.add_entity(grains);
+        solver.domain = Some(Domain::new(-1., 1., -1., 1.));
+        solver.out_of_domain = out_of_domain;
+        solver
+    }
+
+    #[test]
+    fn test_particles_leaving_the_domain() {
+        let mut solver = thrown_grain(OutOfDomain::Clamp);
+        solver.run().unwrap();
+        let grains = solver.entities[0].as_discrete().unwrap();
+        assert_eq!((1., 0.), (grains.x[0], grains.u[0]));
+        assert!(grains.v[0] > 0.);
+
+        let mut solver = thrown_grain(OutOfDomain::Delete);
+        solver.run().unwrap();
+        let grains = solver.entities[0].as_discrete().unwrap();
+        assert_eq!(1, grains.len);
+        assert_eq!(0.5, grains.x[0]);
+
+        let mut solver = thrown_grain(OutOfDomain::Report);
+        solver.run().unwrap();
+        assert_eq!(vec![(0, 0)], solver.outside);
+        assert!(solver.entities[0].as_discrete().unwrap().x[0] > 1.);
+    }
+
+    #[test]
+    fn test_moving_entity_is_not_deleted() {
+        // the second grain of the vibrating bed starts outside the domain
+        let mut solver = Solver::new(RK2, single_material(), 1e-3, 0.125);
+        let mut bed = DemDiscrete::new(2, 0, "bed".to_string());
+        for i in 0..2 {
+            bed.x[i] = 1.5 * i as f32;
+            bed.rad[i] = 0.1;
+            bed.h[i] = 0.1;
+        }
+        let motion = Motion::Sinusoidal {
+            amplitude: Vector3::new(0., 0.1, 0.),
+            frequency: 2.,
+        };
+        let bed = solver.add_moving_entity(bed, motion);
+        solver.domain = Some(Domain::new(-1., 1., -1., 1.));
+        solver.out_of_domain = OutOfDomain::Delete;
+        solver.run().unwrap();
+
+        assert_eq!(vec![(bed, 1)], solver.outside);
+        let bed = solver.entities[bed].as_discrete().unwrap();
+        assert_eq!(2, bed.len);
+        assert_eq!(1.5, bed.x[1]);
+        assert!((bed.y[1] - 0.1 * (4. * PI * solver.t).sin()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_grid_is_rebuilt_when_particles_change() {
+        // the grain reaches the outlet after the first step, and the inlet
+        // replaces it by a grain closer than half the skin in the second one
+        let mut solver = Solver::new(SymplecticEuler, single_material(), 1e-3, 1.);
+        let mut grain = grain(0.295, 0., 0);
+        grain.u[0] = 10.;
+        let grains = solver.add_entity(grain);
+        solver.skin = 1.;
+        let region = Domain::new(0.3, 1., -1., 1.);
+        solver.add_outlet(Outlet::new(grains, OutletRegion::Rectangle(region)));
+        let distribution = SizeDistribution::Uniform { min: 0.1, max: 0.1 };
+        let region = Domain::new(0., 0.25, -0.1, 0.1);
+        let mut inlet = Inlet::new(grains, region, distribution, FlowRate::Particles(1500.), 5);
+        inlet.start = 0.5e-3;
+        inlet.stop = 1.5e-3;
+        solver.add_inlet(inlet);
+
+        solver.step();
+        solver.step();
+        assert_eq!(1, solver.outlets[0].discharged);
+        assert_eq!(1, solver.inlets[0].inserted);
+        assert_eq!(2, solver.grid_builds());
+    }
+
+    #[test]
+    fn test_histories_follow_deleted_and_inserted_particles() {
+        // with friction the contacts keep tangential histories
+        let mut materials = single_material();
+        materials.materials[0].mu = 0.5;
+        let mut solver = Solver::new(SymplecticEuler, materials, 1e-3, 1.);
+        solver.domain = Some(Domain::new(-1., 1., -1., 1.));
+        solver.out_of_domain = OutOfDomain::Delete;
+        // cells of 0.4, so the old and new positions of the grains are in
+        // cells which are not neighbours
+        solver.skin = 0.3;
+
+        // the first grain leaves the domain after the first step, the second
+        // one rests on the floor in contact with the ball
+        let mut grains = DemDiscrete::new(2, 0, "grains".to_string());
+        for i in 0..2 {
+            grains.h[i] = 0.1;
+            grains.rad[i] = 0.1;
+            grains.m[i] = 1.;
+            grains.m_inv[i] = 1.;
+        }
+        grains.x[0] = -0.995;
+        grains.y[0] = 0.5;
+        grains.u[0] = -10.;
+        grains.y[1] = 0.099;
+        let mut ball = grain(0.199, 0.099, 1);
+        ball.h[0] = 0.1;
+        ball.rad[0] = 0.1;
+        let grains = solver.add_entity(grains);
+        let ball = solver.add_entity(ball);
+        let floor = solver.add_wall(Wall::new(
+            WallShape::Line {
+                point: Vector3::new(0., 0., 0.),
+                normal: Vector3::new(0., 1., 0.),
+            },
+            2,
+            "floor".to_string(),
+        ));
+
+        // an inlet replaces the deleted grain in the second step
+        let distribution = SizeDistribution::Uniform { min: 0.1, max: 0.1 };
+        let region = Domain::new(0.5, 0.9, 0.5, 0.9);
+        let mut inlet = Inlet::new(grains, region, distribution, FlowRate::Particles(1500.), 5);
+        inlet.start = 0.5e-3;
+        inlet.stop = 1.5e-3;
+        solver.add_inlet(inlet);
+
+        for &(dst, src) in &[(grains, ball), (ball, grains)] {
+            solver.add_equation(Equation::Contact {
+                dst,
+                src,
+                law: ContactLaw::LinearViscoelastic,
+            });
+        }
+        for &entity in &[grains, ball] {
+            solver.add_equation(Equation::WallContact {
+                entity,
+                wall: floor,
+                law: ContactLaw::LinearViscoelastic,
+            });
+        }
+
+        solver.step();
+        assert!(solver.entities[ball].as_discrete().unwrap().tang_history[0][&0].contains_key(&1));
+        solver.step();
+
+        // the grain resting on the floor is now the first one, and the ball
+        // is still pushed away by it
+        assert_eq!(2, solver.grid_builds());
+        let grains = solver.entities[grains].as_discrete().unwrap();
+        assert_eq!(2, grains.len);
+        assert!(grains.x[0].abs() < 1e-3);
+        assert!(grains.tang_history[0][&2].contains_key(&0));
+        assert!(grains.tang_history[1].is_empty() || grains.tang_history[1][&2].is_empty());
+        let ball = solver.entities[ball].as_discrete().unwrap();
+        assert!(ball.tang_history[0][&0].contains_key(&0));
+        assert!(!ball.tang_history[0][&0].contains_key(&1));
+        assert!(ball.fx[0] > 0.);
+    }
+
+    #[test]
+    fn test_particles_crossing_periodic_sides() {
+        // the thrown grain travels once across the periodic domain
+        let mut solver = thrown_grain(OutOfDomain::Report);
+        solver.domain = Some(Domain::new(-1., 1., -1., 1.).periodic(true, false));
+        solver.run().unwrap();
+        let grains = solver.entities[0].as_discrete().unwrap();
+        assert!(grains.x[0].abs() < 1e-3);
+        assert!(solver.outside.is_empty());
+
+        // two grains overlapping through the periodic sides repel each other
+        let mut solver = Solver::new(RK2, single_material(), 1e-4, 1e-2);
+        let mut grains = DemDiscrete::new(2, 0, "grains".to_string());
+        for i in 0..2 {
+            grains.x[i] = if i == 0 { -0.95 } else { 0.95 };
+            grains.h[i] = 0.1;
+            grains.rad[i] = 0.1;
+            grains.m[i] = 1.;
+            grains.m_inv[i] = 1.;
+        }
+        let grains = solver.add_entity(grains);
+        solver.add_equation(Equation::SelfContact {
+            entity: grains,
+            law: ContactLaw::LinearViscoelastic,
+        });
+        solver.domain = Some(Domain::new(-1., 1., -1., 1.).periodic(true, false));
+        solver.run().unwrap();
+        let grains = solver.entities[grains].as_discrete().unwrap();
+        // pushed away from the image of the other grain beyond the side
+        assert!(grains.u[0] > 0. && grains.u[1] < 0.);
+        assert!((grains.u[0] + grains.u[1]).abs() < 1e-4);
+    }
+
+    /// Two sliding grains in contact, both moving down at 0.5.
+    fn sliding_pair(y: [f32; 2], u: [f32; 2], domain: Option<Domain>) -> Solver<RK2> {
+        let mut materials = MaterialDatabase::new();
+        let mut material = Material::new("grain".to_string(), 1000., 1e5, 0.3);
+        material.kn = 1.;
+        material.mu = 0.5;
+        materials.add(material);
+        let mut solver = Solver::new(RK2, materials, 1e-3, 0.4);
+        let mut grains = DemDiscrete::new(2, 0, "grains".to_string());
+        for i in 0..2 {
+            grains.x[i] = 1.;
+            grains.y[i] = y[i];
+            grains.u[i] = u[i];
+            grains.v[i] = -0.5;
+            grains.h[i] = 0.1;
+            grains.rad[i] = 0.1;
+            grains.m[i] = 1.;
+            grains.m_inv[i] = 1.;
+            grains.inertia[i] = 1.;
+            grains.i_inv[i] = 1.;
+        }
+        let grains = solver.add_entity(grains);
+        solver.add_equation(Equation::SelfContact {
+            entity: grains,
+            law: ContactLaw::LinearViscoelastic,
+        });
+        solver.domain = domain;
+        solver
+    }
+
+    #[test]
+    fn test_contact_across_sheared_sides() {
+        // the lower grain is in contact with the image of the upper one
+        // through the sheared top side, and crosses the bottom side while
+        // in contact. It matches the same pair of grains in the interior,
+        // with the upper grain moving at the shear velocity of the image
+        let domain = Domain::new(0., 2., 0., 2.).sheared(0.5);
+        let mut sheared = sliding_pair([1.95, 0.1], [0.1, -1.], Some(domain));
+        sheared.run().unwrap();
+        let mut interior = sliding_pair([0.95, 1.1], [0.1, 0.], None);
+        interior.run().unwrap();
+
+        let sheared = sheared.entities[0].as_discrete().unwrap();
+        let interior = interior.entities[0].as_discrete().unwrap();
+        // the upper grain has crossed the bottom side, taking the velocity
+        // of its image
+        assert!(sheared.y[1] > 1.5);
+        assert!((sheared.u[1] - interior.u[1]).abs() < 1e-4);
+        assert!((sheared.u[0] - interior.u[0]).abs() < 1e-4);
+        assert!((sheared.omega_z[0] - interior.omega_z[0]).abs() < 1e-4);
+
+        // the tangential history is continuous across the sides
+        let history = interior.tang_history[0][&0][&1];
+        assert!(history.magnitude() > 0.);
+        assert!((sheared.tang_history[0][&0][&1] - history).magnitude() < 1e-5);
+    }
+
+    /// Two bonded particles at `x`, moving right and stretching the bond.
+    fn bonded_pair(x: [f32; 2], domain: Option<Domain>) -> Solver<RK2> {
+        let mut solver = Solver::new(RK2, single_material(), 1e-4, 0.05);
+        let mut pair = DemBonded::new(2, 0, "pair".to_string());
+        for (i, &x) in x.iter().enumerate() {
+            pair.x[i] = x;
+            pair.u[i] = 2. + 0.2 * i as f32;
+            pair.h[i] = 0.12;
+            pair.rad[i] = 0.1;
+            pair.m[i] = 1.;
+            pair.m_inv[i] = 1.;
+            pair.inertia[i] = 1.;
+            pair.i_inv[i] = 1.;
+            pair.bonds[i].insert(1 - i, Bond::new());
+            pair.bonds0[i].insert(1 - i, Bond::new());
+        }
+        let pair = solver.add_entity(pair);
+        solver.add_equation(Equation::Bonds {
+            entity: pair,
+            props: BondProperties::new(1e3, 1e2),
+        });
+        solver.domain = domain;
+        solver
+    }
+
+    #[test]
+    fn test_bond_across_periodic_sides() {
+        // the pair is bonded through the right side, and the left particle
+        // crosses it. It matches the same pair in the interior
+        let domain = Domain::new(-1., 1., -1., 1.).periodic(true, false);
+        let mut periodic = bonded_pair([0.9, -0.9], Some(domain));
+        periodic.run().unwrap();
+        let mut interior = bonded_pair([-0.1, 0.1], None);
+        interior.run().unwrap();
+
+        let periodic = periodic.entities[0].as_bonded().unwrap();
+        let interior = interior.entities[0].as_bonded().unwrap();
+        assert!(periodic.x[0] < 0.);
+        assert!((periodic.u[0] - interior.u[0]).abs() < 1e-4);
+        assert!((periodic.u[1] - interior.u[1]).abs() < 1e-4);
+
+        // the stretched bond pulls the particles together
+        let force = interior.bonds[0][&1].normal_force;
+        assert!(force > 0.);
+        assert!((periodic.bonds[0][&1].normal_force - force).abs() / force < 1e-3);
+    }
+
+    #[test]
+    fn test_entity_conversion() {
+        let entity: Entity = grain(0., 0., 3).into();
+        assert_eq!("grain_3", entity.name());
+        assert!(entity.as_bonded().is_none());
+    }
+}